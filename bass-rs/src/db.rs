@@ -1,9 +1,43 @@
-#![allow(static_mut_refs)]
-use std::sync::{OnceLock, Mutex};
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::cell::RefCell;
 use std::convert::Infallible;
 use std::path::Path;
 
+use parking_lot::ReentrantMutex;
 use rusqlite::{Connection, named_params, OptionalExtension, Row, params_from_iter};
+use rusqlite::types::Value;
+
+use crate::theory::{Key, KeySignature, Mode, Pitch, Range};
+
+mod migrate;
+pub use migrate::SCHEMA_VERSION;
+mod copies;
+pub use copies::MusicCopy;
+mod loans;
+pub use loans::{Borrower, Loan};
+mod performances;
+pub use performances::Performance;
+mod programs;
+pub use programs::{Program, ProgramItem, Timing, format_runtime};
+mod attachments;
+pub use attachments::{Attachment, AttachmentKind};
+mod history;
+pub use history::{Change, ChangeAction, ChangeTarget};
+mod duplicates;
+pub use duplicates::{DuplicateCandidate, DUPLICATE_THRESHOLD};
+mod people;
+pub use people::Person;
+mod contributors;
+pub use contributors::{Contributor, ContributorRole};
+mod part_ranges;
+pub use part_ranges::PartRange;
+mod ismn;
+pub use ismn::Ismn;
+mod custom_fields;
+pub use custom_fields::{CustomField, CustomFieldKind, CustomValue};
+mod spreadsheet;
+pub use spreadsheet::{CsvExport, CsvImport, SheetColumn, ImportReport, ImportedRow, RowError};
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -11,80 +45,246 @@ pub enum Error {
     AlreadyInit,
     #[error("database connection has not been established")]
     NotConnected,
+    #[error("database was written by a newer version of Bass (schema {found}, this version supports up to {supported})")]
+    SchemaTooNew { found: i32, supported: i32 },
+    #[error("migration to schema version {0} left dangling references")]
+    MigrationFailed(i32),
+    #[error("keyword \"{0}\" is not in the database")]
+    MissingKeyword(String),
+    #[error("only {available} of that copy are available to lend")]
+    NotEnoughCopies { available: u32 },
+    #[error("a loan has to be for at least one copy")]
+    EmptyLoan,
+    #[error("loan {0} has already been returned")]
+    AlreadyReturned(i32),
+    #[error("change {0} can't be undone")]
+    CannotUndo(i32),
+    #[error("\"{0}\" can't be used as a field name")]
+    InvalidFieldName(String),
+    #[error("\"{value}\" is not a valid value for {field}")]
+    InvalidCustomValue { field: String, value: String },
 
     #[error(transparent)]
-    DBError(#[from] rusqlite::Error)
+    DBError(#[from] rusqlite::Error),
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+    #[error(transparent)]
+    CSVError(#[from] csv::Error),
 }
 pub type Result<T> = std::result::Result<T, Error>;
 
-static mut DB: OnceLock<Mutex<Connection>> = OnceLock::new();
+/// Whether `s` is a day that exists, written as an ISO 8601 calendar date like `2024-09-30`.
+pub fn is_date(s: &str) -> bool {
+    let parts: Vec<&str> = s.split('-').collect();
+    if parts.len() != 3 || parts[0].len() != 4 || parts[1].len() != 2 || parts[2].len() != 2 {
+        return false;
+    }
+    if !parts.iter().all(|p| p.chars().all(|c| c.is_ascii_digit())) {
+        return false;
+    }
+    let year: u32 = parts[0].parse().unwrap();
+    let month: u32 = parts[1].parse().unwrap();
+    let day: u32 = parts[2].parse().unwrap();
+    let leap = year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+    let days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return false,
+    };
+    (1..=days).contains(&day)
+}
 
-#[allow(dead_code)]
-pub fn init<P: AsRef<Path>>(path: Option<P>) -> Result<()> {
-    let conn = if let Some(path) = path {
-        Connection::open(path)
-    } else {
-        Connection::open_in_memory()
-    }?;
-    let init_script = include_str!("bass-init.sql");
-    conn.execute_batch(init_script)?;
-    unsafe {
-        DB.set(Mutex::new(conn)).map_err(|_| Error::AlreadyInit)
+// Something `Database::transaction_restoring` can put back after a failed transaction
+trait Restore {
+    type Saved;
+    fn save(&self) -> Self::Saved;
+    fn restore(&mut self, saved: Self::Saved);
+}
+
+impl<T: Clone> Restore for T {
+    type Saved = T;
+    fn save(&self) -> T {
+        self.clone()
+    }
+    fn restore(&mut self, saved: T) {
+        *self = saved;
     }
 }
 
-#[allow(dead_code)]
-pub unsafe fn reinit<P: AsRef<Path>>(path: Option<P>) -> Result<()> {
-    let conn = if let Some(path) = path {
-        Connection::open(path)
-    } else {
-        Connection::open_in_memory()
-    }?;
-    let init_script = include_str!("bass-init.sql");
-    conn.execute_batch(init_script)?;
-    let _ = DB.take();
-    let _ = DB.set(Mutex::new(conn));
-    Ok(())
+impl<T: Clone> Restore for [T] {
+    type Saved = Vec<T>;
+    fn save(&self) -> Vec<T> {
+        self.to_vec()
+    }
+    fn restore(&mut self, saved: Vec<T>) {
+        self.clone_from_slice(&saved);
+    }
 }
 
-fn execute<P: rusqlite::Params>(stmt: &str, params: P) -> Result<usize> {
-    unsafe {DB.get().ok_or(Error::NotConnected)?.lock().unwrap()
-        .execute(stmt, params)
-        .map_err(|e| e.into())}
+/// An open music collection. Each `Database` owns its own connection, so several can be open at once.
+pub struct Database {
+    // Reentrant so that a thread holding a transaction open can keep issuing statements while
+    // other threads wait for it to finish.
+    conn: ReentrantMutex<RefCell<Connection>>,
+    prune_keywords: AtomicBool,
 }
 
-fn query_row<T, P, F>(query: &str, params: P, f: F) -> Result<Option<T>> 
-where P: rusqlite::Params,
-      F: FnOnce(&Row<'_>) -> std::result::Result<T, rusqlite::Error> {
-    unsafe {
-    DB.get().ok_or(Error::NotConnected)?.lock().unwrap()
-        .query_row(query, params, f) 
-        .optional()
-        .map_err(|e| e.into())
+impl Database {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Database> {
+        Database::from_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Database> {
+        Database::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(mut conn: Connection) -> Result<Database> {
+        let from = migrate::schema_version(&conn)?;
+        migrate::migrate(&mut conn)?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        let db = Database {
+            conn: ReentrantMutex::new(RefCell::new(conn)),
+            prune_keywords: AtomicBool::new(false),
+        };
+        migrate::after_migrating(&db, from)?;
+        Ok(db)
+    }
+
+    /// When on, keywords no longer used by any piece are deleted whenever a piece loses a keyword
+    /// or is deleted. Off by default.
+    pub fn set_prune_unused_keywords(&self, prune: bool) {
+        self.prune_keywords.store(prune, Ordering::Relaxed);
+    }
+
+    fn auto_prune(&self) -> Result<()> {
+        if self.prune_keywords.load(Ordering::Relaxed) {
+            Keyword::prune_unused(self)?;
+        }
+        Ok(())
+    }
+
+    pub fn schema_version(&self) -> Result<i32> {
+        migrate::schema_version(&self.conn.lock().borrow())
+    }
+
+    /// Runs `f` atomically. If it returns an error, everything it wrote is rolled back.
+    ///
+    /// Transactions nest, so `f` is free to call operations that open transactions of their own.
+    pub fn transaction<T, F>(&self, f: F) -> Result<T>
+    where F: FnOnce(&Database) -> Result<T> {
+        let _guard = self.conn.lock();
+        self.execute_batch("SAVEPOINT bass_transaction;")?;
+        match f(self) {
+            Ok(out) => {
+                self.execute_batch("RELEASE bass_transaction;")?;
+                Ok(out)
+            }
+            Err(e) => {
+                // Report what went wrong in `f`, not any trouble unwinding it.
+                let _ = self.execute_batch("ROLLBACK TO bass_transaction; RELEASE bass_transaction;");
+                Err(e)
+            }
+        }
+    }
+
+    /// Runs `f` on `value` like [`Database::transaction`], putting `value` back the way it was if
+    /// it fails. Ids handed out inside a rolled back transaction don't exist, so they mustn't be
+    /// kept.
+    fn transaction_restoring<V, T, F>(&self, value: &mut V, f: F) -> Result<T>
+    where V: Restore + ?Sized, F: FnOnce(&Database, &mut V) -> Result<T> {
+        let saved = value.save();
+        let result = self.transaction(|db| f(db, value));
+        if result.is_err() {
+            value.restore(saved);
+        }
+        result
+    }
+
+    fn execute_batch(&self, stmts: &str) -> Result<()> {
+        self.conn.lock().borrow()
+            .execute_batch(stmts)
+            .map_err(|e| e.into())
+    }
+
+    fn execute<P: rusqlite::Params>(&self, stmt: &str, params: P) -> Result<usize> {
+        self.conn.lock().borrow()
+            .execute(stmt, params)
+            .map_err(|e| e.into())
+    }
+
+    // Runs a single-row INSERT and returns the new row's id.
+    fn insert<P: rusqlite::Params>(&self, stmt: &str, params: P) -> Result<i32> {
+        let db = self.conn.lock();
+        let db = db.borrow();
+        let mut statement = db.prepare_cached(stmt)?;
+        Ok(statement.insert(params)? as i32)
+    }
+
+    fn query_row<T, P, F>(&self, query: &str, params: P, f: F) -> Result<Option<T>>
+    where P: rusqlite::Params,
+          F: FnOnce(&Row<'_>) -> std::result::Result<T, rusqlite::Error> {
+        self.conn.lock().borrow()
+            .query_row(query, params, f)
+            .optional()
+            .map_err(|e| e.into())
+    }
+
+    fn query<T, P, F>(&self, query: &str, params: P, f: F) -> Result<Vec<T>>
+    where P: rusqlite::Params,
+          F: Fn(&Row<'_>) -> std::result::Result<T, rusqlite::Error> {
+        let db = self.conn.lock();
+        let db = db.borrow();
+        let mut statement = db.prepare_cached(query)?;
+        let mut rows = statement.query(params)?;
+
+        let mut out = Vec::new();
+        while let Some(row) = rows.next()? {
+            out.push(f(row)?);
+        }
+
+        Ok(out)
+    }
+
+    fn exists<P: rusqlite::Params>(&self, query: &str, params: P) -> Result<bool> {
+        let db = self.conn.lock();
+        let db = db.borrow();
+        let mut statement = db.prepare_cached(query)?;
+        statement.exists(params).map_err(|e| e.into())
     }
 }
 
-fn query<T, P, F>(query: &str, params: P, f: F) -> Result<Vec<T>> 
-where P: rusqlite::Params,
-      F: Fn(&Row<'_>) -> std::result::Result<T, rusqlite::Error> {
-    let db = unsafe {DB.get().ok_or(Error::NotConnected)?.lock().unwrap()};
-    let mut statement = db.prepare_cached(query)?;
-    let mut rows = statement
-        .query(params)
-        .map_err(|e| Error::from(e))?;
+// Compatibility layer for code written against the old process-wide connection.
+static DEFAULT: RwLock<Option<Arc<Database>>> = RwLock::new(None);
+
+fn open_optional<P: AsRef<Path>>(path: Option<P>) -> Result<Database> {
+    match path {
+        Some(path) => Database::open(path),
+        None => Database::open_in_memory(),
+    }
+}
 
-    let mut out = Vec::new();
-    while let Some(row) = rows.next()? {
-        out.push(f(row)?);
+#[allow(dead_code)]
+pub fn init<P: AsRef<Path>>(path: Option<P>) -> Result<()> {
+    let mut default = DEFAULT.write().unwrap();
+    if default.is_some() {
+        return Err(Error::AlreadyInit);
     }
+    *default = Some(Arc::new(open_optional(path)?));
+    Ok(())
+}
 
-    Ok(out)
+#[allow(dead_code)]
+pub fn reinit<P: AsRef<Path>>(path: Option<P>) -> Result<()> {
+    let db = open_optional(path)?;
+    *DEFAULT.write().unwrap() = Some(Arc::new(db));
+    Ok(())
 }
 
-fn exists<P: rusqlite::Params>(query: &str, params: P) -> Result<bool> {
-    let db = unsafe {DB.get().ok_or(Error::NotConnected)?.lock().unwrap()};
-    let mut statement = db.prepare_cached(query)?;
-    statement.exists(params).map_err(|e| e.into())
+/// The database set up by [`init`] or [`reinit`].
+pub fn default_database() -> Result<Arc<Database>> {
+    DEFAULT.read().unwrap().clone().ok_or(Error::NotConnected)
 }
 
 #[allow(dead_code)]
@@ -105,27 +305,241 @@ enum Comparison {
     FloatNotEqual(f64),
     StrEqual(String),
     StrNotEqual(String),
+    StrLess(String),
+    StrLessEqual(String),
+    StrGreater(String),
+    StrGreaterEqual(String),
     Contains(String),
     NotContains(String),
 }
 
 impl Comparison {
-    fn param(&self) -> Option<String> {
+    fn param(&self) -> Option<Value> {
         use Comparison::*;
         match self {
             Has | NotHas => None,
-            Less(n) | LessEqual(n) | Greater(n) | GreaterEqual(n) | Equal(n) | NotEqual(n) => Some(n.to_string()),
+            Less(n) | LessEqual(n) | Greater(n) | GreaterEqual(n) | Equal(n) | NotEqual(n) => Some(Value::Integer((*n).into())),
             FloatLess(f) | FloatLessEqual(f) | 
                 FloatGreater(f) | FloatGreaterEqual(f) | 
-                FloatEqual(f) | FloatNotEqual(f) => Some(f.to_string()),
-            StrEqual(ref s) | StrNotEqual(ref s) | Contains(ref s) | NotContains(ref s) => Some(s.clone())
+                FloatEqual(f) | FloatNotEqual(f) => Some(Value::Real(*f)),
+            StrEqual(ref s) | StrNotEqual(ref s) |
+                StrLess(ref s) | StrLessEqual(ref s) |
+                StrGreater(ref s) | StrGreaterEqual(ref s) |
+                Contains(ref s) | NotContains(ref s) => Some(Value::Text(s.clone()))
+        }
+    }
+
+    fn sql(&self, column: &str, param: usize) -> String {
+        use Comparison::*;
+        match self {
+            Has => format!("{} IS NOT NULL", column),
+            NotHas => format!("{} IS NULL", column),
+            Less(_) | FloatLess(_) | StrLess(_) => format!("{} < ?{}", column, param),
+            LessEqual(_) | FloatLessEqual(_) | StrLessEqual(_) => format!("{} <= ?{}", column, param),
+            Greater(_) | FloatGreater(_) | StrGreater(_) => format!("{} > ?{}", column, param),
+            GreaterEqual(_) | FloatGreaterEqual(_) | StrGreaterEqual(_) => format!("{} >= ?{}", column, param),
+            Equal(_) | FloatEqual(_) | StrEqual(_) => format!("{} == ?{}", column, param),
+            NotEqual(_) | FloatNotEqual(_) | StrNotEqual(_) => format!("{} != ?{}", column, param),
+            Contains(_) => format!("instr({}, ?{})", column, param),
+            NotContains(_) => format!("NOT instr({}, ?{})", column, param),
         }
     }
 }
 
-pub struct MusicQuery {
+enum Condition {
     // This can very likely be a &'static str
-    conditions: Vec<(String, Comparison)>,
+    Compare(String, Comparison),
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+    Not(Vec<Condition>),
+    HasKeyword(Option<String>, String),
+    InCategory(String),
+    // Matches if any row of the table linked to the piece by its `mid` column satisfies the
+    // comparison. `None` checks only that such a row exists.
+    Related(&'static str, Option<(String, Comparison)>),
+    // Matches if a copy of the piece hasn't been returned, and if `overdue`, is past its due date.
+    OnLoan { overdue: bool },
+    // Matches if the piece was performed on or after `date(base, modifier)`.
+    PerformedSince { base: String, modifier: String },
+    // Matches if the piece lists a contributor in `role`, and if given, that it's `person`.
+    Contributor { role: String, person: Option<i32> },
+    // Matches if the piece has a range for `part` whose columns satisfy every comparison.
+    PartRange(String, Vec<(&'static str, Comparison)>),
+    // Matches if the piece has a value for the custom field with this id, and if given, the
+    // value satisfies the comparison.
+    Custom(Option<i32>, Option<Comparison>),
+}
+
+impl Condition {
+    // Appends this condition to `sql`, pushing its parameters so that they line up with the
+    // `?N` placeholders it writes.
+    fn write_sql(&self, sql: &mut String, params: &mut Vec<Value>) {
+        match self {
+            Condition::Compare(column, cmp) => {
+                if let Some(param) = cmp.param() {
+                    params.push(param);
+                }
+                *sql += &cmp.sql(column, params.len());
+            }
+            Condition::All(conds) => Self::write_group(conds, " AND ", "1", sql, params),
+            Condition::Any(conds) => Self::write_group(conds, " OR ", "0", sql, params),
+            Condition::Not(conds) => {
+                *sql += "NOT ";
+                Self::write_group(conds, " AND ", "1", sql, params);
+            }
+            // EXISTS rather than a join, so a piece never comes back once per matching keyword
+            Condition::HasKeyword(category, keyword) => {
+                params.push(category.clone().map_or(Value::Null, Value::Text));
+                params.push(Value::Text(keyword.clone()));
+                *sql += &format!("EXISTS (SELECT 1 FROM music_keywords mk
+                    INNER JOIN keywords k ON k.id = mk.kid
+                    WHERE mk.mid = music.id AND k.category IS ?{} AND k.keyword == ?{})",
+                    params.len() - 1, params.len());
+            }
+            Condition::Related(table, cmp) => {
+                *sql += &format!("EXISTS (SELECT 1 FROM {0} WHERE {0}.mid = music.id", table);
+                if let Some((column, cmp)) = cmp {
+                    if let Some(param) = cmp.param() {
+                        params.push(param);
+                    }
+                    *sql += &format!(" AND {}", cmp.sql(&format!("{}.{}", table, column), params.len()));
+                }
+                *sql += ")";
+            }
+            Condition::OnLoan { overdue } => {
+                *sql += "EXISTS (SELECT 1 FROM loans l
+                    INNER JOIN copies c ON c.id = l.cid
+                    WHERE c.mid = music.id AND l.returned IS NULL";
+                if *overdue {
+                    *sql += " AND l.due < date('now')";
+                }
+                *sql += ")";
+            }
+            Condition::PerformedSince { base, modifier } => {
+                params.push(Value::Text(base.clone()));
+                params.push(Value::Text(modifier.clone()));
+                *sql += &format!("EXISTS (SELECT 1 FROM performance_music pm
+                    INNER JOIN performances p ON p.id = pm.pid
+                    WHERE pm.mid = music.id AND p.date >= date(?{}, ?{}))",
+                    params.len() - 1, params.len());
+            }
+            Condition::Contributor { role, person } => {
+                params.push(Value::Text(role.clone()));
+                *sql += &format!("EXISTS (SELECT 1 FROM contributors c
+                    WHERE c.mid = music.id AND c.role == ?{}", params.len());
+                if let Some(person) = person {
+                    params.push(Value::Integer((*person).into()));
+                    *sql += &format!(" AND c.pid == ?{}", params.len());
+                }
+                *sql += ")";
+            }
+            // One EXISTS for all comparisons, so they hold for the same part's range
+            Condition::PartRange(part, cmps) => {
+                params.push(Value::Text(part.clone()));
+                *sql += &format!("EXISTS (SELECT 1 FROM part_ranges pr
+                    WHERE pr.mid = music.id AND pr.part == ?{}", params.len());
+                for (column, cmp) in cmps {
+                    if let Some(param) = cmp.param() {
+                        params.push(param);
+                    }
+                    *sql += &format!(" AND {}", cmp.sql(&format!("pr.{}", column), params.len()));
+                }
+                *sql += ")";
+            }
+            Condition::Custom(field, cmp) => {
+                params.push(field.map_or(Value::Null, |f| Value::Integer(f.into())));
+                *sql += &format!("EXISTS (SELECT 1 FROM custom_values cv
+                    WHERE cv.mid = music.id AND cv.fid == ?{}", params.len());
+                if let Some(cmp) = cmp {
+                    if let Some(param) = cmp.param() {
+                        params.push(param);
+                    }
+                    *sql += &format!(" AND {}", cmp.sql("cv.value", params.len()));
+                }
+                *sql += ")";
+            }
+            Condition::InCategory(category) => {
+                params.push(Value::Text(category.clone()));
+                *sql += &format!("EXISTS (SELECT 1 FROM music_keywords mk
+                    INNER JOIN keywords k ON k.id = mk.kid
+                    WHERE mk.mid = music.id AND k.category == ?{})",
+                    params.len());
+            }
+        }
+    }
+
+    fn write_group(conds: &[Condition], sep: &str, empty: &str, sql: &mut String, params: &mut Vec<Value>) {
+        if conds.is_empty() {
+            *sql += empty;
+            return;
+        }
+        *sql += "(";
+        for (i, cond) in conds.iter().enumerate() {
+            if i > 0 {
+                *sql += sep;
+            }
+            cond.write_sql(sql, params);
+        }
+        *sql += ")";
+    }
+}
+
+/// A column of `music`, for sorting and for picking which fields to work with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MusicField {
+    Id,
+    Title,
+    Source,
+    Composer,
+    Arranger,
+    Notes,
+    Runtime,
+}
+
+impl MusicField {
+    fn column(&self) -> &'static str {
+        match self {
+            MusicField::Id => "id",
+            MusicField::Title => "title",
+            MusicField::Source => "source",
+            MusicField::Composer => "composer",
+            MusicField::Arranger => "arranger",
+            MusicField::Notes => "notes",
+            MusicField::Runtime => "runtime",
+        }
+    }
+
+    fn is_text(&self) -> bool {
+        !matches!(self, MusicField::Id | MusicField::Runtime)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortDirection {
+    Ascending,
+    Descending,
+}
+
+// Which pieces a query looks at, depending on whether they're in the trash.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TrashFilter {
+    Exclude,
+    Include,
+    Only,
+}
+
+/// Builds a search over `music`. Conditions added directly are all required to match; use
+/// [`any_of`](MusicQuery::any_of), [`all_of`](MusicQuery::all_of) and [`not`](MusicQuery::not)
+/// to group them differently.
+///
+/// Pieces in the trash are left out unless asked for with
+/// [`include_trash`](MusicQuery::include_trash) or [`in_trash`](MusicQuery::in_trash).
+pub struct MusicQuery {
+    conditions: Vec<Condition>,
+    trash: TrashFilter,
+    order: Vec<(MusicField, SortDirection)>,
+    limit: Option<u32>,
+    offset: Option<u32>,
 }
 
 
@@ -133,244 +547,772 @@ pub struct MusicQuery {
 impl MusicQuery {
     fn new() -> MusicQuery {
         MusicQuery {
-            conditions: Vec::new()
+            conditions: Vec::new(),
+            trash: TrashFilter::Exclude,
+            order: Vec::new(),
+            limit: None,
+            offset: None,
         }
     }
 
-    fn make_query(&self) -> Result<String> {
-        use Comparison::*;
-        let mut query: String = "SELECT * FROM music".into();
-        if self.conditions.len() > 0 {
-            query += " WHERE";
-        }
-        let mut count = 0;
-        for cond in self.conditions.iter() {
-            count += match cond.1 {
-                Has | NotHas => 0,
-                _ => 1
-            };
-            query += &match cond.1 {
-                Has => format!(" {} IS NOT NULL", cond.0),
-                NotHas => format!(" {} IS NULL", cond.0),
-                Less(_) => format!(" {} < ?{}", cond.0, count),
-                LessEqual(_) => format!(" {} <= ?{}", cond.0, count),
-                Greater(_) => format!(" {} > ?{}", cond.0, count),
-                GreaterEqual(_) => format!(" {} >= ?{}", cond.0, count),
-                Equal(_) => format!(" {} == ?{}", cond.0, count),
-                NotEqual(_) => format!(" {} != ?{}", cond.0, count),
-                FloatLess(_) => format!(" {} < ?{}", cond.0, count),
-                FloatLessEqual(_) => format!(" {} <= ?{}", cond.0, count),
-                FloatGreater(_) => format!(" {} > ?{}", cond.0, count),
-                FloatGreaterEqual(_) => format!(" {} >= ?{}", cond.0, count),
-                FloatEqual(_) => format!(" {} == ?{}", cond.0, count),
-                FloatNotEqual(_) => format!(" {} != ?{}", cond.0, count),
-                StrEqual(_) => format!(" {} == ?{}", cond.0, count),
-                StrNotEqual(_) => format!(" {} != ?{}", cond.0, count),
-                Contains(_) => format!(" instr({}, ?{})", cond.0, count),
-                NotContains(_) => format!(" NOT instr({}, ?{})", cond.0, count),
-            };
-            if self.conditions.len() > 1 {
-                query += " AND";
+    fn make_filter(&self) -> (String, Vec<Value>) {
+        let mut filter: String = "FROM music".into();
+        let mut params = Vec::new();
+        let trash = match self.trash {
+            TrashFilter::Exclude => Some("music.deleted_at IS NULL"),
+            TrashFilter::Include => None,
+            TrashFilter::Only => Some("music.deleted_at IS NOT NULL"),
+        };
+        if !self.conditions.is_empty() || trash.is_some() {
+            filter += " WHERE ";
+            Condition::write_group(&self.conditions, " AND ", "1", &mut filter, &mut params);
+        }
+        if let Some(trash) = trash {
+            filter += &format!(" AND {}", trash);
+        }
+        (filter, params)
+    }
+
+    fn make_query(&self) -> Result<(String, Vec<Value>)> {
+        let (filter, params) = self.make_filter();
+        let mut query = format!("SELECT * {}", filter);
+        if !self.order.is_empty() {
+            let order = self.order.iter().map(|(field, dir)| {
+                format!("{}{} {}",
+                    field.column(),
+                    if field.is_text() {" COLLATE NOCASE"} else {""},
+                    if *dir == SortDirection::Ascending {"ASC"} else {"DESC"})
+            }).collect::<Vec<_>>().join(", ");
+            query += &format!(" ORDER BY {}", order);
+        }
+        if self.limit.is_some() || self.offset.is_some() {
+            // SQLite only takes an OFFSET after a LIMIT, and -1 means no limit
+            query += &format!(" LIMIT {}", self.limit.map_or(-1, i64::from));
+            if let Some(offset) = self.offset {
+                query += &format!(" OFFSET {}", offset);
             }
         }
         query += ";";
-        Ok(query)
+        Ok((query, params))
+    }
+
+    /// Sorts results by `field`. Call again to break ties with further fields.
+    pub fn order_by(&mut self, field: MusicField, direction: SortDirection) -> &mut Self {
+        self.order.push((field, direction));
+        self
+    }
+
+    pub fn limit(&mut self, limit: u32) -> &mut Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn offset(&mut self, offset: u32) -> &mut Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Looks at pieces in the trash as well as the rest.
+    pub fn include_trash(&mut self) -> &mut Self {
+        self.trash = TrashFilter::Include;
+        self
+    }
+
+    /// Looks only at pieces in the trash.
+    pub fn in_trash(&mut self) -> &mut Self {
+        self.trash = TrashFilter::Only;
+        self
+    }
+
+    /// Counts every matching piece, ignoring any limit or offset.
+    pub fn count(&self, db: &Database) -> Result<usize> {
+        let (filter, params) = self.make_filter();
+        let count: Option<i64> = db.query_row(&format!("SELECT count(*) {};", filter), params_from_iter(params), |row| {
+            row.get(0)
+        })?;
+        Ok(count.unwrap_or(0) as usize)
+    }
+
+    pub fn exists(&self, db: &Database) -> Result<bool> {
+        let (filter, params) = self.make_filter();
+        db.exists(&format!("SELECT 1 {};", filter), params_from_iter(params))
+    }
+
+
+    pub fn run(&self, db: &Database) -> Result<Vec<Music>> {
+        let (quer, params) = self.make_query()?;
+        db.query(&quer, params_from_iter(params), |row| Ok(Music::from_row(row)))
+    }
+
+    pub fn run_one(&self, db: &Database) -> Result<Option<Music>> {
+        let (quer, params) = self.make_query()?;
+        db.query_row(&quer, params_from_iter(params), |row| Ok(Music::from_row(row)))
+    }
+
+    fn push(&mut self, column: &str, cmp: Comparison) -> &mut Self {
+        self.conditions.push(Condition::Compare(column.into(), cmp));
+        self
+    }
+
+    fn group<F: FnOnce(&mut MusicQuery)>(f: F) -> Vec<Condition> {
+        let mut inner = MusicQuery::new();
+        f(&mut inner);
+        inner.conditions
+    }
+
+    /// Matches if any of the conditions added by `f` match.
+    pub fn any_of<F: FnOnce(&mut MusicQuery)>(&mut self, f: F) -> &mut Self {
+        let conds = Self::group(f);
+        self.conditions.push(Condition::Any(conds));
+        self
+    }
+
+    /// Matches if all of the conditions added by `f` match. Mostly useful inside
+    /// [`any_of`](MusicQuery::any_of).
+    pub fn all_of<F: FnOnce(&mut MusicQuery)>(&mut self, f: F) -> &mut Self {
+        let conds = Self::group(f);
+        self.conditions.push(Condition::All(conds));
+        self
+    }
+
+    /// Matches unless all of the conditions added by `f` match.
+    pub fn not<F: FnOnce(&mut MusicQuery)>(&mut self, f: F) -> &mut Self {
+        let conds = Self::group(f);
+        self.conditions.push(Condition::Not(conds));
+        self
+    }
+
+    pub fn has_keyword(&mut self, key: &Keyword) -> &mut Self {
+        self.conditions.push(Condition::HasKeyword(key.category.clone(), key.keyword.clone()));
+        self
+    }
+
+    pub fn lacks_keyword(&mut self, key: &Keyword) -> &mut Self {
+        self.not(|q| {
+            q.has_keyword(key);
+        })
+    }
+
+    pub fn has_all_keywords(&mut self, keys: &[Keyword]) -> &mut Self {
+        self.all_of(|q| {
+            for key in keys {
+                q.has_keyword(key);
+            }
+        })
+    }
+
+    /// Matches pieces with at least one of `keys`. An empty list matches nothing.
+    pub fn has_any_keyword(&mut self, keys: &[Keyword]) -> &mut Self {
+        self.any_of(|q| {
+            for key in keys {
+                q.has_keyword(key);
+            }
+        })
+    }
+
+    /// Matches pieces with any keyword in `category`.
+    pub fn in_category(&mut self, category: &str) -> &mut Self {
+        self.conditions.push(Condition::InCategory(category.into()));
+        self
+    }
+
+    fn push_related(&mut self, table: &'static str, column: &str, cmp: Comparison) -> &mut Self {
+        self.conditions.push(Condition::Related(table, Some((column.into(), cmp))));
+        self
+    }
+
+    pub fn has_copies(&mut self) -> &mut Self {
+        self.conditions.push(Condition::Related("copies", None));
+        self
+    }
+
+    pub fn no_copies(&mut self) -> &mut Self {
+        self.not(|q| {
+            q.has_copies();
+        })
+    }
+
+    /// Matches pieces with a copy stored exactly at `location`.
+    pub fn location_eq(&mut self, location: &str) -> &mut Self {
+        self.push_related("copies", "location", Comparison::StrEqual(location.into()))
+    }
+
+    /// Matches pieces with a copy stored somewhere whose location mentions `location`.
+    pub fn location_contains(&mut self, location: &str) -> &mut Self {
+        self.push_related("copies", "location", Comparison::Contains(location.into()))
+    }
+
+    /// Matches pieces with no copy stored anywhere whose location mentions `location`.
+    pub fn location_not_contains(&mut self, location: &str) -> &mut Self {
+        self.not(|q| {
+            q.location_contains(location);
+        })
+    }
+
+    pub fn accession_eq(&mut self, accession: &str) -> &mut Self {
+        self.push_related("copies", "accession", Comparison::StrEqual(accession.into()))
+    }
+
+    /// Matches pieces with at least one copy that is checked out and not yet returned.
+    pub fn on_loan(&mut self) -> &mut Self {
+        self.conditions.push(Condition::OnLoan { overdue: false });
+        self
+    }
+
+    /// Matches pieces with a copy that is still out past its due date.
+    pub fn overdue(&mut self) -> &mut Self {
+        self.conditions.push(Condition::OnLoan { overdue: true });
+        self
+    }
+
+    pub fn performed(&mut self) -> &mut Self {
+        self.conditions.push(Condition::Related("performance_music", None));
+        self
+    }
+
+    pub fn never_performed(&mut self) -> &mut Self {
+        self.not(|q| {
+            q.performed();
+        })
+    }
+
+    /// Matches pieces performed on or after `date` (`YYYY-MM-DD`).
+    pub fn performed_since(&mut self, date: &str) -> &mut Self {
+        self.conditions.push(Condition::PerformedSince { base: date.into(), modifier: "+0 days".into() });
+        self
+    }
+
+    /// Matches pieces performed within the last `years` years.
+    pub fn performed_within_years(&mut self, years: u32) -> &mut Self {
+        self.conditions.push(Condition::PerformedSince { base: "now".into(), modifier: format!("-{} years", years) });
+        self
+    }
+
+    /// Matches pieces not performed in the last `years` years, including ones never performed.
+    pub fn not_performed_in_years(&mut self, years: u32) -> &mut Self {
+        self.not(|q| {
+            q.performed_within_years(years);
+        })
+    }
+
+    pub fn id_eq(&mut self, id: i32) -> &mut Self {
+        self.push("id", Comparison::Equal(id))
+    }
+    
+    pub fn id_ne(&mut self, id: i32) -> &mut Self {
+        self.push("id", Comparison::NotEqual(id))
+    }
+
+    pub fn title_eq(&mut self, title: &str) -> &mut Self {
+        self.push("title", Comparison::StrEqual(title.into()))
+    }
+    
+    pub fn title_ne(&mut self, title: &str) -> &mut Self {
+        self.push("title", Comparison::StrNotEqual(title.into()))
+    }
+    
+    pub fn title_contains(&mut self, title: &str) -> &mut Self {
+        self.push("title", Comparison::Contains(title.into()))
+    }
+    
+    pub fn title_not_contains(&mut self, title: &str) -> &mut Self {
+        self.push("title", Comparison::NotContains(title.into()))
+    }
+    
+    pub fn source_eq(&mut self, source: &str) -> &mut Self {
+        self.push("source", Comparison::StrEqual(source.into()))
+    }
+    
+    pub fn source_ne(&mut self, source: &str) -> &mut Self {
+        self.push("source", Comparison::StrNotEqual(source.into()))
+    }
+    
+    pub fn source_contains(&mut self, source: &str) -> &mut Self {
+        self.push("source", Comparison::Contains(source.into()))
+    }
+    
+    pub fn source_not_contains(&mut self, source: &str) -> &mut Self {
+        self.push("source", Comparison::NotContains(source.into()))
+    }
+    
+    pub fn has_composer(&mut self) -> &mut Self {
+        self.push("composer", Comparison::Has)
+    }
+    
+    pub fn null_composer(&mut self) -> &mut Self {
+        self.push("composer", Comparison::NotHas)
+    }
+    
+    pub fn composer_eq(&mut self, composer: &str) -> &mut Self {
+        self.push("composer", Comparison::StrEqual(composer.into()))
+    }
+    
+    pub fn composer_ne(&mut self, composer: &str) -> &mut Self {
+        self.push("composer", Comparison::StrNotEqual(composer.into()))
+    }
+    
+    pub fn composer_contains(&mut self, composer: &str) -> &mut Self {
+        self.push("composer", Comparison::Contains(composer.into()))
+    }
+    
+    pub fn composer_not_contains(&mut self, composer: &str) -> &mut Self {
+        self.push("composer", Comparison::NotContains(composer.into()))
+    }
+
+    /// Matches pieces composed by the [`Person`] with id `id`, however their name was typed.
+    pub fn composer_id_eq(&mut self, id: i32) -> &mut Self {
+        self.push("composer_id", Comparison::Equal(id))
+    }
+
+    pub fn has_arranger(&mut self) -> &mut Self {
+        self.push("arranger", Comparison::Has)
+    }
+    
+    pub fn null_arranger(&mut self) -> &mut Self {
+        self.push("arranger", Comparison::NotHas)
+    }
+    
+    pub fn arranger_eq(&mut self, arranger: &str) -> &mut Self {
+        self.push("arranger", Comparison::StrEqual(arranger.into()))
+    }
+    
+    pub fn arranger_ne(&mut self, arranger: &str) -> &mut Self {
+        self.push("arranger", Comparison::StrNotEqual(arranger.into()))
+    }
+    
+    pub fn arranger_contains(&mut self, arranger: &str) -> &mut Self {
+        self.push("arranger", Comparison::Contains(arranger.into()))
+    }
+    
+    pub fn arranger_not_contains(&mut self, arranger: &str) -> &mut Self {
+        self.push("arranger", Comparison::NotContains(arranger.into()))
+    }
+
+    /// Matches pieces arranged by the [`Person`] with id `id`, however their name was typed.
+    pub fn arranger_id_eq(&mut self, id: i32) -> &mut Self {
+        self.push("arranger_id", Comparison::Equal(id))
+    }
+
+    /// Matches pieces that credit the [`Person`] with id `id` in any way.
+    pub fn credits_person(&mut self, id: i32) -> &mut Self {
+        self.any_of(|q| {
+            q.composer_id_eq(id);
+            q.arranger_id_eq(id);
+            q.push_related("contributors", "pid", Comparison::Equal(id));
+        })
+    }
+
+    /// Matches pieces that credit anyone in `role`, whether as the composer or arranger or
+    /// among the other [`Contributor`]s.
+    pub fn has_role(&mut self, role: ContributorRole) -> &mut Self {
+        self.any_of(|q| {
+            if let Some(column) = role.music_column() {
+                q.push(column, Comparison::Has);
+            }
+            q.conditions.push(Condition::Contributor { role: role.to_string(), person: None });
+        })
+    }
+
+    pub fn lacks_role(&mut self, role: ContributorRole) -> &mut Self {
+        self.not(|q| {
+            q.has_role(role);
+        })
+    }
+
+    /// Matches pieces that credit the [`Person`] with id `id` in `role`.
+    pub fn credits_as(&mut self, id: i32, role: ContributorRole) -> &mut Self {
+        self.any_of(|q| {
+            if let Some(column) = role.music_column() {
+                q.push(column, Comparison::Equal(id));
+            }
+            q.conditions.push(Condition::Contributor { role: role.to_string(), person: Some(id) });
+        })
+    }
+   
+    pub fn has_key(&mut self) -> &mut Self {
+        self.push("key", Comparison::Has)
+    }
+
+    pub fn null_key(&mut self) -> &mut Self {
+        self.push("key", Comparison::NotHas)
+    }
+
+    /// Matches pieces in exactly `key`. F♯ major and G♭ major are different keys.
+    pub fn key_eq(&mut self, key: &Key) -> &mut Self {
+        self.push("key", Comparison::StrEqual(key.to_string()))
+    }
+
+    pub fn key_ne(&mut self, key: &Key) -> &mut Self {
+        self.push("key", Comparison::StrNotEqual(key.to_string()))
+    }
+
+    /// Matches pieces in any key of `mode`.
+    pub fn key_in_mode(&mut self, mode: Mode) -> &mut Self {
+        // Keys are stored as "<tonic> <mode>"
+        self.push("key", Comparison::Contains(format!(" {}", mode)))
+    }
+
+    /// Matches pieces in any key written with `signature`, like E♭ major and C minor for 3♭.
+    pub fn key_signature_eq(&mut self, signature: KeySignature) -> &mut Self {
+        self.push("key_signature", Comparison::Equal(signature.sharps().into()))
+    }
+
+    pub fn has_range(&mut self) -> &mut Self {
+        self.push("range", Comparison::Has)
+    }
+
+    pub fn null_range(&mut self) -> &mut Self {
+        self.push("range", Comparison::NotHas)
+    }
+
+    /// Matches pieces whose notes all fall within `range`, such as pieces a singer with that
+    /// range can sing.
+    pub fn range_within(&mut self, range: &Range) -> &mut Self {
+        self.all_of(|q| {
+            q.push("range_low", Comparison::GreaterEqual(range.low().number()));
+            q.push("range_high", Comparison::LessEqual(range.high().number()));
+        })
+    }
+
+    /// Matches pieces whose range reaches `pitch`.
+    pub fn range_includes(&mut self, pitch: &Pitch) -> &mut Self {
+        self.all_of(|q| {
+            q.push("range_low", Comparison::LessEqual(pitch.number()));
+            q.push("range_high", Comparison::GreaterEqual(pitch.number()));
+        })
+    }
+
+    /// Matches pieces that never go above `pitch`.
+    pub fn range_high_le(&mut self, pitch: &Pitch) -> &mut Self {
+        self.push("range_high", Comparison::LessEqual(pitch.number()))
+    }
+    pub fn range_high_gt(&mut self, pitch: &Pitch) -> &mut Self {
+        self.push("range_high", Comparison::Greater(pitch.number()))
+    }
+    /// Matches pieces that never go below `pitch`.
+    pub fn range_low_ge(&mut self, pitch: &Pitch) -> &mut Self {
+        self.push("range_low", Comparison::GreaterEqual(pitch.number()))
+    }
+    pub fn range_low_lt(&mut self, pitch: &Pitch) -> &mut Self {
+        self.push("range_low", Comparison::Less(pitch.number()))
+    }
+
+    fn push_part_range(&mut self, part: &str, cmps: Vec<(&'static str, Comparison)>) -> &mut Self {
+        self.conditions.push(Condition::PartRange(part.trim().into(), cmps));
+        self
+    }
+
+    /// Matches pieces with a range recorded for `part`. Part names are compared ignoring case.
+    pub fn has_part_range(&mut self, part: &str) -> &mut Self {
+        self.push_part_range(part, Vec::new())
+    }
+
+    /// Matches pieces whose `part` stays within `range`, like a tuba part within F1–F3.
+    pub fn part_range_within(&mut self, part: &str, range: &Range) -> &mut Self {
+        self.push_part_range(part, vec![
+            ("range_low", Comparison::GreaterEqual(range.low().number())),
+            ("range_high", Comparison::LessEqual(range.high().number())),
+        ])
+    }
+
+    /// Matches pieces whose `part` reaches `pitch`.
+    pub fn part_range_includes(&mut self, part: &str, pitch: &Pitch) -> &mut Self {
+        self.push_part_range(part, vec![
+            ("range_low", Comparison::LessEqual(pitch.number())),
+            ("range_high", Comparison::GreaterEqual(pitch.number())),
+        ])
+    }
+
+    /// Matches pieces whose `part` never goes above `pitch`, like sopranos never above G5.
+    pub fn part_high_le(&mut self, part: &str, pitch: &Pitch) -> &mut Self {
+        self.push_part_range(part, vec![("range_high", Comparison::LessEqual(pitch.number()))])
+    }
+    pub fn part_high_gt(&mut self, part: &str, pitch: &Pitch) -> &mut Self {
+        self.push_part_range(part, vec![("range_high", Comparison::Greater(pitch.number()))])
+    }
+    /// Matches pieces whose `part` never goes below `pitch`.
+    pub fn part_low_ge(&mut self, part: &str, pitch: &Pitch) -> &mut Self {
+        self.push_part_range(part, vec![("range_low", Comparison::GreaterEqual(pitch.number()))])
+    }
+    pub fn part_low_lt(&mut self, part: &str, pitch: &Pitch) -> &mut Self {
+        self.push_part_range(part, vec![("range_low", Comparison::Less(pitch.number()))])
+    }
+
+    pub fn has_notes(&mut self) -> &mut Self {
+        self.push("notes", Comparison::Has)
+    }
+
+    pub fn null_notes(&mut self) -> &mut Self {
+        self.push("notes", Comparison::NotHas)
+    }
+
+    pub fn notes_eq(&mut self, notes: &str) -> &mut Self {
+        self.push("notes", Comparison::StrEqual(notes.into()))
+    }
+    
+    pub fn notes_ne(&mut self, notes: &str) -> &mut Self {
+        self.push("notes", Comparison::StrNotEqual(notes.into()))
+    }
+    
+    pub fn notes_contains(&mut self, notes: &str) -> &mut Self {
+        self.push("notes", Comparison::Contains(notes.into()))
+    }
+    
+    pub fn notes_not_contains(&mut self, notes: &str) -> &mut Self {
+        self.push("notes", Comparison::NotContains(notes.into()))
+    }
+
+    pub fn has_runtime(&mut self) -> &mut Self {
+        self.push("runtime", Comparison::Has)
+    }
+    
+    pub fn null_runtime(&mut self) -> &mut Self {
+        self.push("runtime", Comparison::NotHas)
+    }
+
+    pub fn runtime_eq(&mut self, runtime: u16) -> &mut Self {
+        self.push("runtime", Comparison::Equal(runtime.into()))
+    }
+    pub fn runtime_ne(&mut self, runtime: u16) -> &mut Self {
+        self.push("runtime", Comparison::NotEqual(runtime.into()))
+    }
+    pub fn runtime_lt(&mut self, runtime: u16) -> &mut Self {
+        self.push("runtime", Comparison::Less(runtime.into()))
+    }
+    pub fn runtime_le(&mut self, runtime: u16) -> &mut Self {
+        self.push("runtime", Comparison::LessEqual(runtime.into()))
+    }
+    pub fn runtime_gt(&mut self, runtime: u16) -> &mut Self {
+        self.push("runtime", Comparison::Greater(runtime.into()))
+    }
+    pub fn runtime_ge(&mut self, runtime: u16) -> &mut Self {
+        self.push("runtime", Comparison::GreaterEqual(runtime.into()))
+    }
+
+    fn push_custom(&mut self, field: &CustomField, cmp: Option<Comparison>) -> &mut Self {
+        self.conditions.push(Condition::Custom(field.id(), cmp));
+        self
+    }
+
+    /// Matches pieces with any value for `field`.
+    pub fn has_custom(&mut self, field: &CustomField) -> &mut Self {
+        self.push_custom(field, None)
+    }
+
+    pub fn null_custom(&mut self, field: &CustomField) -> &mut Self {
+        self.not(|q| {
+            q.has_custom(field);
+        })
+    }
+
+    pub fn custom_eq(&mut self, field: &CustomField, value: &CustomValue) -> &mut Self {
+        self.push_custom(field, Some(match value {
+            CustomValue::Number(n) => Comparison::FloatEqual(*n),
+            CustomValue::Bool(b) => Comparison::Equal((*b).into()),
+            other => Comparison::StrEqual(other.to_string()),
+        }))
+    }
+
+    /// Matches pieces with a value for `field` other than `value`.
+    pub fn custom_ne(&mut self, field: &CustomField, value: &CustomValue) -> &mut Self {
+        self.push_custom(field, Some(match value {
+            CustomValue::Number(n) => Comparison::FloatNotEqual(*n),
+            CustomValue::Bool(b) => Comparison::NotEqual((*b).into()),
+            other => Comparison::StrNotEqual(other.to_string()),
+        }))
+    }
+
+    pub fn custom_contains(&mut self, field: &CustomField, text: &str) -> &mut Self {
+        self.push_custom(field, Some(Comparison::Contains(text.into())))
+    }
+
+    /// Matches pieces whose value for `field` comes before `value`: smaller numbers, earlier
+    /// dates, or text earlier in the alphabet.
+    pub fn custom_lt(&mut self, field: &CustomField, value: &CustomValue) -> &mut Self {
+        self.push_custom(field, Some(match value {
+            CustomValue::Number(n) => Comparison::FloatLess(*n),
+            CustomValue::Bool(b) => Comparison::Less((*b).into()),
+            other => Comparison::StrLess(other.to_string()),
+        }))
+    }
+    pub fn custom_le(&mut self, field: &CustomField, value: &CustomValue) -> &mut Self {
+        self.push_custom(field, Some(match value {
+            CustomValue::Number(n) => Comparison::FloatLessEqual(*n),
+            CustomValue::Bool(b) => Comparison::LessEqual((*b).into()),
+            other => Comparison::StrLessEqual(other.to_string()),
+        }))
+    }
+    pub fn custom_gt(&mut self, field: &CustomField, value: &CustomValue) -> &mut Self {
+        self.push_custom(field, Some(match value {
+            CustomValue::Number(n) => Comparison::FloatGreater(*n),
+            CustomValue::Bool(b) => Comparison::Greater((*b).into()),
+            other => Comparison::StrGreater(other.to_string()),
+        }))
+    }
+    pub fn custom_ge(&mut self, field: &CustomField, value: &CustomValue) -> &mut Self {
+        self.push_custom(field, Some(match value {
+            CustomValue::Number(n) => Comparison::FloatGreaterEqual(*n),
+            CustomValue::Bool(b) => Comparison::GreaterEqual((*b).into()),
+            other => Comparison::StrGreaterEqual(other.to_string()),
+        }))
+    }
+
+    pub fn has_voicing(&mut self) -> &mut Self {
+        self.push("voicing", Comparison::Has)
+    }
+
+    pub fn null_voicing(&mut self) -> &mut Self {
+        self.push("voicing", Comparison::NotHas)
+    }
+
+    /// Matches pieces for exactly `voicing`, like "SATB", ignoring case.
+    pub fn voicing_eq(&mut self, voicing: &str) -> &mut Self {
+        self.push("voicing", Comparison::StrEqual(voicing.into()))
     }
 
+    pub fn voicing_ne(&mut self, voicing: &str) -> &mut Self {
+        self.push("voicing", Comparison::StrNotEqual(voicing.into()))
+    }
 
-    pub fn run(&self) -> Result<Vec<Music>> {
-        let quer = self.make_query()?;
-        query(&quer, params_from_iter(self.conditions.iter().filter_map(|c| {
-            c.1.param()
-        })), |row| Ok(Music::from_row(row)))
+    pub fn voicing_contains(&mut self, voicing: &str) -> &mut Self {
+        self.push("voicing", Comparison::Contains(voicing.into()))
     }
 
-    pub fn run_one(&self) -> Result<Option<Music>> {
-        let quer = self.make_query()?;
-        query_row(&quer, params_from_iter(self.conditions.iter().filter_map(|c| {
-            c.1.param()
-        })), |row| Ok(Music::from_row(row)))
+    pub fn voicing_not_contains(&mut self, voicing: &str) -> &mut Self {
+        self.push("voicing", Comparison::NotContains(voicing.into()))
     }
 
-    pub fn id_eq(&mut self, id: i32) -> &mut Self {
-        self.conditions.push(("id".into(), Comparison::Equal(id)));
-        self
+    pub fn has_grade(&mut self) -> &mut Self {
+        self.push("grade", Comparison::Has)
     }
-    
-    pub fn id_ne(&mut self, id: i32) -> &mut Self {
-        self.conditions.push(("id".into(), Comparison::NotEqual(id)));
-        self
+
+    pub fn null_grade(&mut self) -> &mut Self {
+        self.push("grade", Comparison::NotHas)
     }
 
-    pub fn title_eq(&mut self, title: &str) -> &mut Self {
-        self.conditions.push(("title".into(), Comparison::StrEqual(title.into())));
-        self
+    pub fn grade_eq(&mut self, grade: f64) -> &mut Self {
+        self.push("grade", Comparison::FloatEqual(grade))
     }
-    
-    pub fn title_ne(&mut self, title: &str) -> &mut Self {
-        self.conditions.push(("title".into(), Comparison::StrNotEqual(title.into())));
-        self
+    pub fn grade_ne(&mut self, grade: f64) -> &mut Self {
+        self.push("grade", Comparison::FloatNotEqual(grade))
     }
-    
-    pub fn title_contains(&mut self, title: &str) -> &mut Self {
-        self.conditions.push(("title".into(), Comparison::Contains(title.into())));
-        self
+    pub fn grade_lt(&mut self, grade: f64) -> &mut Self {
+        self.push("grade", Comparison::FloatLess(grade))
     }
-    
-    pub fn title_not_contains(&mut self, title: &str) -> &mut Self {
-        self.conditions.push(("title".into(), Comparison::NotContains(title.into())));
-        self
+    pub fn grade_le(&mut self, grade: f64) -> &mut Self {
+        self.push("grade", Comparison::FloatLessEqual(grade))
     }
-    
-    pub fn source_eq(&mut self, source: &str) -> &mut Self {
-        self.conditions.push(("source".into(), Comparison::StrEqual(source.into())));
-        self
+    pub fn grade_gt(&mut self, grade: f64) -> &mut Self {
+        self.push("grade", Comparison::FloatGreater(grade))
     }
-    
-    pub fn source_ne(&mut self, source: &str) -> &mut Self {
-        self.conditions.push(("source".into(), Comparison::StrNotEqual(source.into())));
-        self
+    pub fn grade_ge(&mut self, grade: f64) -> &mut Self {
+        self.push("grade", Comparison::FloatGreaterEqual(grade))
     }
-    
-    pub fn source_contains(&mut self, source: &str) -> &mut Self {
-        self.conditions.push(("source".into(), Comparison::Contains(source.into())));
-        self
+
+    pub fn has_publisher(&mut self) -> &mut Self {
+        self.push("publisher", Comparison::Has)
     }
-    
-    pub fn source_not_contains(&mut self, source: &str) -> &mut Self {
-        self.conditions.push(("source".into(), Comparison::NotContains(source.into())));
-        self
+
+    pub fn null_publisher(&mut self) -> &mut Self {
+        self.push("publisher", Comparison::NotHas)
     }
-    
-    pub fn has_composer(&mut self) -> &mut Self {
-        self.conditions.push(("composer".into(), Comparison::Has));
-        self
+
+    pub fn publisher_eq(&mut self, publisher: &str) -> &mut Self {
+        self.push("publisher", Comparison::StrEqual(publisher.into()))
     }
-    
-    pub fn null_composer(&mut self) -> &mut Self {
-        self.conditions.push(("composer".into(), Comparison::NotHas));
-        self
+
+    pub fn publisher_ne(&mut self, publisher: &str) -> &mut Self {
+        self.push("publisher", Comparison::StrNotEqual(publisher.into()))
     }
-    
-    pub fn composer_eq(&mut self, composer: &str) -> &mut Self {
-        self.conditions.push(("composer".into(), Comparison::StrEqual(composer.into())));
-        self
+
+    pub fn publisher_contains(&mut self, publisher: &str) -> &mut Self {
+        self.push("publisher", Comparison::Contains(publisher.into()))
     }
-    
-    pub fn composer_ne(&mut self, composer: &str) -> &mut Self {
-        self.conditions.push(("composer".into(), Comparison::StrNotEqual(composer.into())));
-        self
+
+    pub fn publisher_not_contains(&mut self, publisher: &str) -> &mut Self {
+        self.push("publisher", Comparison::NotContains(publisher.into()))
     }
-    
-    pub fn composer_contains(&mut self, composer: &str) -> &mut Self {
-        self.conditions.push(("composer".into(), Comparison::Contains(composer.into())));
-        self
+
+    pub fn has_catalog_number(&mut self) -> &mut Self {
+        self.push("catalog_number", Comparison::Has)
     }
-    
-    pub fn composer_not_contains(&mut self, composer: &str) -> &mut Self {
-        self.conditions.push(("composer".into(), Comparison::NotContains(composer.into())));
-        self
+
+    pub fn null_catalog_number(&mut self) -> &mut Self {
+        self.push("catalog_number", Comparison::NotHas)
     }
 
-    pub fn has_arranger(&mut self) -> &mut Self {
-        self.conditions.push(("arranger".into(), Comparison::Has));
-        self
+    pub fn catalog_number_eq(&mut self, number: &str) -> &mut Self {
+        self.push("catalog_number", Comparison::StrEqual(number.trim().into()))
     }
-    
-    pub fn null_arranger(&mut self) -> &mut Self {
-        self.conditions.push(("arranger".into(), Comparison::NotHas));
-        self
+
+    pub fn catalog_number_ne(&mut self, number: &str) -> &mut Self {
+        self.push("catalog_number", Comparison::StrNotEqual(number.trim().into()))
     }
-    
-    pub fn arranger_eq(&mut self, arranger: &str) -> &mut Self {
-        self.conditions.push(("arranger".into(), Comparison::StrEqual(arranger.into())));
-        self
+
+    pub fn has_ismn(&mut self) -> &mut Self {
+        self.push("ismn", Comparison::Has)
     }
-    
-    pub fn arranger_ne(&mut self, arranger: &str) -> &mut Self {
-        self.conditions.push(("arranger".into(), Comparison::StrNotEqual(arranger.into())));
-        self
+
+    pub fn null_ismn(&mut self) -> &mut Self {
+        self.push("ismn", Comparison::NotHas)
     }
-    
-    pub fn arranger_contains(&mut self, arranger: &str) -> &mut Self {
-        self.conditions.push(("arranger".into(), Comparison::Contains(arranger.into())));
-        self
+
+    pub fn ismn_eq(&mut self, ismn: &Ismn) -> &mut Self {
+        self.push("ismn", Comparison::StrEqual(ismn.digits().into()))
     }
-    
-    pub fn arranger_not_contains(&mut self, arranger: &str) -> &mut Self {
-        self.conditions.push(("arranger".into(), Comparison::NotContains(arranger.into())));
-        self
+
+    pub fn ismn_ne(&mut self, ismn: &Ismn) -> &mut Self {
+        self.push("ismn", Comparison::StrNotEqual(ismn.digits().into()))
     }
-   
-    pub fn has_notes(&mut self) -> &mut Self {
-        self.conditions.push(("notes".into(), Comparison::Has));
-        self
+
+    pub fn has_copyright_year(&mut self) -> &mut Self {
+        self.push("copyright_year", Comparison::Has)
     }
 
-    pub fn null_notes(&mut self) -> &mut Self {
-        self.conditions.push(("notes".into(), Comparison::NotHas));
-        self
+    pub fn null_copyright_year(&mut self) -> &mut Self {
+        self.push("copyright_year", Comparison::NotHas)
     }
 
-    pub fn notes_eq(&mut self, notes: &str) -> &mut Self {
-        self.conditions.push(("notes".into(), Comparison::StrEqual(notes.into())));
-        self
+    pub fn copyright_year_eq(&mut self, year: i32) -> &mut Self {
+        self.push("copyright_year", Comparison::Equal(year))
     }
-    
-    pub fn notes_ne(&mut self, notes: &str) -> &mut Self {
-        self.conditions.push(("notes".into(), Comparison::StrNotEqual(notes.into())));
-        self
+    pub fn copyright_year_ne(&mut self, year: i32) -> &mut Self {
+        self.push("copyright_year", Comparison::NotEqual(year))
     }
-    
-    pub fn notes_contains(&mut self, notes: &str) -> &mut Self {
-        self.conditions.push(("notes".into(), Comparison::Contains(notes.into())));
-        self
+    pub fn copyright_year_lt(&mut self, year: i32) -> &mut Self {
+        self.push("copyright_year", Comparison::Less(year))
     }
-    
-    pub fn notes_not_contains(&mut self, notes: &str) -> &mut Self {
-        self.conditions.push(("notes".into(), Comparison::NotContains(notes.into())));
-        self
+    pub fn copyright_year_le(&mut self, year: i32) -> &mut Self {
+        self.push("copyright_year", Comparison::LessEqual(year))
     }
-
-    pub fn has_runtime(&mut self) -> &mut Self {
-        self.conditions.push(("runtime".into(), Comparison::Has));
-        self
+    pub fn copyright_year_gt(&mut self, year: i32) -> &mut Self {
+        self.push("copyright_year", Comparison::Greater(year))
     }
-    
-    pub fn null_runtime(&mut self) -> &mut Self {
-        self.conditions.push(("runtime".into(), Comparison::NotHas));
-        self
+    pub fn copyright_year_ge(&mut self, year: i32) -> &mut Self {
+        self.push("copyright_year", Comparison::GreaterEqual(year))
     }
 
-    pub fn runtime_eq(&mut self, runtime: u16) -> &mut Self {
-        self.conditions.push(("runtime".into(), Comparison::Equal(runtime.into())));
-        self
+    pub fn has_language(&mut self) -> &mut Self {
+        self.push("language", Comparison::Has)
     }
-    pub fn runtime_ne(&mut self, runtime: u16) -> &mut Self {
-        self.conditions.push(("runtime".into(), Comparison::NotEqual(runtime.into())));
-        self
-    }
-    pub fn runtime_lt(&mut self, runtime: u16) -> &mut Self {
-        self.conditions.push(("runtime".into(), Comparison::Less(runtime.into())));
-        self
-    }
-    pub fn runtime_le(&mut self, runtime: u16) -> &mut Self {
-        self.conditions.push(("runtime".into(), Comparison::LessEqual(runtime.into())));
-        self
+
+    pub fn null_language(&mut self) -> &mut Self {
+        self.push("language", Comparison::NotHas)
     }
-    pub fn runtime_gt(&mut self, runtime: u16) -> &mut Self {
-        self.conditions.push(("runtime".into(), Comparison::Greater(runtime.into())));
-        self
+
+    /// Matches pieces in `language`, ignoring case.
+    pub fn language_eq(&mut self, language: &str) -> &mut Self {
+        self.push("language", Comparison::StrEqual(language.trim().into()))
     }
-    pub fn runtime_ge(&mut self, runtime: u16) -> &mut Self {
-        self.conditions.push(("runtime".into(), Comparison::GreaterEqual(runtime.into())));
-        self
+
+    pub fn language_ne(&mut self, language: &str) -> &mut Self {
+        self.push("language", Comparison::StrNotEqual(language.trim().into()))
     }
     
 }
 
 #[allow(dead_code)]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct Music {
     id: Option<i32>,
     pub title: String,
@@ -379,6 +1321,24 @@ pub struct Music {
     pub arranger: Option<String>,
     pub notes: Option<String>,
     pub runtime: Option<u16>,
+    deleted_at: Option<String>,
+    // Who `composer` and `arranger` name, filled in whenever the piece is saved
+    composer_id: Option<i32>,
+    arranger_id: Option<i32>,
+    pub key: Option<Key>,
+    /// The lowest and highest notes across the whole piece.
+    pub range: Option<Range>,
+    /// The voices or ensemble the piece is for, like "SATB", "SSA" or "concert band".
+    pub voicing: Option<String>,
+    /// Difficulty grade, like 3 or 2.5, on whatever scale the library uses.
+    pub grade: Option<f64>,
+    pub publisher: Option<String>,
+    /// The publisher's own number for the edition.
+    pub catalog_number: Option<String>,
+    pub ismn: Option<Ismn>,
+    pub copyright_year: Option<i32>,
+    /// The language of the text, for pieces that have one.
+    pub language: Option<String>,
 }
 
 #[allow(dead_code)]
@@ -392,19 +1352,23 @@ impl Music {
             arranger: row.get_unwrap(4),
             notes: row.get_unwrap(5),
             runtime: row.get_unwrap(6),
+            deleted_at: row.get_unwrap(7),
+            composer_id: row.get_unwrap(8),
+            arranger_id: row.get_unwrap(9),
+            key: row.get_unwrap::<_, Option<String>>(10).and_then(|k| k.parse().ok()),
+            range: row.get_unwrap::<_, Option<String>>(12).and_then(|r| r.parse().ok()),
+            voicing: row.get_unwrap(15),
+            grade: row.get_unwrap(16),
+            publisher: row.get_unwrap(17),
+            catalog_number: row.get_unwrap(18),
+            ismn: row.get_unwrap::<_, Option<String>>(19).and_then(|i| i.parse().ok()),
+            copyright_year: row.get_unwrap(20),
+            language: row.get_unwrap(21),
         }
     }
 
     pub fn new() -> Music {
-        Music {
-            id: None,
-            title: "".into(),
-            source: "".into(),
-            composer: None,
-            arranger: None,
-            notes: None,
-            runtime: None,
-        }
+        Music::default()
     }
 
     pub fn new_with_id(id: i32) -> Music {
@@ -414,98 +1378,161 @@ impl Music {
     }
 
     pub fn id(&self) -> Option<i32> {
-        self.id.clone()
+        self.id
+    }
+
+    /// When the piece was moved to the trash (UTC, as `YYYY-MM-DD HH:MM:SS`), if it's there.
+    pub fn deleted_at(&self) -> Option<&str> {
+        self.deleted_at.as_deref()
+    }
+
+    pub fn is_trashed(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+
+    pub fn composer_id(&self) -> Option<i32> {
+        self.composer_id
+    }
+
+    pub fn arranger_id(&self) -> Option<i32> {
+        self.arranger_id
     }
 
     pub fn query() -> MusicQuery {
         MusicQuery::new()
     }
 
-    pub fn list_all() -> Result<Vec<Music>> {
-        query("SELECT * FROM music;", named_params!{}, |row| {
+    pub fn list_all(db: &Database) -> Result<Vec<Music>> {
+        db.query("SELECT * FROM music WHERE deleted_at IS NULL;", named_params!{}, |row| {
+            Ok(Music::from_row(row))
+        })
+    }
+
+    /// Every piece in the trash, most recently deleted first.
+    pub fn list_trash(db: &Database) -> Result<Vec<Music>> {
+        db.query("SELECT * FROM music WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC, id DESC;", (), |row| {
             Ok(Music::from_row(row))
         })
     }
 
-    pub fn by_id(id: i32) -> Result<Option<Music>> {
+    /// Finds a piece whether or not it's in the trash.
+    pub fn by_id(db: &Database, id: i32) -> Result<Option<Music>> {
         // query_row("SELECT * FROM music WHERE id = :id;", named_params!{":id": id}, |row| {
         //     Ok(Music::from_row(row))
         // })
-        Self::query().id_eq(id).run_one()
+        Self::query().include_trash().id_eq(id).run_one(db)
     }
 
-    pub fn by_title(title: &str) -> Result<Vec<Music>> {
+    pub fn by_title(db: &Database, title: &str) -> Result<Vec<Music>> {
         // query("SELECT * FROM music WHERE title = :title;", named_params!{":title": title}, |row| {
         //     Ok(Music::from_row(row))
         // })
-        Self::query().title_eq(title).run()
+        Self::query().title_eq(title).run(db)
     }
 
-    pub fn by_source(source: &str) -> Result<Vec<Music>> {
+    pub fn by_source(db: &Database, source: &str) -> Result<Vec<Music>> {
         // query("SELECT * FROM music WHERE source = :source;", named_params!{":source": source}, |row| {
         //     Ok(Music::from_row(row))
         // })
-        Self::query().source_eq(source).run()
+        Self::query().source_eq(source).run(db)
     }
-    pub fn by_composer(composer: &str) -> Result<Vec<Music>> {
+    pub fn by_composer(db: &Database, composer: &str) -> Result<Vec<Music>> {
         // query("SELECT * FROM music WHERE composer = :composer;", named_params!{":composer": composer}, |row| {
         //     Ok(Music::from_row(row))
         // })
-        Self::query().composer_eq(composer).run()
+        Self::query().composer_eq(composer).run(db)
     }
-    pub fn by_arranger(arranger: &str) -> Result<Vec<Music>> {
+    pub fn by_arranger(db: &Database, arranger: &str) -> Result<Vec<Music>> {
         // query("SELECT * FROM music WHERE arranger = :arranger;", named_params!{":arranger": arranger}, |row| {
         //     Ok(Music::from_row(row))
         // })
-        Self::query().arranger_eq(arranger).run()
+        Self::query().arranger_eq(arranger).run(db)
     }
     
-    pub fn notes_contains(keyword: &str) -> Result<Vec<Music>> {
+    pub fn notes_contains(db: &Database, keyword: &str) -> Result<Vec<Music>> {
         // query("SELECT * FROM music WHERE instr(notes, :keyword);", named_params!{":keyword": keyword}, |row| {
         //     Ok(Music::from_row(row))
         // })
-        Self::query().notes_contains(keyword).run()
+        Self::query().notes_contains(keyword).run(db)
     }
 
-    pub fn by_keywords(keywords: &[Keyword]) -> Result<Vec<Music>> {
-        if keywords.len() == 0 {
-            return Ok(Vec::new());
-        }
-        let key_ids = Keyword::fetch_ids(keywords)?.into_iter().map(|id| id.to_string()).collect::<Vec<_>>().join(",");
-        query(&format!("SELECT m.id, m.title, m.source, m.composer, m.arranger, m.notes, m.runtime FROM music m
-               INNER JOIN music_keywords mk ON m.id == mk.mid
-               WHERE mk.kid IN ({});", key_ids), (), |row| Ok(Music::from_row(row)))
+    pub fn by_keywords(db: &Database, keywords: &[Keyword]) -> Result<Vec<Music>> {
+        Self::query().has_any_keyword(keywords).run(db)
     }
     
-    pub fn list_titles() -> Result<Vec<String>> {
-        query("SELECT DISTINCT title FROM music;", (), |row| {
-            Ok(row.get(0)?)
+    pub fn list_titles(db: &Database) -> Result<Vec<String>> {
+        db.query("SELECT DISTINCT title FROM music WHERE deleted_at IS NULL;", (), |row| {
+            row.get(0)
         })
     }
     
-    pub fn list_composers() -> Result<Vec<String>> {
-        query("SELECT DISTINCT composer FROM music;", (), |row| {
-            Ok(row.get(0)?)
-        })
+    /// Everyone credited as a composer, by name, in sort name order.
+    pub fn list_composers(db: &Database) -> Result<Vec<String>> {
+        Self::list_role(db, ContributorRole::Composer)
+    }
+
+    /// Everyone credited as an arranger, by name, in sort name order.
+    pub fn list_arrangers(db: &Database) -> Result<Vec<String>> {
+        Self::list_role(db, ContributorRole::Arranger)
     }
-    pub fn list_arrangers() -> Result<Vec<String>> {
-        query("SELECT DISTINCT arranger FROM music;", (), |row| {
-            Ok(row.get(0)?)
+
+    /// Everyone credited in `role` on a piece outside the trash, by name, in sort name order.
+    pub fn list_role(db: &Database, role: ContributorRole) -> Result<Vec<String>> {
+        let main_credit = match role.music_column() {
+            Some(column) => format!("m.{} = p.id", column),
+            None => "0".into(),
+        };
+        db.query(&format!("SELECT p.name FROM people p
+            WHERE EXISTS (SELECT 1 FROM music m WHERE m.deleted_at IS NULL AND ({}
+                OR EXISTS (SELECT 1 FROM contributors c WHERE c.mid = m.id AND c.pid = p.id AND c.role = :role)))
+            ORDER BY coalesce(p.sort_name, p.name) COLLATE NOCASE;", main_credit),
+            named_params!{":role": role.to_string()}, |row| {
+            row.get(0)
         })
     }
 
-    pub fn list_sources() -> Result<Vec<String>> {
-        query("SELECT DISTINCT source FROM music;", (), |row| {
-            Ok(row.get(0)?)
+    pub fn list_sources(db: &Database) -> Result<Vec<String>> {
+        db.query("SELECT DISTINCT source FROM music WHERE deleted_at IS NULL;", (), |row| {
+            row.get(0)
         })
     }
 
-    pub fn keywords(&self) -> Result<Option<Vec<Keyword>>> {
+    /// Searches every text field and keyword at once, best matches first.
+    ///
+    /// Each word of `text` matches any word that starts with it, so partially typed words still
+    /// find something.
+    pub fn search_text(db: &Database, text: &str) -> Result<Vec<SearchHit>> {
+        let terms = text.split_whitespace()
+            .map(|t| format!("\"{}\"*", t.replace('"', "\"\"")))
+            .collect::<Vec<_>>();
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+        db.query("SELECT m.id, m.title, m.source, m.composer, m.arranger, m.notes, m.runtime, m.deleted_at,
+                m.composer_id, m.arranger_id, m.key, m.key_signature, m.range, m.range_low, m.range_high,
+                m.voicing, m.grade, m.publisher, m.catalog_number, m.ismn, m.copyright_year, m.language,
+                snippet(music_search, -1, :start, :end, '…', 10)
+            FROM music_search s
+            INNER JOIN music m ON m.id = s.rowid
+            WHERE music_search MATCH :terms AND m.deleted_at IS NULL
+            ORDER BY s.rank;", named_params!{
+                ":start": HIGHLIGHT_START,
+                ":end": HIGHLIGHT_END,
+                ":terms": terms.join(" "),
+            }, |row| {
+                Ok(SearchHit {
+                    music: Music::from_row(row),
+                    snippet: row.get(22)?,
+                })
+            })
+    }
+
+    pub fn keywords(&self, db: &Database) -> Result<Option<Vec<Keyword>>> {
         if self.id.is_none() {
             return Ok(None);
         }
         // get associated keywords from DB
-        Ok(Some(query("
+        Ok(Some(db.query("
             SELECT k.id, k.category, k.keyword FROM keywords k 
             INNER JOIN music_keywords mk ON k.id = mk.kid
             WHERE mk.mid = :id;", 
@@ -515,15 +1542,42 @@ impl Music {
             })?))
     }
 
-    pub fn insert(&mut self) -> Result<()> {
+    /// Saves the piece. The composer and arranger are looked up by name or alias among
+    /// [`Person`]s, and added as new people if they aren't found.
+    pub fn insert(&mut self, db: &Database) -> Result<()> {
+        db.transaction_restoring(self, |db, music| {
+            music.resolve_people(db)?;
+            music.write(db)
+        })
+    }
+
+    fn write(&mut self, db: &Database) -> Result<()> {
         if !self.is_db_entry() {
-            execute("INSERT INTO music (title, composer, arranger, source, notes, runtime) VALUES (
+            // Never reuse the id of a deleted piece, or the new one would inherit its history.
+            let id = db.insert("INSERT INTO music (id, title, composer, arranger, source, notes, runtime, composer_id, arranger_id,
+                key, key_signature, range, range_low, range_high, voicing, grade, publisher, catalog_number, ismn,
+                copyright_year, language) VALUES (
+                max(ifnull((SELECT max(id) FROM music), 0), ifnull((SELECT max(mid) FROM history), 0)) + 1,
                 :title,
                 :composer,
                 :arranger,
                 :source,
                 :notes,
-                :runtime
+                :runtime,
+                :composer_id,
+                :arranger_id,
+                :key,
+                :key_signature,
+                :range,
+                :range_low,
+                :range_high,
+                :voicing,
+                :grade,
+                :publisher,
+                :catalog_number,
+                :ismn,
+                :copyright_year,
+                :language
             );", named_params!{
                 ":title": self.title,
                 ":composer": self.composer,
@@ -531,20 +1585,45 @@ impl Music {
                 ":source": self.source,
                 ":notes": self.notes,
                 ":runtime": self.runtime,
-            }).map(|_| ())?;
-            let id: i32 = query_row("SELECT max(id) from music LIMIT 1;", (), |row| {
-                Ok(row.get_unwrap(0))
-            })?.unwrap(); // Yes, this heavy duplication of Options is intentional
+                ":composer_id": self.composer_id,
+                ":arranger_id": self.arranger_id,
+                ":key": self.key.map(|k| k.to_string()),
+                ":key_signature": self.key.map(|k| k.signature().sharps()),
+                ":range": self.range.map(|r| r.to_string()),
+                ":range_low": self.range.map(|r| r.low().number()),
+                ":range_high": self.range.map(|r| r.high().number()),
+                ":voicing": self.voicing,
+                ":grade": self.grade,
+                ":publisher": self.publisher,
+                ":catalog_number": self.catalog_number,
+                ":ismn": self.ismn.as_ref().map(Ismn::digits),
+                ":copyright_year": self.copyright_year,
+                ":language": self.language,
+            })?;
             self.id = Some(id);
             Ok(())
         } else {
-            execute("UPDATE music SET
+            db.execute("UPDATE music SET
                 title = :title,
                 composer = :composer,
                 arranger = :arranger,
                 source = :source,
                 notes = :notes,
-                runtime = :runtime
+                runtime = :runtime,
+                composer_id = :composer_id,
+                arranger_id = :arranger_id,
+                key = :key,
+                key_signature = :key_signature,
+                range = :range,
+                range_low = :range_low,
+                range_high = :range_high,
+                voicing = :voicing,
+                grade = :grade,
+                publisher = :publisher,
+                catalog_number = :catalog_number,
+                ismn = :ismn,
+                copyright_year = :copyright_year,
+                language = :language
             WHERE id = :id;", named_params!{
                 ":id": self.id,
                 ":title": self.title,
@@ -553,93 +1632,158 @@ impl Music {
                 ":source": self.source,
                 ":notes": self.notes,
                 ":runtime": self.runtime,
+                ":composer_id": self.composer_id,
+                ":arranger_id": self.arranger_id,
+                ":key": self.key.map(|k| k.to_string()),
+                ":key_signature": self.key.map(|k| k.signature().sharps()),
+                ":range": self.range.map(|r| r.to_string()),
+                ":range_low": self.range.map(|r| r.low().number()),
+                ":range_high": self.range.map(|r| r.high().number()),
+                ":voicing": self.voicing,
+                ":grade": self.grade,
+                ":publisher": self.publisher,
+                ":catalog_number": self.catalog_number,
+                ":ismn": self.ismn.as_ref().map(Ismn::digits),
+                ":copyright_year": self.copyright_year,
+                ":language": self.language,
             }).map(|_| ())
         }
     }
 
-    pub fn insert_with_keywords(&mut self, keys: &mut [Keyword]) -> Result<()> {
-        self.insert()?;
-        // TODO insert keys if they don't exist, then form pairings
-        for key in keys.iter_mut() {
-            if !key.exists()? {
-                key.insert_update()?;
-            }
-            if !key.is_db_entry() {
-                key.update_self()?;
-            }
-            // We are assuming at this point that key has a valid id
-            execute("INSERT INTO music_keywords VALUES (:mid, :kid) ON CONFLICT DO NOTHING;", named_params!{
-                ":mid": self.id,
-                ":kid": key.id,
-            })?;
-        }
-        Ok(())
+    pub fn insert_with_keywords(&mut self, db: &Database, keys: &mut [Keyword]) -> Result<()> {
+        db.transaction_restoring(keys, |db, keys| {
+            db.transaction_restoring(self, |db, music| {
+                music.insert(db)?;
+                for key in keys.iter_mut() {
+                    music.link_keyword(db, key)?;
+                }
+                Ok(())
+            })
+        })
     }
 
-    pub fn update_keywords(&mut self, keys: &mut [Keyword]) -> Result<()> {
-        let keywords = self.keywords()?.unwrap();
-        let to_insert = keys.iter().filter(|k| !keywords.contains(k));
-        let to_remove = keywords.iter().filter(|k| !keys.contains(k));
-        for key in to_insert {
-            let mut key = key.clone();
-            self.add_keyword(&mut key)?;
-        }
-        for key in to_remove {
-            let mut key = key.clone();
-            self.remove_keyword(&mut key)?;
-        }
-        Ok(())
+    pub fn update_keywords(&mut self, db: &Database, keys: &mut [Keyword]) -> Result<()> {
+        db.transaction(|db| {
+            let keywords = self.keywords(db)?.unwrap();
+            let to_insert = keys.iter().filter(|k| !keywords.contains(k));
+            let to_remove = keywords.iter().filter(|k| !keys.contains(k));
+            for key in to_insert {
+                let mut key = key.clone();
+                self.add_keyword(db, &mut key)?;
+            }
+            for key in to_remove {
+                let mut key = key.clone();
+                self.remove_keyword(db, &mut key)?;
+            }
+            Ok(())
+        })
     }
 
-    pub fn remove_keyword(&mut self, key: &mut Keyword) -> Result<()> {
-        let Some(keywords) = self.keywords()? else {
+    pub fn remove_keyword(&mut self, db: &Database, key: &mut Keyword) -> Result<()> {
+        let Some(keywords) = self.keywords(db)? else {
             // ignore trying to delete non-db
             return Ok(());
         };
 
         if keywords.contains(key) {
             if !key.is_db_entry() {
-                key.update_self()?;
+                key.update_self(db)?;
             }
-            execute("DELETE FROM music_keywords WHERE mid = :mid AND kid = :kid;", named_params! {
-                ":mid": self.id,
-                ":kid": key.id,
+            db.transaction(|db| {
+                db.execute("DELETE FROM music_keywords WHERE mid = :mid AND kid = :kid;", named_params! {
+                    ":mid": self.id,
+                    ":kid": key.id,
+                })?;
+                db.auto_prune()
             })?;
         }
         Ok(())
     }
 
-    pub fn add_keyword(&mut self, key: &mut Keyword) -> Result<()> {
+    pub fn add_keyword(&mut self, db: &Database, key: &mut Keyword) -> Result<()> {
         // FIXME should probably add something more to handle being non-db, like an error
         if !self.is_db_entry() {
             return Ok(());
         }
-        if !key.exists()? {
-            key.insert_update()?;
+        let id = key.id;
+        let result = db.transaction(|db| self.link_keyword(db, key));
+        if result.is_err() {
+            key.id = id;
+        }
+        result
+    }
+
+    // Creates the keyword if needed and pairs it with this piece. Expects to run in a transaction.
+    fn link_keyword(&self, db: &Database, key: &mut Keyword) -> Result<()> {
+        if !key.exists(db)? {
+            key.insert_update(db)?;
         }
         if !key.is_db_entry() {
-            key.update_self()?;
+            key.update_self(db)?;
         }
         // We are assuming at this point that key has a valid id
-        execute("INSERT INTO music_keywords VALUES (:mid, :kid) ON CONFLICT DO NOTHING;", named_params!{
+        db.execute("INSERT INTO music_keywords VALUES (:mid, :kid) ON CONFLICT DO NOTHING;", named_params!{
             ":mid": self.id,
             ":kid": key.id,
         })?;
         Ok(())
     }
 
-    pub fn delete(self) -> Result<()> {
-        execute("DELETE FROM music WHERE id = :id;", named_params! {
+    /// Moves the piece to the trash. It keeps its keywords, copies and everything else until
+    /// it's deleted for good with [`Music::delete_permanently`] or [`Music::empty_trash`].
+    pub fn delete(self, db: &Database) -> Result<()> {
+        db.execute("UPDATE music SET deleted_at = strftime('%Y-%m-%d %H:%M:%S', 'now')
+            WHERE id = :id AND deleted_at IS NULL;", named_params! {
             ":id": self.id,
         })?;
         Ok(())
     }
 
+    /// Takes the piece back out of the trash.
+    pub fn restore(&mut self, db: &Database) -> Result<()> {
+        db.execute("UPDATE music SET deleted_at = NULL WHERE id = :id;", named_params!{":id": self.id})?;
+        self.deleted_at = None;
+        Ok(())
+    }
+
+    /// Deletes the piece along with its keyword links, copies, loans and attachments, without
+    /// going through the trash.
+    pub fn delete_permanently(self, db: &Database) -> Result<()> {
+        db.transaction(|db| {
+            db.execute("DELETE FROM music WHERE id = :id;", named_params! {
+                ":id": self.id,
+            })?;
+            db.auto_prune()
+        })
+    }
+
+    /// Permanently deletes every piece in the trash, returning how many there were.
+    pub fn empty_trash(db: &Database) -> Result<usize> {
+        db.transaction(|db| {
+            let removed = db.execute("DELETE FROM music WHERE deleted_at IS NOT NULL;", ())?;
+            db.auto_prune()?;
+            Ok(removed)
+        })
+    }
+
     pub fn is_db_entry(&self) -> bool {
         self.id.is_some()
     }
 }
 
+/// Marks the start of a matched word in [`SearchHit::snippet`].
+pub const HIGHLIGHT_START: &str = "«";
+/// Marks the end of a matched word in [`SearchHit::snippet`].
+pub const HIGHLIGHT_END: &str = "»";
+
+#[derive(Clone, Debug)]
+pub struct SearchHit {
+    pub music: Music,
+    /// The part of the best matching field around the match, with matched words surrounded by
+    /// [`HIGHLIGHT_START`] and [`HIGHLIGHT_END`].
+    pub snippet: String,
+}
+
 #[derive(Clone, Debug)]
 pub struct Keyword {
     id: Option<i32>,
@@ -657,14 +1801,20 @@ impl Keyword {
         }
     }
 
-    pub fn list_all() -> Result<Vec<Keyword>> {
-        query("SELECT * FROM keywords;", (), |row| {
+    pub fn list_all(db: &Database) -> Result<Vec<Keyword>> {
+        db.query("SELECT * FROM keywords;", (), |row| {
+            Ok(Keyword::from_row(row))
+        })
+    }
+
+    pub fn by_id(db: &Database, id: i32) -> Result<Option<Keyword>> {
+        db.query_row("SELECT * FROM keywords WHERE id = :id;", named_params!{":id": id}, |row| {
             Ok(Keyword::from_row(row))
         })
     }
 
-    pub fn list_categories() -> Result<Vec<String>> {
-        query("SELECT DISTINCT category FROM keywords;", (), |row| {
+    pub fn list_categories(db: &Database) -> Result<Vec<String>> {
+        db.query("SELECT DISTINCT category FROM keywords;", (), |row| {
             row.get(0)
         })
     }
@@ -673,33 +1823,49 @@ impl Keyword {
         keyword.parse().unwrap()
     }
 
+    pub fn id(&self) -> Option<i32> {
+        self.id
+    }
+
+    /// Every keyword along with the number of pieces tagged with it.
+    pub fn usage_counts(db: &Database) -> Result<Vec<(Keyword, usize)>> {
+        db.query("SELECT k.id, k.category, k.keyword, count(mk.mid) FROM keywords k
+            LEFT JOIN music_keywords mk ON k.id = mk.kid
+            GROUP BY k.id
+            ORDER BY k.category, k.keyword;", (), |row| {
+                Ok((Keyword::from_row(row), row.get::<_, i64>(3)? as usize))
+            })
+    }
+
+    /// Deletes every keyword that no piece uses, returning how many were removed.
+    pub fn prune_unused(db: &Database) -> Result<usize> {
+        db.execute("DELETE FROM keywords WHERE id NOT IN (SELECT kid FROM music_keywords);", ())
+    }
+
     pub fn is_db_entry(&self) -> bool {
         self.id.is_some()
     }
 
-    pub fn exists(&self) -> Result<bool> {
+    pub fn exists(&self, db: &Database) -> Result<bool> {
         let (query, params) = if self.is_db_entry() {
             ("SELECT * FROM keywords WHERE id = :id;", named_params!{":id": self.id})
         } else {
             ("SELECT * FROM keywords WHERE category IS :category AND keyword == :keyword;", 
              named_params!{":category": self.category, ":keyword": self.keyword})
         };
-        exists(query, params)
+        db.exists(query, params)
     }
 
-    pub fn insert(&mut self) -> Result<()> {
+    pub fn insert(&mut self, db: &Database) -> Result<()> {
         if !self.is_db_entry() {
-            execute("INSERT INTO keywords (category, keyword) VALUES (:category, :keyword);", named_params!{
+            let id = db.insert("INSERT INTO keywords (category, keyword) VALUES (:category, :keyword);", named_params!{
                 ":category": self.category,
                 ":keyword": self.keyword,
-            }).map(|_| ())?;
-            let id = query_row("SELECT max(id) FROM keywords LIMIT 1;", (), |row| {
-                Ok(row.get_unwrap(0))
-            })?.unwrap();
+            })?;
             self.id = Some(id);
             Ok(())
         } else {
-            execute("UPDATE keywords SET category = :category, keyword = :keyword WHERE id == :id;", named_params!{
+            db.execute("UPDATE keywords SET category = :category, keyword = :keyword WHERE id == :id;", named_params!{
                 ":id": self.id,
                 ":category": self.category,
                 ":keyword": self.keyword,
@@ -707,16 +1873,17 @@ impl Keyword {
         }
     }
 
-    pub fn insert_update(&mut self) -> Result<()> {
-        if !self.exists()? {
-            self.insert()?
-        }
-        self.update_self()?;
-        Ok(())
+    pub fn insert_update(&mut self, db: &Database) -> Result<()> {
+        db.transaction(|db| {
+            if !self.exists(db)? {
+                self.insert(db)?
+            }
+            self.update_self(db)
+        })
     }
     
-    fn update_self(&mut self) -> Result<()> {
-        let id = query_row("SELECT id FROM keywords WHERE category IS :category AND keyword == :keyword;",
+    fn update_self(&mut self, db: &Database) -> Result<()> {
+        let id = db.query_row("SELECT id FROM keywords WHERE category IS :category AND keyword == :keyword;",
             named_params!{
                 ":category": self.category,
                 ":keyword": self.keyword,
@@ -726,27 +1893,93 @@ impl Keyword {
         Ok(())
     }
 
-    fn fetch_ids(keys: &[Keyword]) -> Result<Vec<usize>> {
-        if keys.len() == 0 {
-            return Ok(Vec::new());
+    // The id of this keyword, looking it up if this copy doesn't have it yet.
+    fn resolve(&mut self, db: &Database) -> Result<i32> {
+        if !self.is_db_entry() {
+            self.update_self(db)?;
         }
-        let mut params = Vec::new();
-        let conditions = keys.iter().enumerate().map(|(i, k)| {
-            params.push(k.category.as_ref());
-            params.push(Some(&k.keyword));
-            format!("category IS ?{} AND keyword == ?{}", 2*i+1, 2*(i+1))
-        }).collect::<Vec<_>>().join(" OR ");
-        let quer = format!("SELECT id FROM keywords WHERE {};", conditions);
-        query(&quer, params_from_iter(params.into_iter()), |row| {
-            Ok(row.get_unwrap(0))
+        self.id.ok_or_else(|| Error::MissingKeyword(self.to_string()))
+    }
+
+    /// Renames this keyword on every piece that has it. If the new name is already taken in the
+    /// same category, the two keywords are merged.
+    pub fn rename(&mut self, db: &Database, keyword: &str) -> Result<()> {
+        let category = self.category.clone();
+        self.relabel(db, category, keyword.into())
+    }
+
+    /// Moves this keyword to `category`, or out of any category for `None`. If the keyword
+    /// already exists there, the two are merged.
+    pub fn recategorize(&mut self, db: &Database, category: Option<&str>) -> Result<()> {
+        let keyword = self.keyword.clone();
+        self.relabel(db, category.map(|c| c.into()), keyword)
+    }
+
+    fn relabel(&mut self, db: &Database, category: Option<String>, keyword: String) -> Result<()> {
+        let relabeled = db.transaction(|db| {
+            let id = self.clone().resolve(db)?;
+            let mut target = Keyword {
+                id: None,
+                category,
+                keyword,
+            };
+            target.update_self(db)?;
+            match target.id {
+                Some(target_id) if target_id != id => {
+                    Keyword::relink(db, id, target_id)?;
+                }
+                _ => {
+                    db.execute("UPDATE keywords SET category = :category, keyword = :keyword WHERE id == :id;", named_params!{
+                        ":id": id,
+                        ":category": target.category,
+                        ":keyword": target.keyword,
+                    })?;
+                    target.id = Some(id);
+                }
+            }
+            Ok(target)
+        })?;
+        *self = relabeled;
+        Ok(())
+    }
+
+    /// Moves every use of this keyword over to `target`, then deletes this keyword.
+    pub fn merge_into(mut self, db: &Database, target: &mut Keyword) -> Result<()> {
+        db.transaction(|db| {
+            let id = self.resolve(db)?;
+            let target_id = target.resolve(db)?;
+            if id != target_id {
+                Keyword::relink(db, id, target_id)?;
+            }
+            Ok(())
         })
     }
+
+    // Points everything tagged with `from` at `to` instead, and deletes `from`.
+    fn relink(db: &Database, from: i32, to: i32) -> Result<()> {
+        db.execute("INSERT INTO music_keywords (mid, kid)
+            SELECT mid, :to FROM music_keywords WHERE kid = :from
+            ON CONFLICT DO NOTHING;", named_params!{
+                ":from": from,
+                ":to": to,
+            })?;
+        db.execute("DELETE FROM keywords WHERE id = :id;", named_params!{":id": from})?;
+        Ok(())
+    }
+
+    /// Deletes this keyword and removes it from every piece.
+    pub fn delete(mut self, db: &Database) -> Result<()> {
+        let id = self.resolve(db)?;
+        db.execute("DELETE FROM keywords WHERE id = :id;", named_params!{":id": id})?;
+        Ok(())
+    }
 }
 
 impl std::str::FromStr for Keyword {
     type Err = Infallible;
     fn from_str(s: &str) -> std::result::Result<Keyword, Self::Err> {
-        let segments = s.splitn(2, ':').collect::<Vec<_>>();
+        // "season: Christmas" is the same keyword as "season:Christmas"
+        let segments = s.splitn(2, ':').map(str::trim).collect::<Vec<_>>();
         if segments.len() == 1 {
             Ok(Keyword {
                 id: None,
@@ -765,11 +1998,9 @@ impl std::str::FromStr for Keyword {
 
 impl std::fmt::Display for Keyword {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if self.category.is_none() {
-            write!(f, "{}", self.keyword)
-        }
-        else {
-            write!(f, "{}:{}", self.category.as_ref().unwrap(), self.keyword)
+        match self.category {
+            None => write!(f, "{}", self.keyword),
+            Some(ref category) => write!(f, "{}:{}", category, self.keyword),
         }
     }
 }
@@ -780,88 +2011,385 @@ impl PartialEq for Keyword {
     }
 }
 
-/*
-use regex::Regex;
-
-// referencing scientific pitch notation. C0 has u8 value 0
-pub struct Pitch(u8);
-
-pub type PitchError = &'static str;
-
-impl std::fmt::Display for Pitch {
-    fn fmt(&self, &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let letter = match self.0 % 12 {
-            0  => "C",
-            1  => "C♯/D♭",
-            2  => "D",
-            3  => "D♯/E♭",
-            4  => "E",
-            5  => "F",
-            6  => "F♯/G♭",
-            7  => "G",
-            8  => "G♯/A♭",
-            9  => "A",
-            10 => "A♯/B♭",
-            11 => "B",
-        };
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn piece(db: &Database, title: &str) -> Music {
+        let mut music = Music::new();
+        music.title = title.into();
+        music.insert(db).unwrap();
+        music
+    }
+
+    #[test]
+    fn databases_are_separate() {
+        let choir = Database::open_in_memory().unwrap();
+        let band = Database::open_in_memory().unwrap();
+        let music = piece(&choir, "Ave Maria");
+        assert_eq!(Music::list_all(&choir).unwrap().len(), 1);
+        assert!(Music::list_all(&band).unwrap().is_empty());
+        assert_eq!(Music::by_id(&choir, music.id().unwrap()).unwrap().unwrap().title, "Ave Maria");
+    }
+
+    #[test]
+    fn keywords_are_saved_with_their_piece() {
+        let db = Database::open_in_memory().unwrap();
+        let mut music = Music::new();
+        music.title = "Personent Hodie".into();
+        let mut keys = ["season:Christmas".parse().unwrap(), "unison".parse().unwrap()];
+        music.insert_with_keywords(&db, &mut keys).unwrap();
+        assert!(keys.iter().all(|k: &Keyword| k.is_db_entry()));
+        assert_eq!(music.keywords(&db).unwrap().unwrap().len(), 2);
+
+        music.update_keywords(&db, &mut ["unison".parse().unwrap(), "latin".parse().unwrap()]).unwrap();
+        let mut keywords: Vec<_> = music.keywords(&db).unwrap().unwrap().iter().map(|k| k.to_string()).collect();
+        keywords.sort();
+        assert_eq!(keywords, ["latin", "unison"]);
+    }
+
+    #[test]
+    fn failed_transactions_leave_nothing_behind() {
+        let db = Database::open_in_memory().unwrap();
+        let mut music = Music::new();
+        music.title = "In the Bleak Midwinter".into();
+        let result = db.transaction_restoring(&mut music, |db, music| {
+            music.insert(db)?;
+            Err::<(), _>(Error::NotConnected)
+        });
+        assert!(matches!(result, Err(Error::NotConnected)));
+        assert_eq!(music.id(), None);
+        assert!(Music::list_all(&db).unwrap().is_empty());
+    }
+
+    #[test]
+    fn inner_transactions_roll_back_alone() {
+        let db = Database::open_in_memory().unwrap();
+        db.transaction(|db| {
+            piece(db, "Kept");
+            let inner = db.transaction(|db| {
+                piece(db, "Dropped");
+                Err::<(), _>(Error::NotConnected)
+            });
+            assert!(inner.is_err());
+            Ok(())
+        }).unwrap();
+        let titles: Vec<_> = Music::list_all(&db).unwrap().into_iter().map(|m| m.title).collect();
+        assert_eq!(titles, ["Kept"]);
+    }
+
+    fn found(db: &Database, text: &str) -> Vec<String> {
+        Music::search_text(db, text).unwrap().into_iter().map(|h| h.music.title).collect()
+    }
+
+    #[test]
+    fn search_finds_partial_words_and_marks_them() {
+        let db = Database::open_in_memory().unwrap();
+        piece(&db, "Silent Night");
+        piece(&db, "Still, Still, Still");
+        let hits = Music::search_text(&db, "sil nig").unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].snippet, format!("{0}Silent{1} {0}Night{1}", HIGHLIGHT_START, HIGHLIGHT_END));
+        assert_eq!(found(&db, "STILL").len(), 1);
+        assert!(found(&db, "  ").is_empty());
+        assert!(found(&db, "\"unbalanced").is_empty());
+    }
+
+    #[test]
+    fn search_index_follows_changes() {
+        let db = Database::open_in_memory().unwrap();
+        let mut music = piece(&db, "Silent Night");
+        music.composer = Some("Franz Gruber".into());
+        music.insert(&db).unwrap();
+        assert_eq!(found(&db, "gruber"), ["Silent Night"]);
+
+        music.composer = Some("Joseph Mohr".into());
+        music.insert(&db).unwrap();
+        assert!(found(&db, "gruber").is_empty());
+        assert_eq!(found(&db, "mohr"), ["Silent Night"]);
+
+        music.update_keywords(&db, &mut ["season:Christmas".parse().unwrap()]).unwrap();
+        assert_eq!(found(&db, "christmas"), ["Silent Night"]);
+        assert_eq!(found(&db, "season"), ["Silent Night"]);
+        music.update_keywords(&db, &mut []).unwrap();
+        assert!(found(&db, "christmas").is_empty());
+
+        music.delete_permanently(&db).unwrap();
+        assert!(found(&db, "mohr").is_empty());
+        assert_eq!(db.query("SELECT count(*) FROM music_search;", (), |row| row.get::<_, i32>(0)).unwrap(), [0]);
+    }
+
+    fn library(db: &Database) {
+        for (title, composer) in [("Silent Night", "Gruber"), ("Still, Still, Still", "Traditional"),
+            ("Night of Silence", "Kantor"), ("Stille Nacht", "Gruber")] {
+            let mut music = Music::new();
+            music.title = title.into();
+            music.composer = Some(composer.into());
+            music.insert(db).unwrap();
+        }
+    }
 
-        let octave = self.0 / 12;
-        write!("{}{}", letter, octave)
+    fn titles(db: &Database, query: &MusicQuery) -> Vec<String> {
+        let mut titles: Vec<_> = query.run(db).unwrap().into_iter().map(|m| m.title).collect();
+        titles.sort();
+        titles
     }
-}
 
-static NOTE_PATTERN: std::sync::OnceLock<Regex> = OnceLock::new();
+    #[test]
+    fn conditions_must_all_match() {
+        let db = Database::open_in_memory().unwrap();
+        library(&db);
+        let mut query = Music::query();
+        query.title_contains("Night").composer_eq("Gruber");
+        assert_eq!(titles(&db, &query), ["Silent Night"]);
+    }
 
-// allow C sharp/C flat/C♯/C♭/C#/Cb
-impl std::str::FromStr for Pitch {
-    type Err = PitchError;
-    fn from_str(s: &str) -> Result<Pitch, Self::Err> {
-        let pattern = NOTE_PATTERN.get_or_init(|| {
-            Regex::new(r"(?<letter>[a-gA-G])(?<modifier>sharp|flat|#|b|♯|♭)?(?<octave>10|[0-9])").unwrap()
+    #[test]
+    fn groups() {
+        let db = Database::open_in_memory().unwrap();
+        library(&db);
+        let mut query = Music::query();
+        query.any_of(|q| {
+            q.title_eq("Stille Nacht");
+            q.composer_eq("Kantor");
         });
-        let caps = pattern.captures(s).map_err(|| "Invalid note")?;
-        let letter = caps.name("letter").unwrap();
-        let modifier = caps.name("modifier");
-        let octave: u8 = caps.name("octave").unwrap().parse().unwrap();
-        let code = match letter.to_ascii_uppercase() {
-            "C" => 0,
-            "D" => 2,
-            "E" => 4,
-            "F" => 5,
-            "G" => 7,
-            "A" => 9,
-            "B" => 11,
-            _ => unreachable!()
-        };
-        let code = match modifier {
-            None => code,
-            Some(m) => {
-                match m {
-                    "sharp" | "#" | "♯" => {
-                        code + 1
-                    }
-                    "flat" | "b" | "♭" => {
-                        code - 1
-                    }
-                    _ => unreachable!()
-                }
-            }
-        };
+        assert_eq!(titles(&db, &query), ["Night of Silence", "Stille Nacht"]);
 
-        let code = octave * 12 + code;
-        Ok(Pitch(code))
+        let mut query = Music::query();
+        query.not(|q| {
+            q.composer_eq("Gruber");
+            q.title_contains("Nacht");
+        });
+        assert_eq!(titles(&db, &query), ["Night of Silence", "Silent Night", "Still, Still, Still"]);
+
+        let mut query = Music::query();
+        query.any_of(|q| {
+            q.all_of(|q| {
+                q.composer_eq("Gruber");
+                q.title_contains("Silent");
+            });
+            q.not(|q| { q.title_contains("i"); });
+        });
+        assert_eq!(titles(&db, &query), ["Silent Night"]);
+    }
+
+    #[test]
+    fn empty_groups() {
+        let db = Database::open_in_memory().unwrap();
+        library(&db);
+        assert!(titles(&db, Music::query().any_of(|_| {})).is_empty());
+        assert_eq!(titles(&db, Music::query().all_of(|_| {})).len(), 4);
+        assert!(titles(&db, Music::query().not(|_| {})).is_empty());
+    }
+
+    #[test]
+    fn placeholders_line_up_with_params() {
+        let mut query = Music::query();
+        query.title_contains("a").any_of(|q| {
+            q.composer_eq("b");
+            q.not(|q| { q.has_keyword(&"c:d".parse().unwrap()); });
+        }).title_ne("e");
+        let (sql, params) = query.make_query().unwrap();
+        let text = |v: &Value| match v {
+            Value::Text(s) => s.clone(),
+            _ => "?".into(),
+        };
+        assert_eq!(params.iter().map(text).collect::<Vec<_>>(), ["a", "b", "c", "d", "e"]);
+        for n in 1..=5 {
+            assert_eq!(sql.matches(&format!("?{}", n)).count(), 1, "?{} in {}", n, sql);
+        }
+        assert!(!sql.contains("?6"));
     }
-}
 
-impl From<u8> for Pitch {
-    fn from(value: u8) -> Pitch {
-        Pitch(value)
-    }
-}
+    #[test]
+    fn ordering_and_paging() {
+        let db = Database::open_in_memory().unwrap();
+        library(&db);
+        piece(&db, "silent night");
+        let page = |query: &MusicQuery| -> Vec<String> {
+            query.run(&db).unwrap().into_iter().map(|m| m.title).collect()
+        };
+
+        let mut query = Music::query();
+        query.order_by(MusicField::Title, SortDirection::Ascending).order_by(MusicField::Id, SortDirection::Descending);
+        assert_eq!(page(&query), ["Night of Silence", "silent night", "Silent Night", "Still, Still, Still", "Stille Nacht"]);
+        query.limit(2).offset(1);
+        assert_eq!(page(&query), ["silent night", "Silent Night"]);
+        assert_eq!(query.count(&db).unwrap(), 5);
+
+        let mut query = Music::query();
+        query.composer_eq("Gruber").order_by(MusicField::Title, SortDirection::Descending).offset(1);
+        assert_eq!(page(&query), ["Silent Night"]);
+        assert!(query.exists(&db).unwrap());
+        assert!(!Music::query().composer_eq("Bach").exists(&db).unwrap());
+    }
+
+    #[test]
+    fn keyword_predicates() {
+        let db = Database::open_in_memory().unwrap();
+        for (title, keys) in [("Silent Night", "season:Christmas german"), ("Christmas Day", "Christmas"),
+            ("Ave Maria", "latin"), ("Es ist ein Ros", "season:Christmas season:Advent german")] {
+            let mut music = Music::new();
+            music.title = title.into();
+            let mut keys: Vec<Keyword> = keys.split(' ').map(|k| k.parse().unwrap()).collect();
+            music.insert_with_keywords(&db, &mut keys).unwrap();
+        }
+        let key = |k: &str| -> Keyword { k.parse().unwrap() };
+
+        assert_eq!(titles(&db, Music::query().has_keyword(&key("season:Christmas"))), ["Es ist ein Ros", "Silent Night"]);
+        assert_eq!(titles(&db, Music::query().has_keyword(&key("Christmas"))), ["Christmas Day"]);
+        assert_eq!(titles(&db, Music::query().lacks_keyword(&key("german"))), ["Ave Maria", "Christmas Day"]);
+        assert_eq!(titles(&db, Music::query().has_all_keywords(&[key("german"), key("season:Advent")])), ["Es ist ein Ros"]);
+        assert_eq!(titles(&db, Music::query().has_any_keyword(&[key("latin"), key("Christmas")])), ["Ave Maria", "Christmas Day"]);
+        assert!(titles(&db, Music::query().has_any_keyword(&[])).is_empty());
+        // Once per piece, however many keywords in the category it has
+        assert_eq!(titles(&db, Music::query().in_category("season")), ["Es ist ein Ros", "Silent Night"]);
+    }
+
+    fn tagged(db: &Database, title: &str, keys: &str) -> Music {
+        let mut music = Music::new();
+        music.title = title.into();
+        let mut keys: Vec<Keyword> = keys.split(';').map(|k| k.parse().unwrap()).collect();
+        music.insert_with_keywords(db, &mut keys).unwrap();
+        music
+    }
+
+    fn keywords_of(db: &Database, music: &Music) -> Vec<String> {
+        let mut keywords: Vec<_> = music.keywords(db).unwrap().unwrap().iter().map(|k| k.to_string()).collect();
+        keywords.sort();
+        keywords
+    }
+
+    #[test]
+    fn renaming_onto_an_existing_keyword_merges_them() {
+        let db = Database::open_in_memory().unwrap();
+        let both = tagged(&db, "Riu Riu Chiu", "xmas;Christmas");
+        let one = tagged(&db, "Gaudete", "xmas");
+        let mut xmas: Keyword = "xmas".parse().unwrap();
+        xmas.rename(&db, "Christmas").unwrap();
+
+        assert_eq!(keywords_of(&db, &both), ["Christmas"]);
+        assert_eq!(keywords_of(&db, &one), ["Christmas"]);
+        assert_eq!(Keyword::usage_counts(&db).unwrap().into_iter().map(|(k, n)| (k.to_string(), n)).collect::<Vec<_>>(),
+            [("Christmas".to_string(), 2)]);
+        assert_eq!(Music::search_text(&db, "xmas").unwrap().len(), 0);
+    }
+
+    #[test]
+    fn managing_keywords() {
+        let db = Database::open_in_memory().unwrap();
+        let music = tagged(&db, "Veni Emmanuel", "Advent;latin;chant");
+        let mut advent: Keyword = "Advent".parse().unwrap();
+        advent.recategorize(&db, Some("season")).unwrap();
+        assert_eq!(advent.to_string(), "season:Advent");
+        assert_eq!(Music::search_text(&db, "season").unwrap().len(), 1);
+
+        let chant: Keyword = "chant".parse().unwrap();
+        chant.merge_into(&db, &mut "latin".parse().unwrap()).unwrap();
+        let latin: Keyword = "latin".parse().unwrap();
+        latin.delete(&db).unwrap();
+        assert_eq!(keywords_of(&db, &music), ["season:Advent"]);
+
+        let missing: Keyword = "missing".parse().unwrap();
+        assert!(matches!(missing.delete(&db), Err(Error::MissingKeyword(_))));
+    }
+
+    fn keyword_names(db: &Database) -> Vec<String> {
+        let mut names: Vec<_> = Keyword::list_all(db).unwrap().iter().map(|k| k.to_string()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn unused_keywords_are_pruned_only_when_asked() {
+        let db = Database::open_in_memory().unwrap();
+        let mut music = tagged(&db, "Ubi Caritas", "latin;Lent");
+        tagged(&db, "Ave Verum", "latin");
+        music.remove_keyword(&db, &mut "Lent".parse().unwrap()).unwrap();
+        let counts: Vec<_> = Keyword::usage_counts(&db).unwrap().into_iter().map(|(k, n)| (k.to_string(), n)).collect();
+        assert_eq!(counts, [("Lent".to_string(), 0), ("latin".to_string(), 2)]);
+        assert_eq!(Keyword::prune_unused(&db).unwrap(), 1);
+        assert_eq!(keyword_names(&db), ["latin"]);
+
+        db.set_prune_unused_keywords(true);
+        let mut other = tagged(&db, "Pange Lingua", "chant;Lent");
+        other.remove_keyword(&db, &mut "chant".parse().unwrap()).unwrap();
+        assert_eq!(keyword_names(&db), ["Lent", "latin"]);
+        other.delete_permanently(&db).unwrap();
+        assert_eq!(keyword_names(&db), ["latin"]);
+    }
+
+    #[test]
+    fn deleted_pieces_wait_in_the_trash() {
+        let db = Database::open_in_memory().unwrap();
+        let mut hymn = tagged(&db, "Abide with Me", "hymn");
+        let carol = tagged(&db, "Sussex Carol", "carol");
+        let mut copy = MusicCopy::new(carol.id().unwrap());
+        copy.insert(&db).unwrap();
+
+        hymn.clone().delete(&db).unwrap();
+        carol.clone().delete(&db).unwrap();
+        assert!(Music::list_all(&db).unwrap().is_empty());
+        assert!(Music::search_text(&db, "abide").unwrap().is_empty());
+        assert_eq!(titles(&db, Music::query().in_trash().order_by(MusicField::Title, SortDirection::Ascending)),
+            ["Abide with Me", "Sussex Carol"]);
+        assert_eq!(Music::query().include_trash().count(&db).unwrap(), 2);
+        // Everything that belongs to a trashed piece stays until the trash is emptied
+        assert_eq!(keyword_names(&db), ["carol", "hymn"]);
+        assert!(Music::by_id(&db, carol.id().unwrap()).unwrap().unwrap().is_trashed());
+
+        hymn.restore(&db).unwrap();
+        assert_eq!(titles(&db, &Music::query()), ["Abide with Me"]);
+        assert_eq!(Music::empty_trash(&db).unwrap(), 1);
+        assert!(Music::list_trash(&db).unwrap().is_empty());
+        assert!(Music::by_id(&db, carol.id().unwrap()).unwrap().is_none());
+        assert!(MusicCopy::by_id(&db, copy.id().unwrap()).unwrap().is_none());
+    }
+
+    #[test]
+    fn keys_and_ranges_are_searchable() {
+        let db = Database::open_in_memory().unwrap();
+        for (title, key, range) in [("Greensleeves", Some("E dorian"), Some("D4-E5")),
+            ("Ave Verum Corpus", Some("D major"), Some("D4-D5")), ("Jesu, Joy", Some("B minor"), None),
+            ("Chant", None, Some("C4-A4"))] {
+            let mut music = Music::new();
+            music.title = title.into();
+            music.key = key.map(|k| k.parse().unwrap());
+            music.range = range.map(|r| r.parse().unwrap());
+            music.insert(&db).unwrap();
+        }
 
-impl Into<u8> for Pitch {
-    fn into(self) -> u8 {
-        self.0
+        assert_eq!(titles(&db, Music::query().key_eq(&"D".parse().unwrap())), ["Ave Verum Corpus"]);
+        // E dorian is written with the same two sharps as D major and B minor
+        assert_eq!(titles(&db, Music::query().key_signature_eq(KeySignature::new(2))),
+            ["Ave Verum Corpus", "Greensleeves", "Jesu, Joy"]);
+        assert_eq!(titles(&db, Music::query().key_in_mode(Mode::Dorian)), ["Greensleeves"]);
+        assert_eq!(titles(&db, Music::query().null_key()), ["Chant"]);
+        assert_eq!(titles(&db, Music::query().range_within(&"C4-D5".parse().unwrap())), ["Ave Verum Corpus", "Chant"]);
+        assert_eq!(titles(&db, Music::query().range_includes(&"D5".parse().unwrap())), ["Ave Verum Corpus", "Greensleeves"]);
+        assert_eq!(Music::by_title(&db, "Greensleeves").unwrap()[0].key.unwrap().to_string(), "E dorian");
+    }
+
+    #[test]
+    fn catalog_fields_are_saved_and_searchable() {
+        let db = Database::open_in_memory().unwrap();
+        let mut music = Music::new();
+        music.title = "Ave Maria".into();
+        music.voicing = Some("SATB divisi".into());
+        music.grade = Some(3.5);
+        music.publisher = Some("Bärenreiter".into());
+        music.ismn = Some("M-2600-0043-8".parse().unwrap());
+        music.copyright_year = Some(1998);
+        music.language = Some("Latin".into());
+        music.insert(&db).unwrap();
+        piece(&db, "Untitled");
+
+        let saved = Music::by_id(&db, music.id().unwrap()).unwrap().unwrap();
+        assert_eq!(saved.ismn.unwrap().to_string(), "979-0-26000043-8");
+        assert_eq!(saved.grade, Some(3.5));
+        assert_eq!(titles(&db, Music::query().ismn_eq(&"979-0-2600-0043-8".parse().unwrap())), ["Ave Maria"]);
+        assert_eq!(titles(&db, Music::query().voicing_contains("SATB").grade_le(4.0)), ["Ave Maria"]);
+        assert_eq!(titles(&db, Music::query().copyright_year_lt(2000).language_eq("Latin")), ["Ave Maria"]);
+        assert_eq!(titles(&db, Music::query().null_publisher()), ["Untitled"]);
     }
 }
-*/
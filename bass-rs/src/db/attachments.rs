@@ -0,0 +1,302 @@
+use std::path::{Path, PathBuf};
+
+use rusqlite::{named_params, Row};
+
+use super::{Database, Music, Result};
+
+/// What an attached file holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AttachmentKind {
+    Score,
+    Part,
+    Audio,
+    Video,
+    Other,
+}
+
+impl AttachmentKind {
+    pub const ALL: [AttachmentKind; 5] = [
+        AttachmentKind::Score,
+        AttachmentKind::Part,
+        AttachmentKind::Audio,
+        AttachmentKind::Video,
+        AttachmentKind::Other,
+    ];
+
+    /// A best guess from a file's extension. PDFs and images are assumed to be scores.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> AttachmentKind {
+        let extension = path.as_ref().extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("pdf" | "png" | "jpg" | "jpeg" | "tif" | "tiff" | "musicxml" | "mxl" | "mscz" | "sib") => AttachmentKind::Score,
+            Some("mp3" | "m4a" | "aac" | "wav" | "flac" | "ogg" | "aif" | "aiff" | "mid" | "midi") => AttachmentKind::Audio,
+            Some("mp4" | "mov" | "m4v" | "mkv" | "webm") => AttachmentKind::Video,
+            _ => AttachmentKind::Other,
+        }
+    }
+}
+
+impl std::str::FromStr for AttachmentKind {
+    type Err = &'static str;
+    fn from_str(s: &str) -> std::result::Result<AttachmentKind, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "score" => Ok(AttachmentKind::Score),
+            "part" => Ok(AttachmentKind::Part),
+            "audio" => Ok(AttachmentKind::Audio),
+            "video" => Ok(AttachmentKind::Video),
+            "other" => Ok(AttachmentKind::Other),
+            _ => Err("Not a valid attachment kind"),
+        }
+    }
+}
+
+impl std::fmt::Display for AttachmentKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            AttachmentKind::Score => "score",
+            AttachmentKind::Part => "part",
+            AttachmentKind::Audio => "audio",
+            AttachmentKind::Video => "video",
+            AttachmentKind::Other => "other",
+        })
+    }
+}
+
+/// A file attached to a piece. The file is either linked by path or stored in the database.
+#[derive(Clone, Debug)]
+pub struct Attachment {
+    id: Option<i32>,
+    music: i32,
+    pub kind: AttachmentKind,
+    pub label: Option<String>,
+    /// Absolute, or relative to the collection's attachment folder for copied files.
+    pub path: Option<String>,
+    embedded: bool,
+}
+
+#[allow(dead_code)]
+impl Attachment {
+    fn from_row(row: &Row) -> Attachment {
+        let kind: String = row.get_unwrap(2);
+        Attachment {
+            id: row.get_unwrap(0),
+            music: row.get_unwrap(1),
+            kind: kind.parse().unwrap_or(AttachmentKind::Other),
+            label: row.get_unwrap(3),
+            path: row.get_unwrap(4),
+            embedded: row.get_unwrap(5),
+        }
+    }
+
+    /// Links the file at `path` to a piece, guessing its kind from the extension.
+    pub fn new<P: AsRef<Path>>(music_id: i32, path: P) -> Attachment {
+        let path = path.as_ref();
+        Attachment {
+            id: None,
+            music: music_id,
+            kind: AttachmentKind::from_path(path),
+            label: None,
+            path: Some(path.to_string_lossy().into_owned()),
+            embedded: false,
+        }
+    }
+
+    pub fn id(&self) -> Option<i32> {
+        self.id
+    }
+
+    pub fn music_id(&self) -> i32 {
+        self.music
+    }
+
+    /// Whether the file's contents are stored in the database rather than linked.
+    pub fn is_embedded(&self) -> bool {
+        self.embedded
+    }
+
+    /// The label if there is one, or else the file name.
+    pub fn name(&self) -> String {
+        if let Some(label) = &self.label {
+            return label.clone();
+        }
+        self.path.as_ref()
+            .and_then(|p| Path::new(p).file_name())
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    pub fn by_id(db: &Database, id: i32) -> Result<Option<Attachment>> {
+        db.query_row("SELECT id, mid, kind, label, path, data IS NOT NULL FROM attachments WHERE id = :id;",
+            named_params!{":id": id}, |row| {
+            Ok(Attachment::from_row(row))
+        })
+    }
+
+    /// Where the linked file is, with copied files looked up in `folder`.
+    pub fn resolve<P: AsRef<Path>>(&self, folder: P) -> Option<PathBuf> {
+        let path = Path::new(self.path.as_ref()?);
+        if path.is_absolute() {
+            Some(path.to_owned())
+        } else {
+            Some(folder.as_ref().join(path))
+        }
+    }
+
+    /// Copies the linked file into `folder`, under a directory for its piece, and links the copy
+    /// instead. The path stored is relative to `folder`, so the folder can move with the
+    /// database. Call [`Attachment::insert`] afterwards to save the new path.
+    pub fn copy_into<P: AsRef<Path>>(&mut self, folder: P) -> Result<()> {
+        let Some(source) = self.path.as_ref().map(PathBuf::from) else {
+            return Ok(());
+        };
+        let dir = PathBuf::from(self.music.to_string());
+        std::fs::create_dir_all(folder.as_ref().join(&dir))?;
+
+        let file_name = source.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or("attachment".into());
+        let mut target = dir.join(&file_name);
+        // Never overwrite another attachment that happens to share the name
+        let mut n = 1;
+        while folder.as_ref().join(&target).exists() {
+            target = dir.join(format!("{}-{}", n, file_name));
+            n += 1;
+        }
+        std::fs::copy(&source, folder.as_ref().join(&target))?;
+        self.path = Some(target.to_string_lossy().into_owned());
+        Ok(())
+    }
+
+    /// Stores `data` as the contents of the attachment, saving it if it isn't already.
+    pub fn embed(&mut self, db: &Database, data: &[u8]) -> Result<()> {
+        db.transaction(|db| {
+            self.insert(db)?;
+            db.execute("UPDATE attachments SET data = :data WHERE id = :id;", named_params!{
+                ":id": self.id,
+                ":data": data,
+            })?;
+            self.embedded = true;
+            Ok(())
+        })
+    }
+
+    /// The stored contents of an embedded attachment.
+    pub fn data(&self, db: &Database) -> Result<Option<Vec<u8>>> {
+        Ok(db.query_row("SELECT data FROM attachments WHERE id = :id;", named_params!{":id": self.id}, |row| {
+            row.get(0)
+        })?.flatten())
+    }
+
+    pub fn insert(&mut self, db: &Database) -> Result<()> {
+        if !self.is_db_entry() {
+            let id = db.insert("INSERT INTO attachments (mid, kind, label, path) VALUES (
+                :mid,
+                :kind,
+                :label,
+                :path
+            );", named_params!{
+                ":mid": self.music,
+                ":kind": self.kind.to_string(),
+                ":label": self.label,
+                ":path": self.path,
+            })?;
+            self.id = Some(id);
+            Ok(())
+        } else {
+            db.execute("UPDATE attachments SET
+                kind = :kind,
+                label = :label,
+                path = :path
+            WHERE id = :id;", named_params!{
+                ":id": self.id,
+                ":kind": self.kind.to_string(),
+                ":label": self.label,
+                ":path": self.path,
+            }).map(|_| ())
+        }
+    }
+
+    /// Removes the attachment from the database. Linked files are left where they are.
+    pub fn delete(self, db: &Database) -> Result<()> {
+        db.execute("DELETE FROM attachments WHERE id = :id;", named_params!{":id": self.id})?;
+        Ok(())
+    }
+
+    pub fn is_db_entry(&self) -> bool {
+        self.id.is_some()
+    }
+}
+
+impl Music {
+    pub fn attachments(&self, db: &Database) -> Result<Option<Vec<Attachment>>> {
+        if self.id.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(db.query("SELECT id, mid, kind, label, path, data IS NOT NULL FROM attachments
+            WHERE mid = :id ORDER BY kind, id;", named_params!{":id": self.id}, |row| {
+            Ok(Attachment::from_row(row))
+        })?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn piece(db: &Database) -> Music {
+        let mut music = Music::new();
+        music.title = "Jerusalem".into();
+        music.insert(db).unwrap();
+        music
+    }
+
+    #[test]
+    fn kinds_are_guessed_from_the_extension() {
+        assert_eq!(AttachmentKind::from_path("Jerusalem.PDF"), AttachmentKind::Score);
+        assert_eq!(AttachmentKind::from_path("rehearsal/alto.mp3"), AttachmentKind::Audio);
+        assert_eq!(AttachmentKind::from_path("notes"), AttachmentKind::Other);
+        for kind in AttachmentKind::ALL {
+            assert_eq!(kind.to_string().parse(), Ok(kind));
+        }
+    }
+
+    #[test]
+    fn embedded_files_are_kept_in_the_database() {
+        let db = Database::open_in_memory().unwrap();
+        let music = piece(&db);
+        let mut linked = Attachment::new(music.id().unwrap(), "/scores/jerusalem.pdf");
+        linked.insert(&db).unwrap();
+        let mut embedded = Attachment::new(music.id().unwrap(), "alto.mp3");
+        embedded.label = Some("Alto rehearsal track".into());
+        embedded.embed(&db, b"not really audio").unwrap();
+
+        assert_eq!(linked.name(), "jerusalem.pdf");
+        assert_eq!(linked.resolve("/library").unwrap(), Path::new("/scores/jerusalem.pdf"));
+        assert_eq!(linked.data(&db).unwrap(), None);
+        let loaded = Attachment::by_id(&db, embedded.id().unwrap()).unwrap().unwrap();
+        assert!(loaded.is_embedded());
+        assert_eq!(loaded.name(), "Alto rehearsal track");
+        assert_eq!(loaded.data(&db).unwrap().unwrap(), b"not really audio");
+
+        let kinds = music.attachments(&db).unwrap().unwrap().into_iter().map(|a| a.kind).collect::<Vec<_>>();
+        assert_eq!(kinds, [AttachmentKind::Audio, AttachmentKind::Score]);
+    }
+
+    #[test]
+    fn copies_never_overwrite_each_other() {
+        let root = std::env::temp_dir().join(format!("bass-attachments-test-{}", std::process::id()));
+        let source = root.join("source.pdf");
+        let folder = root.join("library");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(&source, b"score").unwrap();
+
+        let mut first = Attachment::new(7, &source);
+        first.copy_into(&folder).unwrap();
+        let mut second = Attachment::new(7, &source);
+        second.copy_into(&folder).unwrap();
+
+        assert_eq!(first.path.as_deref(), Some(Path::new("7").join("source.pdf").to_str().unwrap()));
+        assert_eq!(second.path.as_deref(), Some(Path::new("7").join("1-source.pdf").to_str().unwrap()));
+        assert_eq!(std::fs::read(second.resolve(&folder).unwrap()).unwrap(), b"score");
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,214 @@
+use rusqlite::{named_params, Row};
+
+use super::{Database, Music, Person, Result};
+
+/// What someone did for a piece.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContributorRole {
+    Composer,
+    Arranger,
+    Lyricist,
+    Translator,
+    Editor,
+    Orchestrator,
+    Other,
+}
+
+impl ContributorRole {
+    pub const ALL: [ContributorRole; 7] = [
+        ContributorRole::Composer,
+        ContributorRole::Arranger,
+        ContributorRole::Lyricist,
+        ContributorRole::Translator,
+        ContributorRole::Editor,
+        ContributorRole::Orchestrator,
+        ContributorRole::Other,
+    ];
+
+    // The column of `music` holding the piece's main credit in this role, if it has one.
+    pub(super) fn music_column(&self) -> Option<&'static str> {
+        match self {
+            ContributorRole::Composer => Some("composer_id"),
+            ContributorRole::Arranger => Some("arranger_id"),
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for ContributorRole {
+    type Err = &'static str;
+    fn from_str(s: &str) -> std::result::Result<ContributorRole, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "composer" => Ok(ContributorRole::Composer),
+            "arranger" => Ok(ContributorRole::Arranger),
+            "lyricist" => Ok(ContributorRole::Lyricist),
+            "translator" => Ok(ContributorRole::Translator),
+            "editor" => Ok(ContributorRole::Editor),
+            "orchestrator" => Ok(ContributorRole::Orchestrator),
+            "other" => Ok(ContributorRole::Other),
+            _ => Err("Not a valid contributor role"),
+        }
+    }
+}
+
+impl std::fmt::Display for ContributorRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            ContributorRole::Composer => "composer",
+            ContributorRole::Arranger => "arranger",
+            ContributorRole::Lyricist => "lyricist",
+            ContributorRole::Translator => "translator",
+            ContributorRole::Editor => "editor",
+            ContributorRole::Orchestrator => "orchestrator",
+            ContributorRole::Other => "other",
+        })
+    }
+}
+
+/// Someone credited on a piece besides its composer and arranger, or as one of several.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Contributor {
+    person: Option<i32>,
+    pub name: String,
+    pub role: ContributorRole,
+}
+
+#[allow(dead_code)]
+impl Contributor {
+    fn from_row(row: &Row) -> Contributor {
+        let role: String = row.get_unwrap(2);
+        Contributor {
+            person: row.get_unwrap(0),
+            name: row.get_unwrap(1),
+            role: role.parse().unwrap_or(ContributorRole::Other),
+        }
+    }
+
+    pub fn new(name: &str, role: ContributorRole) -> Contributor {
+        Contributor {
+            person: None,
+            name: name.trim().into(),
+            role,
+        }
+    }
+
+    /// The [`Person`] credited, once the contributor has been saved.
+    pub fn person_id(&self) -> Option<i32> {
+        self.person
+    }
+}
+
+impl std::fmt::Display for Contributor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.name, self.role)
+    }
+}
+
+impl Music {
+    /// Everyone credited on the piece besides its composer and arranger, in order.
+    pub fn contributors(&self, db: &Database) -> Result<Option<Vec<Contributor>>> {
+        if self.id.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(db.query("SELECT c.pid, p.name, c.role FROM contributors c
+            INNER JOIN people p ON p.id = c.pid
+            WHERE c.mid = :id ORDER BY c.position;", named_params!{":id": self.id}, |row| {
+            Ok(Contributor::from_row(row))
+        })?))
+    }
+
+    /// Replaces the piece's contributors with `contributors`, in that order. Names are looked up
+    /// among [`Person`]s like the composer's is, and show the person's own name afterwards.
+    /// Contributors without a name are left out.
+    pub fn set_contributors(&self, db: &Database, contributors: &mut [Contributor]) -> Result<()> {
+        db.transaction_restoring(contributors, |db, contributors| {
+            // Credits are rewritten in place so history only records the ones that changed
+            let named = contributors.iter_mut().filter(|c| !c.name.trim().is_empty());
+            let mut count = 0;
+            for (position, contributor) in named.enumerate() {
+                let person = Person::find_or_insert(db, &contributor.name)?;
+                contributor.person = person.id();
+                contributor.name = person.name;
+                db.execute("INSERT INTO contributors (mid, position, pid, role)
+                    VALUES (:mid, :position, :pid, :role)
+                    ON CONFLICT (mid, position) DO UPDATE SET pid = excluded.pid, role = excluded.role
+                    WHERE pid IS NOT excluded.pid OR role IS NOT excluded.role;", named_params!{
+                    ":mid": self.id,
+                    ":position": position,
+                    ":pid": contributor.person,
+                    ":role": contributor.role.to_string(),
+                })?;
+                count = position + 1;
+            }
+            db.execute("DELETE FROM contributors WHERE mid = :id AND position >= :count;",
+                named_params!{":id": self.id, ":count": count})?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn titles(db: &Database, query: &crate::db::MusicQuery) -> Vec<String> {
+        query.run(db).unwrap().into_iter().map(|m| m.title).collect()
+    }
+
+    fn piece(db: &Database, title: &str, composer: &str) -> Music {
+        let mut music = Music::new();
+        music.title = title.into();
+        music.composer = Some(composer.into());
+        music.insert(db).unwrap();
+        music
+    }
+
+    #[test]
+    fn roles_read_back() {
+        for role in ContributorRole::ALL {
+            assert_eq!(role.to_string().parse(), Ok(role));
+        }
+        assert_eq!("Lyricist".parse(), Ok(ContributorRole::Lyricist));
+        assert!("conductor".parse::<ContributorRole>().is_err());
+    }
+
+    #[test]
+    fn contributors_are_kept_in_order() {
+        let db = Database::open_in_memory().unwrap();
+        let music = piece(&db, "Silent Night", "Franz Gruber");
+        let mut credits = [
+            Contributor::new("Joseph Mohr", ContributorRole::Lyricist),
+            Contributor::new("  ", ContributorRole::Editor),
+            Contributor::new("John Freeman Young", ContributorRole::Translator),
+        ];
+        music.set_contributors(&db, &mut credits).unwrap();
+
+        let saved = music.contributors(&db).unwrap().unwrap();
+        assert_eq!(saved.iter().map(|c| c.to_string()).collect::<Vec<_>>(),
+            ["Joseph Mohr (lyricist)", "John Freeman Young (translator)"]);
+        assert!(saved.iter().all(|c| c.person_id().is_some()));
+
+        let mohr = Person::by_name(&db, "Joseph Mohr").unwrap().unwrap();
+        assert_eq!(titles(&db, Music::query().has_role(ContributorRole::Translator)), ["Silent Night"]);
+        assert_eq!(titles(&db, Music::query().credits_as(mohr.id().unwrap(), ContributorRole::Lyricist)), ["Silent Night"]);
+        assert!(titles(&db, Music::query().has_role(ContributorRole::Arranger)).is_empty());
+        assert_eq!(Music::list_role(&db, ContributorRole::Composer).unwrap(), ["Franz Gruber"]);
+
+        music.set_contributors(&db, &mut []).unwrap();
+        assert!(music.contributors(&db).unwrap().unwrap().is_empty());
+    }
+
+    #[test]
+    fn merged_people_keep_their_contributions() {
+        let db = Database::open_in_memory().unwrap();
+        let music = piece(&db, "O Come, All Ye Faithful", "John Francis Wade");
+        music.set_contributors(&db, &mut [Contributor::new("F. Oakeley", ContributorRole::Translator)]).unwrap();
+        let oakeley = Person::find_or_insert(&db, "Frederick Oakeley").unwrap();
+
+        Person::by_name(&db, "F. Oakeley").unwrap().unwrap().merge_into(&db, &oakeley).unwrap();
+        let credits = music.contributors(&db).unwrap().unwrap();
+        assert_eq!(credits[0].person_id(), oakeley.id());
+        assert_eq!(credits[0].name, "Frederick Oakeley");
+        assert_eq!(oakeley.aliases(&db).unwrap(), ["F. Oakeley"]);
+    }
+}
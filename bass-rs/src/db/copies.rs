@@ -0,0 +1,176 @@
+use rusqlite::{named_params, Row};
+
+use super::{Database, Music, Result};
+
+/// Physical copies of one part of a piece, and where they are kept.
+#[derive(Clone, Debug)]
+pub struct MusicCopy {
+    id: Option<i32>,
+    music: i32,
+    /// The part these copies are of, or `None` for complete sets.
+    pub part: Option<String>,
+    pub count: u32,
+    /// Where the copies are stored, like "Cabinet 2, Drawer 3".
+    pub location: Option<String>,
+    pub condition: Option<String>,
+    pub accession: Option<String>,
+}
+
+#[allow(dead_code)]
+impl MusicCopy {
+    fn from_row(row: &Row) -> MusicCopy {
+        MusicCopy {
+            id: row.get_unwrap(0),
+            music: row.get_unwrap(1),
+            part: row.get_unwrap(2),
+            count: row.get_unwrap(3),
+            location: row.get_unwrap(4),
+            condition: row.get_unwrap(5),
+            accession: row.get_unwrap(6),
+        }
+    }
+
+    pub fn new(music_id: i32) -> MusicCopy {
+        MusicCopy {
+            id: None,
+            music: music_id,
+            part: None,
+            count: 1,
+            location: None,
+            condition: None,
+            accession: None,
+        }
+    }
+
+    pub fn id(&self) -> Option<i32> {
+        self.id
+    }
+
+    pub fn music_id(&self) -> i32 {
+        self.music
+    }
+
+    pub fn by_id(db: &Database, id: i32) -> Result<Option<MusicCopy>> {
+        db.query_row("SELECT * FROM copies WHERE id = :id;", named_params!{":id": id}, |row| {
+            Ok(MusicCopy::from_row(row))
+        })
+    }
+
+    pub fn by_accession(db: &Database, accession: &str) -> Result<Option<MusicCopy>> {
+        db.query_row("SELECT * FROM copies WHERE accession = :accession;", named_params!{":accession": accession}, |row| {
+            Ok(MusicCopy::from_row(row))
+        })
+    }
+
+    pub fn list_locations(db: &Database) -> Result<Vec<String>> {
+        db.query("SELECT DISTINCT location FROM copies WHERE location IS NOT NULL ORDER BY location;", (), |row| {
+            row.get(0)
+        })
+    }
+
+    pub fn insert(&mut self, db: &Database) -> Result<()> {
+        if !self.is_db_entry() {
+            let id = db.insert("INSERT INTO copies (mid, part, count, location, condition, accession) VALUES (
+                :mid,
+                :part,
+                :count,
+                :location,
+                :condition,
+                :accession
+            );", named_params!{
+                ":mid": self.music,
+                ":part": self.part,
+                ":count": self.count,
+                ":location": self.location,
+                ":condition": self.condition,
+                ":accession": self.accession,
+            })?;
+            self.id = Some(id);
+            Ok(())
+        } else {
+            db.execute("UPDATE copies SET
+                part = :part,
+                count = :count,
+                location = :location,
+                condition = :condition,
+                accession = :accession
+            WHERE id = :id;", named_params!{
+                ":id": self.id,
+                ":part": self.part,
+                ":count": self.count,
+                ":location": self.location,
+                ":condition": self.condition,
+                ":accession": self.accession,
+            }).map(|_| ())
+        }
+    }
+
+    pub fn delete(self, db: &Database) -> Result<()> {
+        db.execute("DELETE FROM copies WHERE id = :id;", named_params!{":id": self.id})?;
+        Ok(())
+    }
+
+    pub fn is_db_entry(&self) -> bool {
+        self.id.is_some()
+    }
+}
+
+impl Music {
+    pub fn copies(&self, db: &Database) -> Result<Option<Vec<MusicCopy>>> {
+        if self.id.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(db.query("SELECT * FROM copies WHERE mid = :id ORDER BY part, id;", named_params!{":id": self.id}, |row| {
+            Ok(MusicCopy::from_row(row))
+        })?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MusicQuery;
+
+    fn copy(db: &Database, music: &Music, part: Option<&str>, location: &str) -> MusicCopy {
+        let mut copy = MusicCopy::new(music.id().unwrap());
+        copy.part = part.map(|p| p.into());
+        copy.location = Some(location.into());
+        copy.insert(db).unwrap();
+        copy
+    }
+
+    fn titles(db: &Database, query: &MusicQuery) -> Vec<String> {
+        query.run(db).unwrap().into_iter().map(|m| m.title).collect()
+    }
+
+    #[test]
+    fn copies_are_found_by_where_they_are() {
+        let db = Database::open_in_memory().unwrap();
+        let mut carol = Music::new();
+        carol.title = "Sussex Carol".into();
+        carol.insert(&db).unwrap();
+        let mut hymn = Music::new();
+        hymn.title = "Abide with Me".into();
+        hymn.insert(&db).unwrap();
+
+        let mut tenor = copy(&db, &carol, Some("Tenor"), "Cabinet 2, Drawer 3");
+        copy(&db, &carol, None, "Cabinet 1");
+        tenor.count = 12;
+        tenor.accession = Some("B-0117".into());
+        tenor.insert(&db).unwrap();
+
+        let copies = carol.copies(&db).unwrap().unwrap();
+        assert_eq!(copies.iter().map(|c| c.part.as_deref()).collect::<Vec<_>>(), [None, Some("Tenor")]);
+        assert_eq!(MusicCopy::by_accession(&db, "B-0117").unwrap().unwrap().count, 12);
+        assert_eq!(MusicCopy::list_locations(&db).unwrap(), ["Cabinet 1", "Cabinet 2, Drawer 3"]);
+
+        assert_eq!(titles(&db, Music::query().location_contains("Drawer 3")), ["Sussex Carol"]);
+        assert_eq!(titles(&db, Music::query().no_copies()), ["Abide with Me"]);
+        assert_eq!(titles(&db, Music::query().location_not_contains("Cabinet")), ["Abide with Me"]);
+        assert_eq!(titles(&db, Music::query().accession_eq("B-0117")), ["Sussex Carol"]);
+
+        tenor.delete(&db).unwrap();
+        carol.delete_permanently(&db).unwrap();
+        assert!(MusicCopy::list_locations(&db).unwrap().is_empty());
+    }
+}
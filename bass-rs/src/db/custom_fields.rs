@@ -0,0 +1,396 @@
+use rusqlite::{named_params, Row};
+use rusqlite::types::Value;
+
+use super::{is_date, Database, Error, Music, Result};
+
+/// What a [`CustomField`] holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CustomFieldKind {
+    Text,
+    Number,
+    /// A day, as `YYYY-MM-DD`.
+    Date,
+    /// One of the field's [`CustomField::choices`].
+    Choice,
+    Bool,
+}
+
+impl CustomFieldKind {
+    pub const ALL: [CustomFieldKind; 5] = [
+        CustomFieldKind::Text,
+        CustomFieldKind::Number,
+        CustomFieldKind::Date,
+        CustomFieldKind::Choice,
+        CustomFieldKind::Bool,
+    ];
+}
+
+impl std::str::FromStr for CustomFieldKind {
+    type Err = &'static str;
+    fn from_str(s: &str) -> std::result::Result<CustomFieldKind, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(CustomFieldKind::Text),
+            "number" => Ok(CustomFieldKind::Number),
+            "date" => Ok(CustomFieldKind::Date),
+            "choice" | "enum" => Ok(CustomFieldKind::Choice),
+            "yes/no" | "bool" => Ok(CustomFieldKind::Bool),
+            _ => Err("Not a valid custom field kind"),
+        }
+    }
+}
+
+impl std::fmt::Display for CustomFieldKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            CustomFieldKind::Text => "text",
+            CustomFieldKind::Number => "number",
+            CustomFieldKind::Date => "date",
+            CustomFieldKind::Choice => "choice",
+            CustomFieldKind::Bool => "yes/no",
+        })
+    }
+}
+
+/// The value a piece has for a [`CustomField`]. Make one with [`CustomField::parse_value`] so
+/// that it fits the field.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CustomValue {
+    Text(String),
+    Number(f64),
+    Date(String),
+    Choice(String),
+    Bool(bool),
+}
+
+impl CustomValue {
+    pub fn kind(&self) -> CustomFieldKind {
+        match self {
+            CustomValue::Text(_) => CustomFieldKind::Text,
+            CustomValue::Number(_) => CustomFieldKind::Number,
+            CustomValue::Date(_) => CustomFieldKind::Date,
+            CustomValue::Choice(_) => CustomFieldKind::Choice,
+            CustomValue::Bool(_) => CustomFieldKind::Bool,
+        }
+    }
+
+    pub(super) fn to_sql(&self) -> Value {
+        match self {
+            CustomValue::Text(s) | CustomValue::Date(s) | CustomValue::Choice(s) => Value::Text(s.clone()),
+            CustomValue::Number(n) => Value::Real(*n),
+            CustomValue::Bool(b) => Value::Integer((*b).into()),
+        }
+    }
+
+    fn from_sql(kind: CustomFieldKind, value: Value) -> Option<CustomValue> {
+        match (kind, value) {
+            (CustomFieldKind::Text, Value::Text(s)) => Some(CustomValue::Text(s)),
+            (CustomFieldKind::Date, Value::Text(s)) => Some(CustomValue::Date(s)),
+            (CustomFieldKind::Choice, Value::Text(s)) => Some(CustomValue::Choice(s)),
+            (CustomFieldKind::Number, Value::Real(n)) => Some(CustomValue::Number(n)),
+            (CustomFieldKind::Number, Value::Integer(n)) => Some(CustomValue::Number(n as f64)),
+            (CustomFieldKind::Bool, Value::Integer(n)) => Some(CustomValue::Bool(n != 0)),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for CustomValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CustomValue::Text(s) | CustomValue::Date(s) | CustomValue::Choice(s) => write!(f, "{}", s),
+            CustomValue::Number(n) => write!(f, "{}", n),
+            CustomValue::Bool(b) => write!(f, "{}", if *b {"yes"} else {"no"}),
+        }
+    }
+}
+
+/// A field a library added for itself, like "Folder number" or "Needs rebinding".
+#[derive(Clone, Debug, PartialEq)]
+pub struct CustomField {
+    id: Option<i32>,
+    /// Unique, ignoring case. Can't contain ":", which the search syntax puts after it.
+    pub name: String,
+    pub kind: CustomFieldKind,
+    /// The values a [`CustomFieldKind::Choice`] field allows, in order.
+    pub choices: Vec<String>,
+}
+
+#[allow(dead_code)]
+impl CustomField {
+    fn from_row(row: &Row) -> CustomField {
+        let kind: String = row.get_unwrap(2);
+        let choices: Option<String> = row.get_unwrap(3);
+        CustomField {
+            id: row.get_unwrap(0),
+            name: row.get_unwrap(1),
+            kind: kind.parse().unwrap_or(CustomFieldKind::Text),
+            choices: choices.map(|c| c.lines().map(String::from).collect()).unwrap_or_default(),
+        }
+    }
+
+    pub fn new(name: &str, kind: CustomFieldKind) -> CustomField {
+        CustomField {
+            id: None,
+            name: name.trim().into(),
+            kind,
+            choices: Vec::new(),
+        }
+    }
+
+    pub fn id(&self) -> Option<i32> {
+        self.id
+    }
+
+    /// Every field, in the order they were added.
+    pub fn list_all(db: &Database) -> Result<Vec<CustomField>> {
+        db.query("SELECT * FROM custom_fields ORDER BY id;", (), |row| {
+            Ok(CustomField::from_row(row))
+        })
+    }
+
+    /// Every field, in the order they were added, with how many pieces have a value for it.
+    /// Pieces in the trash don't count.
+    pub fn usage_counts(db: &Database) -> Result<Vec<(CustomField, usize)>> {
+        db.query("SELECT f.*, (SELECT count(*) FROM custom_values v
+                INNER JOIN music m ON m.id = v.mid
+                WHERE v.fid = f.id AND m.deleted_at IS NULL)
+            FROM custom_fields f ORDER BY f.id;", (), |row| {
+                Ok((CustomField::from_row(row), row.get::<_, i64>(4)? as usize))
+            })
+    }
+
+    pub fn by_id(db: &Database, id: i32) -> Result<Option<CustomField>> {
+        db.query_row("SELECT * FROM custom_fields WHERE id = :id;", named_params!{":id": id}, |row| {
+            Ok(CustomField::from_row(row))
+        })
+    }
+
+    /// Finds the field called `name`, ignoring case.
+    pub fn by_name(db: &Database, name: &str) -> Result<Option<CustomField>> {
+        db.query_row("SELECT * FROM custom_fields WHERE name = :name;", named_params!{":name": name.trim()}, |row| {
+            Ok(CustomField::from_row(row))
+        })
+    }
+
+    /// Reads `s` as a value of this field. Numbers are plain decimals, dates are `YYYY-MM-DD`,
+    /// choices have to be one of [`CustomField::choices`] (ignoring case), and yes/no fields take
+    /// yes, no, true, false, 1 or 0.
+    pub fn parse_value(&self, s: &str) -> std::result::Result<CustomValue, &'static str> {
+        let s = s.trim();
+        match self.kind {
+            _ if s.is_empty() => Err("No value given"),
+            CustomFieldKind::Text => Ok(CustomValue::Text(s.into())),
+            CustomFieldKind::Number => s.parse().ok()
+                .filter(|n: &f64| n.is_finite())
+                .map(CustomValue::Number)
+                .ok_or("Not a number"),
+            CustomFieldKind::Date if is_date(s) => Ok(CustomValue::Date(s.into())),
+            CustomFieldKind::Date => Err("Not a date, like 2024-05-31"),
+            CustomFieldKind::Choice => self.choices.iter()
+                .find(|c| c.eq_ignore_ascii_case(s))
+                .map(|c| CustomValue::Choice(c.clone()))
+                .ok_or("Not one of the field's choices"),
+            CustomFieldKind::Bool => match s.to_ascii_lowercase().as_str() {
+                "yes" | "true" | "1" => Ok(CustomValue::Bool(true)),
+                "no" | "false" | "0" => Ok(CustomValue::Bool(false)),
+                _ => Err("Not yes or no"),
+            },
+        }
+    }
+
+    /// Saves the field. If its kind or choices changed, values that still make sense are
+    /// converted and the rest are dropped.
+    pub fn insert(&mut self, db: &Database) -> Result<()> {
+        self.name = self.name.trim().into();
+        if self.name.is_empty() || self.name.contains(':') {
+            return Err(Error::InvalidFieldName(self.name.clone()));
+        }
+        self.choices = self.choices.iter()
+            .map(|c| c.trim().to_string())
+            .filter(|c| !c.is_empty())
+            .collect();
+        let choices = (!self.choices.is_empty()).then(|| self.choices.join("\n"));
+        if !self.is_db_entry() {
+            let id = db.insert("INSERT INTO custom_fields (name, kind, choices) VALUES (:name, :kind, :choices);", named_params!{
+                ":name": self.name,
+                ":kind": self.kind.to_string(),
+                ":choices": choices,
+            })?;
+            self.id = Some(id);
+            Ok(())
+        } else {
+            db.transaction(|db| {
+                db.execute("UPDATE custom_fields SET name = :name, kind = :kind, choices = :choices WHERE id = :id;", named_params!{
+                    ":id": self.id,
+                    ":name": self.name,
+                    ":kind": self.kind.to_string(),
+                    ":choices": choices,
+                })?;
+                let values = db.query("SELECT mid, value FROM custom_values WHERE fid = :id;", named_params!{":id": self.id}, |row| {
+                    Ok((row.get::<_, i32>(0)?, row.get::<_, Value>(1)?))
+                })?;
+                for (mid, value) in values {
+                    let text = match value {
+                        Value::Text(s) => s,
+                        Value::Real(n) => n.to_string(),
+                        Value::Integer(n) => n.to_string(),
+                        _ => String::new(),
+                    };
+                    match self.parse_value(&text) {
+                        Ok(value) => db.execute("UPDATE custom_values SET value = :value WHERE mid = :mid AND fid = :id;",
+                            named_params!{":mid": mid, ":id": self.id, ":value": value.to_sql()})?,
+                        Err(_) => db.execute("DELETE FROM custom_values WHERE mid = :mid AND fid = :id;",
+                            named_params!{":mid": mid, ":id": self.id})?,
+                    };
+                }
+                Ok(())
+            })
+        }
+    }
+
+    /// Removes the field, and its value from every piece.
+    pub fn delete(self, db: &Database) -> Result<()> {
+        db.execute("DELETE FROM custom_fields WHERE id = :id;", named_params!{":id": self.id})?;
+        Ok(())
+    }
+
+    pub fn is_db_entry(&self) -> bool {
+        self.id.is_some()
+    }
+}
+
+impl Music {
+    /// The piece's values for custom fields, in the order the fields were added. Fields the
+    /// piece has no value for are left out.
+    pub fn custom_values(&self, db: &Database) -> Result<Option<Vec<(CustomField, CustomValue)>>> {
+        if self.id.is_none() {
+            return Ok(None);
+        }
+        let values = db.query("SELECT f.*, v.value FROM custom_values v
+            INNER JOIN custom_fields f ON f.id = v.fid
+            WHERE v.mid = :id ORDER BY f.id;", named_params!{":id": self.id}, |row| {
+                let field = CustomField::from_row(row);
+                let value = CustomValue::from_sql(field.kind, row.get(4)?);
+                Ok(value.map(|v| (field, v)))
+            })?;
+        Ok(Some(values.into_iter().flatten().collect()))
+    }
+
+    /// Sets the piece's value for `field`, or clears it if `value` is `None`.
+    pub fn set_custom_value(&self, db: &Database, field: &CustomField, value: Option<&CustomValue>) -> Result<()> {
+        match value {
+            Some(value) if value.kind() != field.kind => Err(Error::InvalidCustomValue {
+                field: field.name.clone(),
+                value: value.to_string(),
+            }),
+            Some(value) => {
+                db.execute("INSERT INTO custom_values (mid, fid, value) VALUES (:mid, :fid, :value)
+                    ON CONFLICT (mid, fid) DO UPDATE SET value = excluded.value;", named_params!{
+                    ":mid": self.id,
+                    ":fid": field.id,
+                    ":value": value.to_sql(),
+                })?;
+                Ok(())
+            }
+            None => {
+                db.execute("DELETE FROM custom_values WHERE mid = :mid AND fid = :fid;",
+                    named_params!{":mid": self.id, ":fid": field.id})?;
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(db: &Database, name: &str, kind: CustomFieldKind) -> CustomField {
+        let mut field = CustomField::new(name, kind);
+        field.insert(db).unwrap();
+        field
+    }
+
+    fn piece(db: &Database, title: &str) -> Music {
+        let mut music = Music::new();
+        music.title = title.into();
+        music.insert(db).unwrap();
+        music
+    }
+
+    fn titles(db: &Database, query: &crate::db::MusicQuery) -> Vec<String> {
+        let mut titles: Vec<_> = query.run(db).unwrap().into_iter().map(|m| m.title).collect();
+        titles.sort();
+        titles
+    }
+
+    #[test]
+    fn values_are_read_by_kind() {
+        let mut condition = CustomField::new("Condition", CustomFieldKind::Choice);
+        condition.choices = vec!["Good".into(), "Worn".into()];
+        assert_eq!(condition.parse_value("worn"), Ok(CustomValue::Choice("Worn".into())));
+        assert!(condition.parse_value("Torn").is_err());
+        let number = CustomField::new("Folder", CustomFieldKind::Number);
+        assert_eq!(number.parse_value(" 12.5 "), Ok(CustomValue::Number(12.5)));
+        assert!(number.parse_value("inf").is_err());
+        let date = CustomField::new("Acquired", CustomFieldKind::Date);
+        assert!(date.parse_value("2024-02-29").is_ok());
+        assert!(date.parse_value("2024-13-01").is_err());
+        let flag = CustomField::new("Needs rebinding", CustomFieldKind::Bool);
+        assert_eq!(flag.parse_value("Yes"), Ok(CustomValue::Bool(true)));
+        assert!(flag.parse_value("").is_err());
+    }
+
+    #[test]
+    fn values_are_saved_and_searchable() {
+        let db = Database::open_in_memory().unwrap();
+        let folder = field(&db, "Folder", CustomFieldKind::Number);
+        let rebind = field(&db, "Needs rebinding", CustomFieldKind::Bool);
+        let messiah = piece(&db, "Messiah");
+        let gloria = piece(&db, "Gloria");
+        messiah.set_custom_value(&db, &folder, Some(&CustomValue::Number(12.0))).unwrap();
+        messiah.set_custom_value(&db, &rebind, Some(&CustomValue::Bool(true))).unwrap();
+        gloria.set_custom_value(&db, &folder, Some(&CustomValue::Number(3.0))).unwrap();
+        assert!(matches!(gloria.set_custom_value(&db, &rebind, Some(&CustomValue::Text("yes".into()))),
+            Err(Error::InvalidCustomValue { .. })));
+
+        let values = messiah.custom_values(&db).unwrap().unwrap();
+        assert_eq!(values.iter().map(|(f, v)| format!("{}: {}", f.name, v)).collect::<Vec<_>>(),
+            ["Folder: 12", "Needs rebinding: yes"]);
+        assert_eq!(titles(&db, Music::query().custom_gt(&folder, &CustomValue::Number(5.0))), ["Messiah"]);
+        assert_eq!(titles(&db, Music::query().null_custom(&rebind)), ["Gloria"]);
+        assert_eq!(CustomField::usage_counts(&db).unwrap().into_iter().map(|(_, n)| n).collect::<Vec<_>>(), [2, 1]);
+
+        messiah.set_custom_value(&db, &rebind, None).unwrap();
+        assert_eq!(messiah.custom_values(&db).unwrap().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn changing_a_fields_kind_keeps_the_values_that_still_fit() {
+        let db = Database::open_in_memory().unwrap();
+        let mut shelf = field(&db, "Shelf", CustomFieldKind::Text);
+        let first = piece(&db, "Jerusalem");
+        let second = piece(&db, "Zadok the Priest");
+        first.set_custom_value(&db, &shelf, Some(&CustomValue::Text("7".into()))).unwrap();
+        second.set_custom_value(&db, &shelf, Some(&CustomValue::Text("top".into()))).unwrap();
+
+        shelf.kind = CustomFieldKind::Number;
+        shelf.insert(&db).unwrap();
+        assert_eq!(first.custom_values(&db).unwrap().unwrap()[0].1, CustomValue::Number(7.0));
+        assert!(second.custom_values(&db).unwrap().unwrap().is_empty());
+
+        shelf.name = "Shelf: top".into();
+        assert!(matches!(shelf.insert(&db), Err(Error::InvalidFieldName(_))));
+    }
+
+    #[test]
+    fn dates_must_exist() {
+        let field = CustomField::new("Acquired", CustomFieldKind::Date);
+        assert_eq!(field.parse_value("2024-02-29"), Ok(CustomValue::Date("2024-02-29".into())));
+        assert!(field.parse_value("2023-02-29").is_err());
+        assert!(field.parse_value("2024-02-31").is_err());
+        assert!(field.parse_value("2024-04-31").is_err());
+        assert!(field.parse_value("2024-13-01").is_err());
+        assert!(field.parse_value("2024-1-01").is_err());
+    }
+}
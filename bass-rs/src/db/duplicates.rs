@@ -0,0 +1,359 @@
+use rusqlite::named_params;
+
+use super::{Database, Music, Result};
+
+/// How alike two pieces have to be before they're reported as likely duplicates.
+pub const DUPLICATE_THRESHOLD: f64 = 0.85;
+
+/// Two pieces that look like the same piece entered twice.
+#[derive(Clone, Debug)]
+pub struct DuplicateCandidate {
+    pub first: Music,
+    pub second: Music,
+    /// From 0 (nothing alike) to 1 (identical once normalized).
+    pub score: f64,
+}
+
+// Relative weight of each field in the overall score. Fields empty on either side don't count.
+const TITLE_WEIGHT: f64 = 3.0;
+const COMPOSER_WEIGHT: f64 = 2.0;
+const ARRANGER_WEIGHT: f64 = 1.0;
+const SOURCE_WEIGHT: f64 = 1.0;
+
+// Pieces whose titles are less alike than this are never duplicates, whatever else matches.
+const MIN_TITLE_SIMILARITY: f64 = 0.6;
+
+fn fold_char(c: char) -> &'static str {
+    match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' => "a",
+        'æ' => "ae",
+        'ç' | 'ć' | 'č' => "c",
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ě' => "e",
+        'ì' | 'í' | 'î' | 'ï' | 'ī' => "i",
+        'ñ' | 'ń' | 'ň' => "n",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' => "o",
+        'œ' => "oe",
+        'ř' => "r",
+        'ś' | 'š' => "s",
+        'ß' => "ss",
+        'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ů' => "u",
+        'ý' | 'ÿ' => "y",
+        'ź' | 'ż' | 'ž' => "z",
+        _ => "",
+    }
+}
+
+// Lowercases, folds accents, turns punctuation into spaces and drops a leading article, so
+// "The Stars & Stripes Forever!" and "stars and stripes forever" come out nearly the same.
+pub(super) fn normalize(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars().flat_map(char::to_lowercase) {
+        if c.is_ascii_alphanumeric() {
+            out.push(c);
+        } else if c == '&' {
+            out.push_str(" and ");
+        } else if !fold_char(c).is_empty() {
+            out.push_str(fold_char(c));
+        } else if c.is_alphanumeric() {
+            out.push(c);
+        } else {
+            out.push(' ');
+        }
+    }
+    let words: Vec<&str> = out.split_whitespace().collect();
+    let words = match words.first() {
+        Some(&"the" | &"a" | &"an") if words.len() > 1 => &words[1..],
+        _ => &words[..],
+    };
+    words.join(" ")
+}
+
+// The same words in alphabetical order, so that "Bach, J. S." matches "J. S. Bach".
+fn sort_words(s: &str) -> String {
+    let mut words: Vec<&str> = s.split_whitespace().collect();
+    words.sort_unstable();
+    words.join(" ")
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == cb {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+fn ratio(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    1.0 - levenshtein(&a, &b) as f64 / longest as f64
+}
+
+// How alike two normalized names are. Names are often written with just a surname or with
+// initials, so a name whose words all appear in the other, or are initials of words in it,
+// counts as a near match: "bach j s" and "johann sebastian bach".
+pub(super) fn name_similarity(a: &str, b: &str) -> f64 {
+    let exact = ratio(a, b).max(ratio(&sort_words(a), &sort_words(b)));
+    let (shorter, longer) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    let longer: Vec<&str> = longer.split_whitespace().collect();
+    let contained = shorter.split_whitespace().all(|w| {
+        longer.iter().any(|l| *l == w || (w.len() == 1 && l.starts_with(w)))
+    });
+    if contained { exact.max(0.9) } else { exact }
+}
+
+// Normalized fields of a piece, worked out once rather than for every pair it's part of.
+struct Fingerprint {
+    title: String,
+    title_sorted: String,
+    numbers: Vec<String>,
+    composer: Option<String>,
+    arranger: Option<String>,
+    source: Option<String>,
+}
+
+impl Fingerprint {
+    fn of(m: &Music) -> Fingerprint {
+        let field = |s: Option<&str>| s.map(normalize).filter(|s| !s.is_empty());
+        let title = normalize(&m.title);
+        let mut numbers: Vec<String> = title.split_whitespace()
+            .filter(|w| w.chars().all(|c| c.is_ascii_digit()))
+            .map(|w| w.trim_start_matches('0').into())
+            .collect();
+        numbers.sort_unstable();
+        Fingerprint {
+            title_sorted: sort_words(&title),
+            numbers,
+            title,
+            composer: field(m.composer.as_deref()),
+            arranger: field(m.arranger.as_deref()),
+            source: field(Some(&m.source)),
+        }
+    }
+
+    fn title_similarity(&self, other: &Fingerprint) -> f64 {
+        ratio(&self.title, &other.title).max(ratio(&self.title_sorted, &other.title_sorted))
+    }
+
+    fn similarity(&self, other: &Fingerprint) -> f64 {
+        // Symphony No. 5 and Symphony No. 9 are as alike as titles get, but never the same piece
+        if !self.numbers.is_empty() && !other.numbers.is_empty() && self.numbers != other.numbers {
+            return 0.0;
+        }
+        let title = self.title_similarity(other);
+        if title < MIN_TITLE_SIMILARITY {
+            return 0.0;
+        }
+        let mut total = title * TITLE_WEIGHT;
+        let mut weights = TITLE_WEIGHT;
+        let fields = [
+            (&self.composer, &other.composer, COMPOSER_WEIGHT),
+            (&self.arranger, &other.arranger, ARRANGER_WEIGHT),
+            (&self.source, &other.source, SOURCE_WEIGHT),
+        ];
+        for (a, b, weight) in fields {
+            if let (Some(a), Some(b)) = (a, b) {
+                total += name_similarity(a, b) * weight;
+                weights += weight;
+            }
+        }
+        total / weights
+    }
+}
+
+#[allow(dead_code)]
+impl Music {
+    /// How alike this piece is to `other`, from 0 to 1, comparing title, composer, arranger and
+    /// source after normalizing case, accents and punctuation.
+    pub fn similarity(&self, other: &Music) -> f64 {
+        Fingerprint::of(self).similarity(&Fingerprint::of(other))
+    }
+
+    /// Pieces already in the library that score at least `threshold` against this one, most
+    /// alike first. Works for pieces that haven't been saved yet.
+    pub fn likely_duplicates(&self, db: &Database, threshold: f64) -> Result<Vec<(Music, f64)>> {
+        let fingerprint = Fingerprint::of(self);
+        let mut found: Vec<(Music, f64)> = Music::list_all(db)?.into_iter()
+            .filter(|m| self.id.is_none() || m.id != self.id)
+            .filter_map(|m| {
+                let score = fingerprint.similarity(&Fingerprint::of(&m));
+                (score >= threshold).then_some((m, score))
+            })
+            .collect();
+        found.sort_by(|a, b| b.1.total_cmp(&a.1));
+        Ok(found)
+    }
+
+    /// Every pair of pieces in the library that score at least `threshold` against each other,
+    /// most alike first.
+    pub fn find_duplicates(db: &Database, threshold: f64) -> Result<Vec<DuplicateCandidate>> {
+        let music = Music::list_all(db)?;
+        let fingerprints: Vec<Fingerprint> = music.iter().map(Fingerprint::of).collect();
+        let mut found = Vec::new();
+        for i in 0..music.len() {
+            for j in i + 1..music.len() {
+                let score = fingerprints[i].similarity(&fingerprints[j]);
+                if score >= threshold {
+                    found.push(DuplicateCandidate {
+                        first: music[i].clone(),
+                        second: music[j].clone(),
+                        score,
+                    });
+                }
+            }
+        }
+        found.sort_by(|a, b| b.score.total_cmp(&a.score));
+        Ok(found)
+    }
+
+    /// Folds `other` into this piece: its keywords and contributors are added to this one, its copies, loans,
+    /// performances, program slots and attachments move over, and any field, part range or custom
+    /// field this piece leaves empty is taken from it. `other` then goes to the trash.
+    pub fn merge(&mut self, db: &Database, other: Music) -> Result<()> {
+        if !self.is_db_entry() || !other.is_db_entry() || self.id == other.id {
+            return Ok(());
+        }
+        db.transaction(|db| {
+            let params = named_params!{":keep": self.id, ":other": other.id};
+            db.execute("INSERT INTO music_keywords (mid, kid)
+                SELECT :keep, kid FROM music_keywords WHERE mid = :other
+                ON CONFLICT DO NOTHING;", params)?;
+            // Credits this piece lacks go after its own
+            db.execute("INSERT INTO contributors (mid, position, pid, role)
+                SELECT :keep, o.position + (SELECT ifnull(max(position) + 1, 0) FROM contributors WHERE mid = :keep), o.pid, o.role
+                FROM contributors o WHERE o.mid = :other AND NOT EXISTS (SELECT 1 FROM contributors c
+                    WHERE c.mid = :keep AND c.pid = o.pid AND c.role = o.role);", params)?;
+            db.execute("INSERT INTO part_ranges (mid, part, range, range_low, range_high)
+                SELECT :keep, part, range, range_low, range_high FROM part_ranges WHERE mid = :other
+                ON CONFLICT DO NOTHING;", params)?;
+            db.execute("INSERT INTO custom_values (mid, fid, value)
+                SELECT :keep, fid, value FROM custom_values WHERE mid = :other
+                ON CONFLICT DO NOTHING;", params)?;
+            for table in ["copies", "performance_music", "program_items", "attachments"] {
+                db.execute(&format!("UPDATE {} SET mid = :keep WHERE mid = :other;", table), params)?;
+            }
+
+            if self.composer.is_none() {
+                self.composer = other.composer.clone();
+            }
+            if self.arranger.is_none() {
+                self.arranger = other.arranger.clone();
+            }
+            if self.notes.is_none() {
+                self.notes = other.notes.clone();
+            }
+            if self.runtime.is_none() {
+                self.runtime = other.runtime;
+            }
+            if self.key.is_none() {
+                self.key = other.key;
+            }
+            if self.range.is_none() {
+                self.range = other.range;
+            }
+            if self.voicing.is_none() {
+                self.voicing = other.voicing.clone();
+            }
+            if self.grade.is_none() {
+                self.grade = other.grade;
+            }
+            if self.publisher.is_none() {
+                self.publisher = other.publisher.clone();
+            }
+            if self.catalog_number.is_none() {
+                self.catalog_number = other.catalog_number.clone();
+            }
+            if self.ismn.is_none() {
+                self.ismn = other.ismn.clone();
+            }
+            if self.copyright_year.is_none() {
+                self.copyright_year = other.copyright_year;
+            }
+            if self.language.is_none() {
+                self.language = other.language.clone();
+            }
+            self.insert(db)?;
+            other.delete(db)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn piece(db: &Database, title: &str, composer: Option<&str>, keys: &str) -> Music {
+        let mut music = Music::new();
+        music.title = title.into();
+        music.composer = composer.map(|c| c.into());
+        let mut keys: Vec<_> = keys.split(';').filter(|k| !k.is_empty()).map(|k| k.parse().unwrap()).collect();
+        music.insert_with_keywords(db, &mut keys).unwrap();
+        music
+    }
+
+    #[test]
+    fn names_are_compared_after_normalizing() {
+        assert_eq!(normalize("Bach, J. S."), "bach j s");
+        assert_eq!(normalize("The Stars & Stripes Forever!"), "stars and stripes forever");
+        assert_eq!(normalize("Dvořák"), "dvorak");
+        assert!(name_similarity(&normalize("Bach, J. S."), &normalize("Johann Sebastian Bach")) >= 0.9);
+        assert!(name_similarity("bach", "johann sebastian bach") >= 0.9);
+        assert!(name_similarity("bach j s", "brahms johannes") < 0.5);
+    }
+
+    #[test]
+    fn ratios_count_edits() {
+        assert_eq!(ratio("", ""), 1.0);
+        assert_eq!(ratio("kitten", "kitten"), 1.0);
+        // Three edits: k→s, e→i and an added g
+        assert_eq!(ratio("kitten", "sitting"), 1.0 - 3.0 / 7.0);
+        assert_eq!(ratio("abc", "xyz"), 0.0);
+    }
+
+    #[test]
+    fn numbered_pieces_are_never_duplicates_of_each_other() {
+        let fifth = Music { title: "Symphony No. 5".into(), composer: Some("Beethoven".into()), ..Music::new() };
+        let ninth = Music { title: "Symphony No. 9".into(), ..fifth.clone() };
+        let again = Music { title: "Symphony no 5".into(), composer: Some("L. van Beethoven".into()), ..Music::new() };
+        assert_eq!(fifth.similarity(&ninth), 0.0);
+        assert!(fifth.similarity(&again) >= DUPLICATE_THRESHOLD);
+        assert_eq!(fifth.similarity(&fifth), 1.0);
+    }
+
+    #[test]
+    fn merged_pieces_keep_everything() {
+        let db = Database::open_in_memory().unwrap();
+        let mut kept = piece(&db, "Ave Verum Corpus", None, "latin;Easter");
+        let mut other = piece(&db, "Ave verum corpus", Some("Mozart"), "latin;communion");
+        other.runtime = Some(180);
+        other.insert(&db).unwrap();
+        piece(&db, "Ave Maria", Some("Mozart"), "");
+
+        let found = Music::find_duplicates(&db, DUPLICATE_THRESHOLD).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(kept.likely_duplicates(&db, DUPLICATE_THRESHOLD).unwrap()[0].0.id(), other.id());
+
+        kept.merge(&db, other.clone()).unwrap();
+        let merged = Music::by_id(&db, kept.id().unwrap()).unwrap().unwrap();
+        assert_eq!(merged.composer.as_deref(), Some("Mozart"));
+        assert_eq!(merged.runtime, Some(180));
+        let mut keys: Vec<String> = merged.keywords(&db).unwrap().unwrap().iter().map(|k| k.to_string()).collect();
+        keys.sort();
+        assert_eq!(keys, ["Easter", "communion", "latin"]);
+        assert!(Music::by_id(&db, other.id().unwrap()).unwrap().unwrap().is_trashed());
+    }
+}
@@ -0,0 +1,472 @@
+use rusqlite::{named_params, Row};
+use rusqlite::types::Value;
+
+use super::{ContributorRole, CustomField, Database, Error, Keyword, Music, PartRange, Person, Result, format_runtime};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeTarget {
+    Music,
+    Keyword,
+    Copy,
+    Contributor,
+    PartRange,
+    CustomValue,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeAction {
+    Insert,
+    Update,
+    Delete,
+}
+
+// Columns of `music` that updates are recorded for. Anything else in `field` is refused, since
+// it ends up in the SQL that undoes the change.
+const MUSIC_FIELDS: [&str; 16] = ["title", "source", "composer", "arranger", "notes", "runtime", "deleted_at", "key", "range",
+    "voicing", "grade", "publisher", "catalog_number", "ismn", "copyright_year", "language"];
+
+// After the recorded columns: what a row-level change is about, and how its old and new values
+// read for the rows that keep more than one.
+const CHANGE_COLUMNS: &str = "id, changed_at, mid, target, action, field, ref, old_value, new_value,
+    CASE target
+        WHEN 'copy' THEN json_extract(coalesce(new_value, old_value), '$.part')
+        WHEN 'part_range' THEN json_extract(coalesce(new_value, old_value), '$.part')
+        WHEN 'custom_value' THEN json_extract(coalesce(new_value, old_value), '$.field')
+    END,
+    CASE target
+        WHEN 'contributor' THEN json_extract(old_value, '$.name') || ' (' || json_extract(old_value, '$.role') || ')'
+        WHEN 'part_range' THEN json_extract(old_value, '$.range')
+        WHEN 'custom_value' THEN json_extract(old_value, '$.shown')
+    END,
+    CASE target
+        WHEN 'contributor' THEN json_extract(new_value, '$.name') || ' (' || json_extract(new_value, '$.role') || ')'
+        WHEN 'part_range' THEN json_extract(new_value, '$.range')
+        WHEN 'custom_value' THEN json_extract(new_value, '$.shown')
+    END";
+
+/// One recorded change to a piece or to one of its keyword links, copies, credits, part ranges or
+/// custom values.
+#[derive(Clone, Debug)]
+pub struct Change {
+    id: i32,
+    /// UTC, as `YYYY-MM-DD HH:MM:SS`.
+    pub changed_at: String,
+    music: i32,
+    pub target: ChangeTarget,
+    pub action: ChangeAction,
+    /// The column of `music` that changed, for updates to a piece.
+    pub field: Option<String>,
+    reference: Option<i32>,
+    /// Single values for pieces and keywords, whole rows as JSON for everything else and for
+    /// deleted pieces.
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    // The part of a copy or range, or the name of a custom field.
+    subject: Option<String>,
+    old_shown: Option<String>,
+    new_shown: Option<String>,
+}
+
+fn value_to_string(value: Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::Integer(i) => Some(i.to_string()),
+        Value::Real(f) => Some(f.to_string()),
+        Value::Text(s) => Some(s),
+        Value::Blob(_) => Some("<binary>".into()),
+    }
+}
+
+#[allow(dead_code)]
+impl Change {
+    fn from_row(row: &Row) -> Change {
+        let target: String = row.get_unwrap(3);
+        let action: String = row.get_unwrap(4);
+        Change {
+            id: row.get_unwrap(0),
+            changed_at: row.get_unwrap(1),
+            music: row.get_unwrap(2),
+            target: match target.as_str() {
+                "keyword" => ChangeTarget::Keyword,
+                "copy" => ChangeTarget::Copy,
+                "contributor" => ChangeTarget::Contributor,
+                "part_range" => ChangeTarget::PartRange,
+                "custom_value" => ChangeTarget::CustomValue,
+                _ => ChangeTarget::Music,
+            },
+            action: match action.as_str() {
+                "insert" => ChangeAction::Insert,
+                "delete" => ChangeAction::Delete,
+                _ => ChangeAction::Update,
+            },
+            field: row.get_unwrap(5),
+            reference: row.get_unwrap(6),
+            old_value: value_to_string(row.get_unwrap(7)),
+            new_value: value_to_string(row.get_unwrap(8)),
+            subject: value_to_string(row.get_unwrap(9)),
+            old_shown: value_to_string(row.get_unwrap(10)),
+            new_shown: value_to_string(row.get_unwrap(11)),
+        }
+    }
+
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn music_id(&self) -> i32 {
+        self.music
+    }
+
+    pub fn by_id(db: &Database, id: i32) -> Result<Option<Change>> {
+        db.query_row(&format!("SELECT {} FROM history WHERE id = :id;", CHANGE_COLUMNS), named_params!{":id": id}, |row| {
+            Ok(Change::from_row(row))
+        })
+    }
+
+    /// The most recent changes across the whole library, newest first.
+    pub fn recent(db: &Database, limit: u32) -> Result<Vec<Change>> {
+        db.query(&format!("SELECT {} FROM history ORDER BY id DESC LIMIT :limit;", CHANGE_COLUMNS),
+            named_params!{":limit": limit}, |row| {
+            Ok(Change::from_row(row))
+        })
+    }
+
+    /// Reverses this change, which is itself recorded as a new change.
+    ///
+    /// Undoing the creation of a piece moves it to the trash. Undoing its permanent deletion also
+    /// restores the keyword links, copies, credits, part ranges and custom values deleted along
+    /// with it, and fails with [`Error::CannotUndo`] if it took loans, performances, program slots
+    /// or attachments, since those aren't recorded.
+    pub fn undo(&self, db: &Database) -> Result<()> {
+        db.transaction(|db| {
+            let params = named_params!{":id": self.id, ":mid": self.music, ":ref": self.reference};
+            match (self.target, self.action) {
+                (ChangeTarget::Music, ChangeAction::Insert) => {
+                    Music::new_with_id(self.music).delete(db)?;
+                }
+                (ChangeTarget::Music, ChangeAction::Update) => {
+                    let field = self.field.as_deref().filter(|f| MUSIC_FIELDS.contains(f))
+                        .ok_or(Error::CannotUndo(self.id))?;
+                    db.execute(&format!("UPDATE music SET {} = (SELECT old_value FROM history WHERE id = :id)
+                        WHERE id = :mid;", field), named_params!{":id": self.id, ":mid": self.music})?;
+                    if matches!(field, "composer" | "arranger" | "key" | "range") {
+                        Change::resave(db, self.music)?;
+                    }
+                }
+                (ChangeTarget::Music, ChangeAction::Delete) => {
+                    if self.recorded(db, "$.lost")?.as_deref() == Some("1") {
+                        return Err(Error::CannotUndo(self.id));
+                    }
+                    db.execute("INSERT INTO music (id, title, source, composer, arranger, notes, runtime, key, range,
+                            voicing, grade, publisher, catalog_number, ismn, copyright_year, language)
+                        SELECT json_extract(old_value, '$.id'), json_extract(old_value, '$.title'),
+                            json_extract(old_value, '$.source'), json_extract(old_value, '$.composer'),
+                            json_extract(old_value, '$.arranger'), json_extract(old_value, '$.notes'),
+                            json_extract(old_value, '$.runtime'), json_extract(old_value, '$.key'),
+                            json_extract(old_value, '$.range'), json_extract(old_value, '$.voicing'),
+                            json_extract(old_value, '$.grade'), json_extract(old_value, '$.publisher'),
+                            json_extract(old_value, '$.catalog_number'), json_extract(old_value, '$.ismn'),
+                            json_extract(old_value, '$.copyright_year'), json_extract(old_value, '$.language')
+                        FROM history WHERE id = :id;", named_params!{":id": self.id})?;
+                    Change::resave(db, self.music)?;
+                    // Everything the cascade took with it is marked with this change's id
+                    let casualties = db.query(&format!("SELECT {} FROM history WHERE batch = :id ORDER BY id;",
+                        CHANGE_COLUMNS), named_params!{":id": self.id}, |row| {
+                        Ok(Change::from_row(row))
+                    })?;
+                    for change in casualties {
+                        change.undo(db)?;
+                    }
+                }
+                (ChangeTarget::Keyword, ChangeAction::Insert) => {
+                    db.execute("DELETE FROM music_keywords WHERE mid = :mid AND kid = :ref;",
+                        named_params!{":mid": self.music, ":ref": self.reference})?;
+                    db.auto_prune()?;
+                }
+                (ChangeTarget::Keyword, ChangeAction::Delete) => {
+                    // The keyword may have been pruned since, so go by name rather than id
+                    let mut key: Keyword = self.old_value.as_deref().ok_or(Error::CannotUndo(self.id))?
+                        .parse().unwrap();
+                    Music::new_with_id(self.music).link_keyword(db, &mut key)?;
+                }
+                (ChangeTarget::Keyword, ChangeAction::Update) => return Err(Error::CannotUndo(self.id)),
+                (ChangeTarget::Copy, ChangeAction::Insert) => {
+                    db.execute("DELETE FROM copies WHERE id = :ref AND mid = :mid;",
+                        named_params!{":mid": self.music, ":ref": self.reference})?;
+                }
+                (ChangeTarget::Copy, ChangeAction::Update) => {
+                    db.execute("UPDATE copies SET
+                            part = json_extract(h.old_value, '$.part'),
+                            count = json_extract(h.old_value, '$.count'),
+                            location = json_extract(h.old_value, '$.location'),
+                            condition = json_extract(h.old_value, '$.condition'),
+                            accession = json_extract(h.old_value, '$.accession')
+                        FROM (SELECT old_value FROM history WHERE id = :id AND mid = :mid AND ref = :ref) AS h
+                        WHERE copies.id = :ref;", params)?;
+                }
+                (ChangeTarget::Copy, ChangeAction::Delete) => {
+                    db.execute("INSERT INTO copies (id, mid, part, count, location, condition, accession)
+                        SELECT :ref, :mid, json_extract(old_value, '$.part'), json_extract(old_value, '$.count'),
+                            json_extract(old_value, '$.location'), json_extract(old_value, '$.condition'),
+                            json_extract(old_value, '$.accession')
+                        FROM history WHERE id = :id;", params)?;
+                }
+                (ChangeTarget::Contributor, ChangeAction::Insert) => {
+                    db.execute("DELETE FROM contributors WHERE mid = :mid AND position = :ref;",
+                        named_params!{":mid": self.music, ":ref": self.reference})?;
+                }
+                (ChangeTarget::Contributor, ChangeAction::Update) => {
+                    let (person, role) = self.recorded_credit(db)?;
+                    db.execute("UPDATE contributors SET pid = :pid, role = :role WHERE mid = :mid AND position = :ref;",
+                        named_params!{":mid": self.music, ":ref": self.reference, ":pid": person.id(), ":role": role.to_string()})?;
+                }
+                (ChangeTarget::Contributor, ChangeAction::Delete) => {
+                    // Credits added since may have taken its place, in which case it goes last
+                    let (person, role) = self.recorded_credit(db)?;
+                    db.execute("INSERT INTO contributors (mid, position, pid, role)
+                        SELECT :mid, CASE WHEN EXISTS (SELECT 1 FROM contributors WHERE mid = :mid AND position = :ref)
+                            THEN (SELECT max(position) + 1 FROM contributors WHERE mid = :mid) ELSE :ref END, :pid, :role;",
+                        named_params!{":mid": self.music, ":ref": self.reference, ":pid": person.id(), ":role": role.to_string()})?;
+                }
+                (ChangeTarget::PartRange, ChangeAction::Insert) => {
+                    let part = self.subject.as_deref().ok_or(Error::CannotUndo(self.id))?;
+                    Music::new_with_id(self.music).remove_part_range(db, part)?;
+                }
+                (ChangeTarget::PartRange, _) => {
+                    let part = self.subject.as_deref().ok_or(Error::CannotUndo(self.id))?;
+                    let range = self.old_shown.as_deref().and_then(|r| r.parse().ok())
+                        .ok_or(Error::CannotUndo(self.id))?;
+                    Music::new_with_id(self.music).set_part_range(db, &PartRange::new(part, range))?;
+                }
+                (ChangeTarget::CustomValue, ChangeAction::Insert) => {
+                    db.execute("DELETE FROM custom_values WHERE mid = :mid AND fid = :ref;",
+                        named_params!{":mid": self.music, ":ref": self.reference})?;
+                }
+                (ChangeTarget::CustomValue, _) => {
+                    // The field may have been removed or changed kind since
+                    let field = match self.reference {
+                        Some(id) => CustomField::by_id(db, id)?,
+                        None => None,
+                    }.ok_or(Error::CannotUndo(self.id))?;
+                    let value = self.old_shown.as_deref().and_then(|v| field.parse_value(v).ok())
+                        .ok_or(Error::CannotUndo(self.id))?;
+                    Music::new_with_id(self.music).set_custom_value(db, &field, Some(&value))?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    // History holds only what was typed in, so save a restored piece again to point it back at
+    // its people and work out the columns searches use from its key and range.
+    fn resave(db: &Database, music: i32) -> Result<()> {
+        if let Some(mut m) = Music::by_id(db, music)? {
+            m.insert(db)?;
+        }
+        Ok(())
+    }
+
+    // A value out of the JSON recorded for the row this change removed or replaced.
+    fn recorded(&self, db: &Database, path: &str) -> Result<Option<String>> {
+        Ok(db.query_row("SELECT json_extract(old_value, :path) FROM history WHERE id = :id;",
+            named_params!{":id": self.id, ":path": path}, |row| Ok(value_to_string(row.get(0)?)))?.flatten())
+    }
+
+    // People may have been merged or removed since, so credits go by name rather than id.
+    fn recorded_credit(&self, db: &Database) -> Result<(Person, ContributorRole)> {
+        let name = self.recorded(db, "$.name")?.ok_or(Error::CannotUndo(self.id))?;
+        let role = self.recorded(db, "$.role")?.and_then(|r| r.parse().ok()).unwrap_or(ContributorRole::Other);
+        Ok((Person::find_or_insert(db, &name)?, role))
+    }
+
+    fn show(&self, value: &Option<String>) -> String {
+        match (self.field.as_deref(), value) {
+            (_, None) => "nothing".into(),
+            (Some("runtime"), Some(v)) => v.parse().map(format_runtime).unwrap_or(v.clone()),
+            (_, Some(v)) => format!("\"{}\"", v),
+        }
+    }
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let part = self.subject.as_deref().unwrap_or("complete set");
+        let subject = self.subject.as_deref().unwrap_or("?");
+        let old_shown = self.old_shown.as_deref().unwrap_or("?");
+        let new_shown = self.new_shown.as_deref().unwrap_or("?");
+        match (self.target, self.action) {
+            (ChangeTarget::Music, ChangeAction::Insert) => write!(f, "Added to the library"),
+            (ChangeTarget::Music, ChangeAction::Delete) => write!(f, "Deleted permanently"),
+            (ChangeTarget::Music, ChangeAction::Update) if self.field.as_deref() == Some("deleted_at") => {
+                write!(f, "{}", if self.new_value.is_some() {"Moved to the trash"} else {"Restored from the trash"})
+            }
+            (ChangeTarget::Music, ChangeAction::Update) => write!(f, "Changed {} from {} to {}",
+                self.field.as_deref().unwrap_or("?").replace('_', " "), self.show(&self.old_value), self.show(&self.new_value)),
+            (ChangeTarget::Keyword, ChangeAction::Insert) => write!(f, "Added keyword {}", self.show(&self.new_value)),
+            (ChangeTarget::Keyword, _) => write!(f, "Removed keyword {}", self.show(&self.old_value)),
+            (ChangeTarget::Copy, ChangeAction::Insert) => write!(f, "Added copies ({})", part),
+            (ChangeTarget::Copy, ChangeAction::Update) => write!(f, "Changed copies ({})", part),
+            (ChangeTarget::Copy, ChangeAction::Delete) => write!(f, "Removed copies ({})", part),
+            (ChangeTarget::Contributor, ChangeAction::Insert) => write!(f, "Credited {}", new_shown),
+            (ChangeTarget::Contributor, ChangeAction::Update) => write!(f, "Changed credit {} to {}", old_shown, new_shown),
+            (ChangeTarget::Contributor, ChangeAction::Delete) => write!(f, "Removed credit {}", old_shown),
+            (ChangeTarget::PartRange, ChangeAction::Insert) => write!(f, "Set {} range to {}", subject, new_shown),
+            (ChangeTarget::PartRange, ChangeAction::Update) => write!(f, "Changed {} range from {} to {}",
+                subject, old_shown, new_shown),
+            (ChangeTarget::PartRange, ChangeAction::Delete) => write!(f, "Removed {} range", subject),
+            (ChangeTarget::CustomValue, ChangeAction::Insert) => write!(f, "Set {} to \"{}\"", subject, new_shown),
+            (ChangeTarget::CustomValue, ChangeAction::Update) => write!(f, "Changed {} from \"{}\" to \"{}\"",
+                subject, old_shown, new_shown),
+            (ChangeTarget::CustomValue, ChangeAction::Delete) => write!(f, "Cleared {}", subject),
+        }
+    }
+}
+
+impl Music {
+    /// The most recent `limit` changes to this piece, newest first.
+    pub fn history(&self, db: &Database, limit: u32) -> Result<Option<Vec<Change>>> {
+        if self.id.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(db.query(&format!("SELECT {} FROM history WHERE mid = :id ORDER BY id DESC LIMIT :limit;", CHANGE_COLUMNS),
+            named_params!{":id": self.id, ":limit": limit}, |row| {
+            Ok(Change::from_row(row))
+        })?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{Borrower, Contributor, CustomFieldKind, CustomValue, Loan, MusicCopy};
+
+    fn piece(db: &Database, title: &str) -> Music {
+        let mut music = Music::new();
+        music.title = title.into();
+        music.insert_with_keywords(db, &mut ["season:Christmas".parse().unwrap()]).unwrap();
+        music
+    }
+
+    fn described(db: &Database, music: &Music) -> Vec<String> {
+        music.history(db, 100).unwrap().unwrap().iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn edits_are_recorded_and_undone() {
+        let db = Database::open_in_memory().unwrap();
+        let mut music = piece(&db, "Once in Royal");
+        music.title = "Once in Royal David's City".into();
+        music.runtime = Some(245);
+        music.insert(&db).unwrap();
+        music.update_keywords(&db, &mut ["season:Advent".parse().unwrap()]).unwrap();
+
+        assert_eq!(described(&db, &music), [
+            "Removed keyword \"season:Christmas\"",
+            "Added keyword \"season:Advent\"",
+            "Changed runtime from nothing to 4:05",
+            "Changed title from \"Once in Royal\" to \"Once in Royal David's City\"",
+            "Added keyword \"season:Christmas\"",
+            "Added to the library",
+        ]);
+
+        let history = music.history(&db, 100).unwrap().unwrap();
+        for change in &history[..4] {
+            change.undo(&db).unwrap();
+        }
+        let music = Music::by_id(&db, music.id().unwrap()).unwrap().unwrap();
+        assert_eq!(music.title, "Once in Royal");
+        assert_eq!(music.runtime, None);
+        assert_eq!(music.keywords(&db).unwrap().unwrap(), ["season:Christmas".parse().unwrap()]);
+        // Undoing is itself a change
+        assert_eq!(Change::recent(&db, 100).unwrap().len(), history.len() + 4);
+    }
+
+    fn deletion(db: &Database, music: i32) -> Change {
+        Change::recent(db, 100).unwrap().into_iter()
+            .find(|c| c.music_id() == music && c.target == ChangeTarget::Music && c.action == ChangeAction::Delete)
+            .unwrap()
+    }
+
+    #[test]
+    fn undoing_a_permanent_delete_restores_what_went_with_it() {
+        let db = Database::open_in_memory().unwrap();
+        let music = piece(&db, "Lo, How a Rose");
+        let mid = music.id().unwrap();
+        MusicCopy::new(mid).insert(&db).unwrap();
+        music.set_contributors(&db, &mut [Contributor::new("Michael Praetorius", ContributorRole::Composer)]).unwrap();
+        music.set_part_range(&db, &PartRange::new("Soprano", "D4-F5".parse().unwrap())).unwrap();
+        let mut field = CustomField::new("Acquired", CustomFieldKind::Date);
+        field.insert(&db).unwrap();
+        music.set_custom_value(&db, &field, Some(&field.parse_value("2024-02-29").unwrap())).unwrap();
+
+        Music::new_with_id(mid).delete_permanently(&db).unwrap();
+        deletion(&db, mid).undo(&db).unwrap();
+
+        let music = Music::by_id(&db, mid).unwrap().unwrap();
+        assert_eq!(music.title, "Lo, How a Rose");
+        assert_eq!(music.keywords(&db).unwrap().unwrap().len(), 1);
+        assert_eq!(music.copies(&db).unwrap().unwrap().len(), 1);
+        let credits = music.contributors(&db).unwrap().unwrap();
+        assert_eq!(credits.len(), 1);
+        assert_eq!(credits[0].to_string(), "Michael Praetorius (composer)");
+        assert_eq!(music.part_ranges(&db).unwrap().unwrap(), vec![PartRange::new("Soprano", "D4-F5".parse().unwrap())]);
+        assert_eq!(music.custom_values(&db).unwrap().unwrap()[0].1, CustomValue::Date("2024-02-29".into()));
+    }
+
+    #[test]
+    fn undoing_an_insert_moves_the_piece_to_the_trash() {
+        let db = Database::open_in_memory().unwrap();
+        let music = piece(&db, "Gaudete");
+        let added = music.history(&db, 100).unwrap().unwrap().pop().unwrap();
+        assert_eq!(added.to_string(), "Added to the library");
+        added.undo(&db).unwrap();
+        assert!(Music::by_id(&db, music.id().unwrap()).unwrap().unwrap().is_trashed());
+
+        let trashed = music.history(&db, 1).unwrap().unwrap().pop().unwrap();
+        assert_eq!(trashed.to_string(), "Moved to the trash");
+        trashed.undo(&db).unwrap();
+        assert!(!Music::by_id(&db, music.id().unwrap()).unwrap().unwrap().is_trashed());
+    }
+
+    #[test]
+    fn unrelated_changes_stay_undone() {
+        let db = Database::open_in_memory().unwrap();
+        let mut music = piece(&db, "Gaudete");
+        let mid = music.id().unwrap();
+        // Removed in the same second as the delete, but not by it
+        music.update_keywords(&db, &mut []).unwrap();
+        Music::new_with_id(mid).delete_permanently(&db).unwrap();
+        deletion(&db, mid).undo(&db).unwrap();
+        assert!(Music::by_id(&db, mid).unwrap().unwrap().keywords(&db).unwrap().unwrap().is_empty());
+    }
+
+    #[test]
+    fn loans_cannot_be_brought_back() {
+        let db = Database::open_in_memory().unwrap();
+        let music = piece(&db, "Personent Hodie");
+        let mid = music.id().unwrap();
+        let mut copy = MusicCopy::new(mid);
+        copy.insert(&db).unwrap();
+        Loan::check_out(&db, &copy, &Borrower::find_or_insert(&db, "Tenors").unwrap(), 1, None).unwrap();
+        Music::new_with_id(mid).delete_permanently(&db).unwrap();
+        assert!(matches!(deletion(&db, mid).undo(&db), Err(Error::CannotUndo(_))));
+        assert!(Music::by_id(&db, mid).unwrap().is_none());
+    }
+
+    #[test]
+    fn unchanged_credits_leave_no_history() {
+        let db = Database::open_in_memory().unwrap();
+        let music = piece(&db, "Ding Dong Merrily on High");
+        let mut credits = [Contributor::new("Charles Wood", ContributorRole::Arranger)];
+        music.set_contributors(&db, &mut credits).unwrap();
+        let before = music.history(&db, 100).unwrap().unwrap().len();
+        music.set_contributors(&db, &mut credits).unwrap();
+        assert_eq!(music.history(&db, 100).unwrap().unwrap().len(), before);
+
+        music.set_contributors(&db, &mut []).unwrap();
+        let removed = &music.history(&db, 1).unwrap().unwrap()[0];
+        assert_eq!(removed.to_string(), "Removed credit Charles Wood (arranger)");
+        removed.undo(&db).unwrap();
+        assert_eq!(music.contributors(&db).unwrap().unwrap(), credits);
+    }
+}
@@ -0,0 +1,83 @@
+/// An International Standard Music Number, the ISBN of printed music.
+///
+/// Parses the current 13 digit form, like "979-0-2600-0043-8", and the older 10 character one
+/// starting with M, like "M-2600-0043-8", which is the same number. Hyphens and spaces are
+/// ignored, and the check digit has to be right.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Ismn(String);
+
+impl Ismn {
+    /// The 13 digits, without hyphens.
+    pub fn digits(&self) -> &str {
+        &self.0
+    }
+
+    // EAN-13 check digit of the first 12 digits
+    fn check_digit(digits: &[u8]) -> u8 {
+        let sum: u32 = digits.iter().take(12).enumerate()
+            .map(|(i, d)| u32::from(*d) * if i % 2 == 0 { 1 } else { 3 })
+            .sum();
+        ((10 - sum % 10) % 10) as u8
+    }
+}
+
+impl std::str::FromStr for Ismn {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Ismn, Self::Err> {
+        let compact: String = s.trim().chars().filter(|c| !matches!(c, '-' | ' ')).collect();
+        // M stands in for 979-0 in the old form
+        let full = match compact.strip_prefix(['M', 'm']) {
+            Some(rest) => format!("9790{}", rest),
+            None => compact,
+        };
+        if full.len() != 13 || !full.chars().all(|c| c.is_ascii_digit()) {
+            return Err("An ISMN has 13 digits, or M and 9 digits");
+        }
+        if !full.starts_with("9790") {
+            return Err("An ISMN starts with 979-0");
+        }
+        let digits: Vec<u8> = full.bytes().map(|b| b - b'0').collect();
+        if Ismn::check_digit(&digits) != digits[12] {
+            return Err("The ISMN's check digit is wrong");
+        }
+        Ok(Ismn(full))
+    }
+}
+
+impl std::fmt::Display for Ismn {
+    // How the publisher and item parts split depends on the publisher, so only the fixed
+    // parts are set apart
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "979-0-{}-{}", &self.0[4..12], &self.0[12..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_and_new_forms_are_the_same_number() {
+        let new: Ismn = "979-0-2600-0043-8".parse().unwrap();
+        let old: Ismn = "M-2600-0043-8".parse().unwrap();
+        assert_eq!(new, old);
+        assert_eq!(new.digits(), "9790260000438");
+        assert_eq!("m 2600 0043 8".parse(), Ok(new.clone()));
+        assert_eq!(new.to_string(), "979-0-26000043-8");
+        assert_eq!(new.to_string().parse(), Ok(new));
+    }
+
+    #[test]
+    fn check_digits_are_checked() {
+        assert_eq!("979-0-2600-0043-9".parse::<Ismn>(), Err("The ISMN's check digit is wrong"));
+        assert_eq!("M-2600-0043-0".parse::<Ismn>(), Err("The ISMN's check digit is wrong"));
+    }
+
+    #[test]
+    fn only_979_0_numbers_are_ismns() {
+        // A valid ISBN, check digit and all
+        assert_eq!("978-1-2345-6789-7".parse::<Ismn>(), Err("An ISMN starts with 979-0"));
+        assert!("979-0-2600-0043".parse::<Ismn>().is_err());
+        assert!("M-2600-0O43-8".parse::<Ismn>().is_err());
+    }
+}
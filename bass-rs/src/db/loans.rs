@@ -0,0 +1,358 @@
+use rusqlite::{named_params, Row};
+
+use super::{Database, Error, Music, MusicCopy, Result};
+
+// `overdue` isn't stored, so every loan query selects it alongside the table's own columns.
+const LOAN_COLUMNS: &str = "id, cid, bid, count, checked_out, due, returned,
+    ifnull(returned IS NULL AND due < date('now'), 0)";
+
+/// A member or ensemble that music can be lent to.
+#[derive(Clone, Debug)]
+pub struct Borrower {
+    id: Option<i32>,
+    pub name: String,
+    /// Phone number, email address, or whatever else reaches them.
+    pub contact: Option<String>,
+    pub notes: Option<String>,
+}
+
+#[allow(dead_code)]
+impl Borrower {
+    fn from_row(row: &Row) -> Borrower {
+        Borrower {
+            id: row.get_unwrap(0),
+            name: row.get_unwrap(1),
+            contact: row.get_unwrap(2),
+            notes: row.get_unwrap(3),
+        }
+    }
+
+    pub fn new(name: &str) -> Borrower {
+        Borrower {
+            id: None,
+            name: name.into(),
+            contact: None,
+            notes: None,
+        }
+    }
+
+    pub fn id(&self) -> Option<i32> {
+        self.id
+    }
+
+    pub fn list_all(db: &Database) -> Result<Vec<Borrower>> {
+        db.query("SELECT * FROM borrowers ORDER BY name;", (), |row| {
+            Ok(Borrower::from_row(row))
+        })
+    }
+
+    pub fn by_id(db: &Database, id: i32) -> Result<Option<Borrower>> {
+        db.query_row("SELECT * FROM borrowers WHERE id = :id;", named_params!{":id": id}, |row| {
+            Ok(Borrower::from_row(row))
+        })
+    }
+
+    pub fn by_name(db: &Database, name: &str) -> Result<Option<Borrower>> {
+        db.query_row("SELECT * FROM borrowers WHERE name = :name;", named_params!{":name": name}, |row| {
+            Ok(Borrower::from_row(row))
+        })
+    }
+
+    /// Finds the borrower called `name`, adding them if they aren't in the database yet.
+    pub fn find_or_insert(db: &Database, name: &str) -> Result<Borrower> {
+        db.transaction(|db| {
+            if let Some(borrower) = Borrower::by_name(db, name)? {
+                return Ok(borrower);
+            }
+            let mut borrower = Borrower::new(name);
+            borrower.insert(db)?;
+            Ok(borrower)
+        })
+    }
+
+    pub fn insert(&mut self, db: &Database) -> Result<()> {
+        if !self.is_db_entry() {
+            let id = db.insert("INSERT INTO borrowers (name, contact, notes) VALUES (
+                :name,
+                :contact,
+                :notes
+            );", named_params!{
+                ":name": self.name,
+                ":contact": self.contact,
+                ":notes": self.notes,
+            })?;
+            self.id = Some(id);
+            Ok(())
+        } else {
+            db.execute("UPDATE borrowers SET
+                name = :name,
+                contact = :contact,
+                notes = :notes
+            WHERE id = :id;", named_params!{
+                ":id": self.id,
+                ":name": self.name,
+                ":contact": self.contact,
+                ":notes": self.notes,
+            }).map(|_| ())
+        }
+    }
+
+    /// Loans to this borrower, newest first, including ones that have been returned.
+    pub fn loans(&self, db: &Database) -> Result<Vec<Loan>> {
+        db.query(&format!("SELECT {} FROM loans WHERE bid = :id ORDER BY checked_out DESC, id DESC;", LOAN_COLUMNS),
+            named_params!{":id": self.id}, |row| {
+            Ok(Loan::from_row(row))
+        })
+    }
+
+    /// Fails while the borrower has any loan on record, returned or not, so history isn't lost.
+    pub fn delete(self, db: &Database) -> Result<()> {
+        db.execute("DELETE FROM borrowers WHERE id = :id;", named_params!{":id": self.id})?;
+        Ok(())
+    }
+
+    pub fn is_db_entry(&self) -> bool {
+        self.id.is_some()
+    }
+}
+
+/// Some number of one [`MusicCopy`] checked out to a [`Borrower`].
+///
+/// Dates are ISO 8601 strings (`YYYY-MM-DD`).
+#[derive(Clone, Debug)]
+pub struct Loan {
+    id: Option<i32>,
+    copy: i32,
+    borrower: i32,
+    pub count: u32,
+    pub checked_out: String,
+    pub due: Option<String>,
+    pub returned: Option<String>,
+    overdue: bool,
+}
+
+#[allow(dead_code)]
+impl Loan {
+    fn from_row(row: &Row) -> Loan {
+        Loan {
+            id: row.get_unwrap(0),
+            copy: row.get_unwrap(1),
+            borrower: row.get_unwrap(2),
+            count: row.get_unwrap(3),
+            checked_out: row.get_unwrap(4),
+            due: row.get_unwrap(5),
+            returned: row.get_unwrap(6),
+            overdue: row.get_unwrap(7),
+        }
+    }
+
+    pub fn id(&self) -> Option<i32> {
+        self.id
+    }
+
+    pub fn copy_id(&self) -> i32 {
+        self.copy
+    }
+
+    pub fn borrower_id(&self) -> i32 {
+        self.borrower
+    }
+
+    /// Whether the loan was still out past its due date when it was loaded.
+    pub fn is_overdue(&self) -> bool {
+        self.overdue
+    }
+
+    pub fn is_returned(&self) -> bool {
+        self.returned.is_some()
+    }
+
+    pub fn by_id(db: &Database, id: i32) -> Result<Option<Loan>> {
+        db.query_row(&format!("SELECT {} FROM loans WHERE id = :id;", LOAN_COLUMNS), named_params!{":id": id}, |row| {
+            Ok(Loan::from_row(row))
+        })
+    }
+
+    /// Every loan that hasn't been returned, soonest due first.
+    pub fn outstanding(db: &Database) -> Result<Vec<Loan>> {
+        db.query(&format!("SELECT {} FROM loans WHERE returned IS NULL
+            ORDER BY due IS NULL, due, checked_out;", LOAN_COLUMNS), (), |row| {
+            Ok(Loan::from_row(row))
+        })
+    }
+
+    /// Loans still out past their due date, most overdue first.
+    pub fn overdue(db: &Database) -> Result<Vec<Loan>> {
+        db.query(&format!("SELECT {} FROM loans WHERE returned IS NULL AND due < date('now')
+            ORDER BY due, checked_out;", LOAN_COLUMNS), (), |row| {
+            Ok(Loan::from_row(row))
+        })
+    }
+
+    /// Checks out `count` of `copy` to `borrower` as of today.
+    pub fn check_out(db: &Database, copy: &MusicCopy, borrower: &Borrower, count: u32, due: Option<&str>) -> Result<Loan> {
+        if count == 0 {
+            return Err(Error::EmptyLoan);
+        }
+        db.transaction(|db| {
+            let available = copy.available(db)?;
+            if count > available {
+                return Err(Error::NotEnoughCopies { available });
+            }
+            let id = db.insert("INSERT INTO loans (cid, bid, count, due) VALUES (:cid, :bid, :count, :due);", named_params!{
+                ":cid": copy.id(),
+                ":bid": borrower.id(),
+                ":count": count,
+                ":due": due,
+            })?;
+            // Read back so the checkout date and overdue flag come from the database
+            Ok(Loan::by_id(db, id)?.expect("loan was just inserted"))
+        })
+    }
+
+    /// Marks the loan as returned today.
+    pub fn check_in(&mut self, db: &Database) -> Result<()> {
+        if self.is_returned() {
+            return Ok(());
+        }
+        let returned = db.query_row("UPDATE loans SET returned = date('now') WHERE id = :id RETURNING returned;",
+            named_params!{":id": self.id}, |row| row.get(0))?;
+        self.returned = returned;
+        self.overdue = false;
+        Ok(())
+    }
+
+    /// Changes the due date of a loan that is still out. Returned loans keep the due date they had.
+    pub fn extend(&mut self, db: &Database, due: Option<&str>) -> Result<()> {
+        let overdue = db.query_row("UPDATE loans SET due = :due WHERE id = :id AND returned IS NULL
+            RETURNING ifnull(due < date('now'), 0);",
+            named_params!{":id": self.id, ":due": due}, |row| row.get(0))?;
+        let Some(overdue) = overdue else {
+            return Err(Error::AlreadyReturned(self.id.unwrap_or_default()));
+        };
+        self.due = due.map(|d| d.into());
+        self.overdue = overdue;
+        Ok(())
+    }
+
+    pub fn delete(self, db: &Database) -> Result<()> {
+        db.execute("DELETE FROM loans WHERE id = :id;", named_params!{":id": self.id})?;
+        Ok(())
+    }
+
+    pub fn is_db_entry(&self) -> bool {
+        self.id.is_some()
+    }
+}
+
+impl MusicCopy {
+    /// How many of these copies are on the shelf, not counting ones out on loan.
+    pub fn available(&self, db: &Database) -> Result<u32> {
+        let lent: u32 = db.query_row("SELECT coalesce(sum(count), 0) FROM loans WHERE cid = :id AND returned IS NULL;",
+            named_params!{":id": self.id()}, |row| row.get(0))?.unwrap_or(0);
+        Ok(self.count.saturating_sub(lent))
+    }
+
+    /// Loans of these copies that haven't been returned.
+    pub fn outstanding_loans(&self, db: &Database) -> Result<Vec<Loan>> {
+        db.query(&format!("SELECT {} FROM loans WHERE cid = :id AND returned IS NULL ORDER BY checked_out;", LOAN_COLUMNS),
+            named_params!{":id": self.id()}, |row| {
+            Ok(Loan::from_row(row))
+        })
+    }
+}
+
+impl Music {
+    /// Every loan of any copy of this piece that hasn't been returned.
+    pub fn outstanding_loans(&self, db: &Database) -> Result<Option<Vec<Loan>>> {
+        if self.id.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(db.query(&format!("SELECT {} FROM loans
+            WHERE returned IS NULL AND cid IN (SELECT id FROM copies WHERE mid = :id)
+            ORDER BY due IS NULL, due, checked_out;", LOAN_COLUMNS), named_params!{":id": self.id}, |row| {
+            Ok(Loan::from_row(row))
+        })?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shelved(db: &Database, count: u32) -> (Music, MusicCopy) {
+        let mut music = Music::new();
+        music.title = "Zadok the Priest".into();
+        music.insert(db).unwrap();
+        let mut copy = MusicCopy::new(music.id().unwrap());
+        copy.count = count;
+        copy.insert(db).unwrap();
+        (music, copy)
+    }
+
+    #[test]
+    fn loans_take_copies_off_the_shelf_until_returned() {
+        let db = Database::open_in_memory().unwrap();
+        let (music, copy) = shelved(&db, 10);
+        let altos = Borrower::find_or_insert(&db, "Altos").unwrap();
+        assert_eq!(Borrower::find_or_insert(&db, "Altos").unwrap().id(), altos.id());
+
+        let mut loan = Loan::check_out(&db, &copy, &altos, 6, Some("2999-01-01")).unwrap();
+        assert_eq!(copy.available(&db).unwrap(), 4);
+        assert!(matches!(Loan::check_out(&db, &copy, &altos, 5, None), Err(Error::NotEnoughCopies { available: 4 })));
+        assert!(!loan.is_overdue());
+        assert_eq!(music.outstanding_loans(&db).unwrap().unwrap().len(), 1);
+
+        loan.check_in(&db).unwrap();
+        assert!(loan.is_returned());
+        assert_eq!(copy.available(&db).unwrap(), 10);
+        assert!(Loan::outstanding(&db).unwrap().is_empty());
+    }
+
+    #[test]
+    fn late_loans_are_overdue() {
+        let db = Database::open_in_memory().unwrap();
+        let (_, copy) = shelved(&db, 4);
+        let tenors = Borrower::find_or_insert(&db, "Tenors").unwrap();
+        let mut late = Loan::check_out(&db, &copy, &tenors, 2, Some("2000-01-01")).unwrap();
+        Loan::check_out(&db, &copy, &tenors, 1, None).unwrap();
+
+        assert!(late.is_overdue());
+        assert_eq!(Loan::outstanding(&db).unwrap().len(), 2);
+        assert_eq!(Loan::overdue(&db).unwrap().iter().map(|l| l.id()).collect::<Vec<_>>(), [late.id()]);
+
+        late.extend(&db, Some("2999-01-01")).unwrap();
+        assert!(!late.is_overdue());
+        assert!(Loan::overdue(&db).unwrap().is_empty());
+    }
+
+    fn lendable(db: &Database) -> (MusicCopy, Borrower) {
+        let mut music = Music::new();
+        music.title = "Lux Aurumque".into();
+        music.insert(db).unwrap();
+        let mut copy = MusicCopy::new(music.id().unwrap());
+        copy.count = 3;
+        copy.insert(db).unwrap();
+        (copy, Borrower::find_or_insert(db, "Alto section").unwrap())
+    }
+
+    #[test]
+    fn no_empty_loans() {
+        let db = Database::open_in_memory().unwrap();
+        let (copy, borrower) = lendable(&db);
+        assert!(matches!(Loan::check_out(&db, &copy, &borrower, 0, None), Err(Error::EmptyLoan)));
+        assert_eq!(copy.available(&db).unwrap(), 3);
+    }
+
+    #[test]
+    fn returned_loans_keep_their_due_date() {
+        let db = Database::open_in_memory().unwrap();
+        let (copy, borrower) = lendable(&db);
+        let mut loan = Loan::check_out(&db, &copy, &borrower, 2, Some("2030-01-01")).unwrap();
+        loan.extend(&db, Some("2030-02-01")).unwrap();
+        loan.check_in(&db).unwrap();
+        assert!(matches!(loan.extend(&db, Some("2030-03-01")), Err(Error::AlreadyReturned(_))));
+        let stored = Loan::by_id(&db, loan.id.unwrap()).unwrap().unwrap();
+        assert_eq!(stored.due.as_deref(), Some("2030-02-01"));
+    }
+}
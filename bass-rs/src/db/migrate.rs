@@ -0,0 +1,205 @@
+use rusqlite::Connection;
+
+use super::{Database, Error, Person, Result};
+
+// Migrations are applied in order, and the position of each one in this list is the schema
+// version it produces. Never edit or reorder a migration that has shipped; add a new one instead.
+const MIGRATIONS: &[&str] = &[
+    include_str!("migrations/0001-init.sql"),
+    include_str!("migrations/0002-fulltext.sql"),
+    include_str!("migrations/0003-copies.sql"),
+    include_str!("migrations/0004-loans.sql"),
+    include_str!("migrations/0005-performances.sql"),
+    include_str!("migrations/0006-programs.sql"),
+    include_str!("migrations/0007-attachments.sql"),
+    include_str!("migrations/0008-history.sql"),
+    include_str!("migrations/0009-trash.sql"),
+    include_str!("migrations/0010-people.sql"),
+    include_str!("migrations/0011-contributors.sql"),
+    include_str!("migrations/0012-key-range.sql"),
+    include_str!("migrations/0013-part-ranges.sql"),
+    include_str!("migrations/0014-catalog-fields.sql"),
+    include_str!("migrations/0015-custom-fields.sql"),
+    include_str!("migrations/0016-history-batches.sql"),
+];
+
+/// The schema version written by this build of libbass.
+pub const SCHEMA_VERSION: i32 = MIGRATIONS.len() as i32;
+
+pub(super) fn schema_version(conn: &Connection) -> Result<i32> {
+    conn.pragma_query_value(None, "user_version", |row| row.get(0))
+        .map_err(|e| e.into())
+}
+
+/// Brings the schema of `conn` up to [`SCHEMA_VERSION`], backing up on-disk databases first.
+pub(super) fn migrate(conn: &mut Connection) -> Result<()> {
+    let version = schema_version(conn)?;
+    if version > SCHEMA_VERSION {
+        return Err(Error::SchemaTooNew { found: version, supported: SCHEMA_VERSION });
+    }
+    if version == SCHEMA_VERSION {
+        return Ok(());
+    }
+
+    backup(conn, version)?;
+
+    // foreign_keys can't be changed inside a transaction, and some migrations need to rebuild
+    // tables, so constraints are checked by hand before each commit instead.
+    conn.pragma_update(None, "foreign_keys", false)?;
+    let result = apply_pending(conn, version);
+    conn.pragma_update(None, "foreign_keys", true)?;
+    result
+}
+
+// How alike two people's names have to be for migrating to make them one person
+const SAME_PERSON_THRESHOLD: f64 = 0.9;
+
+/// Finishes what migrations can't do in SQL, once the schema is up to date. `from` is the
+/// version the database was at before migrating.
+pub(super) fn after_migrating(db: &Database, from: i32) -> Result<()> {
+    // 0010-people only makes one person of names that differ by case and spacing
+    if from < 10 {
+        Person::merge_unambiguous(db, SAME_PERSON_THRESHOLD)?;
+    }
+    Ok(())
+}
+
+fn apply_pending(conn: &mut Connection, from: i32) -> Result<()> {
+    for (i, script) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        let version = i as i32 + 1;
+        let tx = conn.transaction()?;
+        tx.execute_batch(script)?;
+        let violation = tx.prepare("PRAGMA foreign_key_check;")?.exists(())?;
+        if violation {
+            return Err(Error::MigrationFailed(version));
+        }
+        tx.pragma_update(None, "user_version", version)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+// Databases that have never been written to have nothing worth keeping.
+fn backup(conn: &Connection, version: i32) -> Result<()> {
+    let path = match conn.path() {
+        Some(path) if !path.is_empty() => path.to_owned(),
+        _ => return Ok(()),
+    };
+    let tables: i32 = conn.query_row("SELECT count(*) FROM sqlite_schema;", (), |row| row.get(0))?;
+    if tables == 0 {
+        return Ok(());
+    }
+
+    let backup_path = format!("{}.v{}.bak", path, version);
+    let _ = std::fs::remove_file(&backup_path);
+    conn.execute("VACUUM INTO ?1;", [backup_path])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{ChangeAction, ChangeTarget, Database, Music, Person};
+
+    // A connection at schema `version`, as an older build would have left it.
+    fn at_version(version: usize) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        for script in &MIGRATIONS[..version] {
+            conn.execute_batch(script).unwrap();
+        }
+        conn.pragma_update(None, "user_version", version as i32).unwrap();
+        conn.pragma_update(None, "foreign_keys", true).unwrap();
+        conn
+    }
+
+    // Collections made before migrations existed have the first schema but no version.
+    fn unversioned() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0]).unwrap();
+        conn
+    }
+
+    #[test]
+    fn unversioned_collections_are_brought_up_to_date() {
+        let conn = unversioned();
+        conn.execute_batch("INSERT INTO music (id, title, source, composer, runtime)
+                VALUES (1, 'The Holly and the Ivy', 'Carols for Choirs 1', 'Traditional', 150);
+            INSERT INTO keywords (id, category, keyword) VALUES (1, 'season', 'Christmas');
+            INSERT INTO music_keywords (mid, kid) VALUES (1, 1);").unwrap();
+
+        let db = Database::from_connection(conn).unwrap();
+        assert_eq!(db.schema_version().unwrap(), SCHEMA_VERSION);
+        let music = Music::by_id(&db, 1).unwrap().unwrap();
+        assert_eq!(music.title, "The Holly and the Ivy");
+        assert_eq!(music.runtime, Some(150));
+        assert_eq!(music.keywords(&db).unwrap().unwrap().len(), 1);
+        assert_eq!(Music::search_text(&db, "ivy christmas").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn newer_schemas_are_refused() {
+        let conn = at_version(MIGRATIONS.len());
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1).unwrap();
+        assert!(matches!(Database::from_connection(conn),
+            Err(Error::SchemaTooNew { found, supported: SCHEMA_VERSION }) if found == SCHEMA_VERSION + 1));
+    }
+
+    #[test]
+    fn collections_on_disk_are_backed_up_first() {
+        let path = std::env::temp_dir().join(format!("bass-migrate-{}.sqlite3", std::process::id()));
+        let backup = format!("{}.v0.bak", path.display());
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(MIGRATIONS[0]).unwrap();
+        conn.execute("INSERT INTO music (title, source) VALUES ('Gaudete', '');", ()).unwrap();
+        drop(conn);
+
+        let db = Database::open(&path).unwrap();
+        assert_eq!(db.schema_version().unwrap(), SCHEMA_VERSION);
+        let old = Connection::open(&backup).unwrap();
+        assert_eq!(schema_version(&old).unwrap(), 0);
+        let titles: String = old.query_row("SELECT group_concat(title) FROM music;", (), |row| row.get(0)).unwrap();
+        assert_eq!(titles, "Gaudete");
+
+        drop((db, old));
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&backup).unwrap();
+    }
+
+    #[test]
+    fn older_deletes_can_still_be_undone() {
+        let conn = at_version(15);
+        conn.execute_batch("INSERT INTO music (id, title, source) VALUES (1, 'Coventry Carol', '');
+            INSERT INTO keywords (id, keyword) VALUES (1, 'Advent');
+            INSERT INTO music_keywords (mid, kid) VALUES (1, 1);
+            DELETE FROM music WHERE id = 1;").unwrap();
+
+        let db = Database::from_connection(conn).unwrap();
+        let deleted = db.query("SELECT id FROM history WHERE target = 'music' AND action = 'delete';", (),
+            |row| row.get::<_, i32>(0)).unwrap()[0];
+        let change = crate::db::Change::by_id(&db, deleted).unwrap().unwrap();
+        assert_eq!((change.target, change.action), (ChangeTarget::Music, ChangeAction::Delete));
+        change.undo(&db).unwrap();
+        let music = Music::by_id(&db, 1).unwrap().unwrap();
+        assert_eq!(music.keywords(&db).unwrap().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn spellings_of_one_person_become_aliases() {
+        let conn = at_version(9);
+        for (id, composer) in ["Ralph Vaughan Williams", "Ralph Vaughan Williams", "vaughan williams ",
+            "Johann Sebastian Bach", "C. P. E. Bach", "Bach", "Gustav Holst"].into_iter().enumerate() {
+            conn.execute("INSERT INTO music (id, title, source, composer) VALUES (?1, 'Carol', '', ?2);",
+                (id as i32 + 1, composer)).unwrap();
+        }
+
+        let db = Database::from_connection(conn).unwrap();
+        let people: Vec<String> = Person::list_all(&db).unwrap().into_iter().map(|p| p.name).collect();
+        // "Bach" could be either Bach, so it's left alone
+        assert_eq!(people, ["Bach", "C. P. E. Bach", "Gustav Holst", "Johann Sebastian Bach", "Ralph Vaughan Williams"]);
+        let rvw = Person::by_name(&db, "Ralph Vaughan Williams").unwrap().unwrap();
+        assert_eq!(rvw.aliases(&db).unwrap(), ["vaughan williams"]);
+        let music = Music::by_id(&db, 3).unwrap().unwrap();
+        assert_eq!(music.composer_id, rvw.id());
+        assert_eq!(music.composer.as_deref(), Some("Ralph Vaughan Williams"));
+    }
+}
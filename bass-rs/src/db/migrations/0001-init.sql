@@ -1,5 +1,3 @@
-PRAGMA foreign_keys = ON;
-
 CREATE TABLE IF NOT EXISTS `music` (
   `id` integer PRIMARY KEY,
   `title` string NOT NULL,
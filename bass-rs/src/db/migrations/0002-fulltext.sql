@@ -0,0 +1,61 @@
+-- Full text index over every searchable field of a piece. The rowid of each entry is the id of
+-- the piece it indexes, and `keywords` holds all of the piece's keywords, including categories.
+CREATE VIRTUAL TABLE `music_search` USING fts5(
+  `title`,
+  `source`,
+  `composer`,
+  `arranger`,
+  `notes`,
+  `keywords`,
+  tokenize = 'unicode61 remove_diacritics 2'
+);
+
+INSERT INTO music_search (rowid, title, source, composer, arranger, notes, keywords)
+  SELECT m.id, m.title, m.source, m.composer, m.arranger, m.notes,
+    (SELECT group_concat(coalesce(k.category || ' ', '') || k.keyword, ' ')
+      FROM keywords k INNER JOIN music_keywords mk ON k.id = mk.kid
+      WHERE mk.mid = m.id)
+  FROM music m;
+
+CREATE TRIGGER music_search_insert AFTER INSERT ON music BEGIN
+  INSERT INTO music_search (rowid, title, source, composer, arranger, notes, keywords)
+    VALUES (new.id, new.title, new.source, new.composer, new.arranger, new.notes, NULL);
+END;
+
+CREATE TRIGGER music_search_update AFTER UPDATE ON music BEGIN
+  UPDATE music_search SET
+    title = new.title,
+    source = new.source,
+    composer = new.composer,
+    arranger = new.arranger,
+    notes = new.notes
+  WHERE rowid = old.id;
+END;
+
+CREATE TRIGGER music_search_delete AFTER DELETE ON music BEGIN
+  DELETE FROM music_search WHERE rowid = old.id;
+END;
+
+CREATE TRIGGER music_search_link AFTER INSERT ON music_keywords BEGIN
+  UPDATE music_search SET keywords =
+    (SELECT group_concat(coalesce(k.category || ' ', '') || k.keyword, ' ')
+      FROM keywords k INNER JOIN music_keywords mk ON k.id = mk.kid
+      WHERE mk.mid = new.mid)
+  WHERE rowid = new.mid;
+END;
+
+CREATE TRIGGER music_search_unlink AFTER DELETE ON music_keywords BEGIN
+  UPDATE music_search SET keywords =
+    (SELECT group_concat(coalesce(k.category || ' ', '') || k.keyword, ' ')
+      FROM keywords k INNER JOIN music_keywords mk ON k.id = mk.kid
+      WHERE mk.mid = old.mid)
+  WHERE rowid = old.mid;
+END;
+
+CREATE TRIGGER music_search_keyword_update AFTER UPDATE ON keywords BEGIN
+  UPDATE music_search SET keywords =
+    (SELECT group_concat(coalesce(k.category || ' ', '') || k.keyword, ' ')
+      FROM keywords k INNER JOIN music_keywords mk ON k.id = mk.kid
+      WHERE mk.mid = music_search.rowid)
+  WHERE rowid IN (SELECT mid FROM music_keywords WHERE kid = new.id);
+END;
@@ -0,0 +1,13 @@
+-- Physical copies of a piece. A piece can have several rows, one for each part (or set) owned.
+CREATE TABLE `copies` (
+  `id` integer PRIMARY KEY,
+  `mid` integer NOT NULL,
+  `part` string,
+  `count` integer NOT NULL DEFAULT 1,
+  `location` string,
+  `condition` string,
+  `accession` string UNIQUE,
+  FOREIGN KEY(mid) REFERENCES music(id) ON DELETE CASCADE
+);
+
+CREATE INDEX `copies_mid` ON `copies` (`mid`);
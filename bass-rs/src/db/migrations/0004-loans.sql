@@ -0,0 +1,22 @@
+CREATE TABLE `borrowers` (
+  `id` integer PRIMARY KEY,
+  `name` string NOT NULL UNIQUE,
+  `contact` string,
+  `notes` string
+);
+
+-- Dates are ISO 8601 (YYYY-MM-DD) so they compare correctly as text and with date('now').
+CREATE TABLE `loans` (
+  `id` integer PRIMARY KEY,
+  `cid` integer NOT NULL,
+  `bid` integer NOT NULL,
+  `count` integer NOT NULL DEFAULT 1,
+  `checked_out` string NOT NULL DEFAULT (date('now')),
+  `due` string,
+  `returned` string,
+  FOREIGN KEY(cid) REFERENCES copies(id) ON DELETE CASCADE,
+  FOREIGN KEY(bid) REFERENCES borrowers(id) ON DELETE RESTRICT
+);
+
+CREATE INDEX `loans_cid` ON `loans` (`cid`);
+CREATE INDEX `loans_outstanding` ON `loans` (`returned`, `due`);
@@ -0,0 +1,21 @@
+-- Concerts and other events. `date` is ISO 8601 (YYYY-MM-DD).
+CREATE TABLE `performances` (
+  `id` integer PRIMARY KEY,
+  `date` string NOT NULL,
+  `venue` string,
+  `ensemble` string,
+  `notes` string
+);
+
+-- The pieces played at a performance, in the order they were played.
+CREATE TABLE `performance_music` (
+  `pid` integer NOT NULL,
+  `position` integer NOT NULL,
+  `mid` integer NOT NULL,
+  PRIMARY KEY (`pid`, `position`),
+  FOREIGN KEY(pid) REFERENCES performances(id) ON DELETE CASCADE,
+  FOREIGN KEY(mid) REFERENCES music(id) ON DELETE CASCADE
+);
+
+CREATE INDEX `performances_date` ON `performances` (`date`);
+CREATE INDEX `performance_music_mid` ON `performance_music` (`mid`);
@@ -0,0 +1,23 @@
+-- Concert programs being planned. `date` is ISO 8601 (YYYY-MM-DD), if it's been set.
+CREATE TABLE `programs` (
+  `id` integer PRIMARY KEY,
+  `name` string NOT NULL,
+  `date` string,
+  `notes` string
+);
+
+-- The items of a program in order. Pieces reference `music`; intermissions and spoken segments
+-- have a label and a duration in seconds instead.
+CREATE TABLE `program_items` (
+  `pid` integer NOT NULL,
+  `position` integer NOT NULL,
+  `kind` string NOT NULL CHECK (`kind` IN ('piece', 'intermission', 'spoken')),
+  `mid` integer,
+  `label` string,
+  `duration` integer,
+  PRIMARY KEY (`pid`, `position`),
+  FOREIGN KEY(pid) REFERENCES programs(id) ON DELETE CASCADE,
+  FOREIGN KEY(mid) REFERENCES music(id) ON DELETE CASCADE
+);
+
+CREATE INDEX `program_items_mid` ON `program_items` (`mid`);
@@ -0,0 +1,14 @@
+-- Files that go with a piece, like scanned scores or reference recordings. A file is either
+-- linked by `path`, which is relative to the collection's attachment folder when it was copied
+-- there, or stored in `data` itself.
+CREATE TABLE `attachments` (
+  `id` integer PRIMARY KEY,
+  `mid` integer NOT NULL,
+  `kind` string NOT NULL DEFAULT 'other',
+  `label` string,
+  `path` string,
+  `data` blob,
+  FOREIGN KEY(mid) REFERENCES music(id) ON DELETE CASCADE
+);
+
+CREATE INDEX `attachments_mid` ON `attachments` (`mid`);
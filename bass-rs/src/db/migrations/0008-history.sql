@@ -0,0 +1,83 @@
+-- Every change made to a piece, its keywords and its copies, filled in by the triggers below.
+-- `mid` deliberately isn't a foreign key, so the history of a deleted piece survives it.
+--
+-- `target` is what changed: 'music', 'keyword' or 'copy'. `field` is the column for changes to
+-- a piece, and `ref` is the keyword or copy id for the others. Whole rows are stored as JSON
+-- objects, single columns as they were.
+CREATE TABLE `history` (
+  `id` integer PRIMARY KEY,
+  `changed_at` string NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%S', 'now')),
+  `mid` integer NOT NULL,
+  `target` string NOT NULL CHECK (`target` IN ('music', 'keyword', 'copy')),
+  `action` string NOT NULL CHECK (`action` IN ('insert', 'update', 'delete')),
+  `field` string,
+  `ref` integer,
+  `old_value`,
+  `new_value`
+);
+
+CREATE INDEX `history_mid` ON `history` (`mid`, `id`);
+
+CREATE TRIGGER history_music_insert AFTER INSERT ON music BEGIN
+  INSERT INTO history (mid, target, action, new_value)
+    VALUES (new.id, 'music', 'insert', json_object(
+      'id', new.id, 'title', new.title, 'source', new.source, 'composer', new.composer,
+      'arranger', new.arranger, 'notes', new.notes, 'runtime', new.runtime));
+END;
+
+CREATE TRIGGER history_music_update AFTER UPDATE ON music BEGIN
+  INSERT INTO history (mid, target, action, field, old_value, new_value)
+    SELECT new.id, 'music', 'update', field, old_value, new_value FROM (
+      SELECT 'title' AS field, old.title AS old_value, new.title AS new_value
+      UNION ALL SELECT 'source', old.source, new.source
+      UNION ALL SELECT 'composer', old.composer, new.composer
+      UNION ALL SELECT 'arranger', old.arranger, new.arranger
+      UNION ALL SELECT 'notes', old.notes, new.notes
+      UNION ALL SELECT 'runtime', old.runtime, new.runtime
+    ) WHERE old_value IS NOT new_value;
+END;
+
+CREATE TRIGGER history_music_delete AFTER DELETE ON music BEGIN
+  INSERT INTO history (mid, target, action, old_value)
+    VALUES (old.id, 'music', 'delete', json_object(
+      'id', old.id, 'title', old.title, 'source', old.source, 'composer', old.composer,
+      'arranger', old.arranger, 'notes', old.notes, 'runtime', old.runtime));
+END;
+
+CREATE TRIGGER history_keyword_link AFTER INSERT ON music_keywords BEGIN
+  INSERT INTO history (mid, target, action, ref, new_value)
+    VALUES (new.mid, 'keyword', 'insert', new.kid,
+      (SELECT coalesce(category || ':', '') || keyword FROM keywords WHERE id = new.kid));
+END;
+
+CREATE TRIGGER history_keyword_unlink AFTER DELETE ON music_keywords BEGIN
+  INSERT INTO history (mid, target, action, ref, old_value)
+    VALUES (old.mid, 'keyword', 'delete', old.kid,
+      (SELECT coalesce(category || ':', '') || keyword FROM keywords WHERE id = old.kid));
+END;
+
+CREATE TRIGGER history_copy_insert AFTER INSERT ON copies BEGIN
+  INSERT INTO history (mid, target, action, ref, new_value)
+    VALUES (new.mid, 'copy', 'insert', new.id, json_object(
+      'id', new.id, 'mid', new.mid, 'part', new.part, 'count', new.count, 'location', new.location,
+      'condition', new.condition, 'accession', new.accession));
+END;
+
+CREATE TRIGGER history_copy_update AFTER UPDATE ON copies
+WHEN old.part IS NOT new.part OR old.count IS NOT new.count OR old.location IS NOT new.location
+  OR old.condition IS NOT new.condition OR old.accession IS NOT new.accession
+BEGIN
+  INSERT INTO history (mid, target, action, ref, old_value, new_value)
+    VALUES (new.mid, 'copy', 'update', new.id, json_object(
+      'id', old.id, 'mid', old.mid, 'part', old.part, 'count', old.count, 'location', old.location,
+      'condition', old.condition, 'accession', old.accession), json_object(
+      'id', new.id, 'mid', new.mid, 'part', new.part, 'count', new.count, 'location', new.location,
+      'condition', new.condition, 'accession', new.accession));
+END;
+
+CREATE TRIGGER history_copy_delete AFTER DELETE ON copies BEGIN
+  INSERT INTO history (mid, target, action, ref, old_value)
+    VALUES (old.mid, 'copy', 'delete', old.id, json_object(
+      'id', old.id, 'mid', old.mid, 'part', old.part, 'count', old.count, 'location', old.location,
+      'condition', old.condition, 'accession', old.accession));
+END;
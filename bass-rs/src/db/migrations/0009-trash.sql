@@ -0,0 +1,20 @@
+-- Deleting a piece moves it to the trash by setting `deleted_at`; it's only removed for good when
+-- the trash is emptied.
+ALTER TABLE `music` ADD COLUMN `deleted_at` string;
+
+CREATE INDEX `music_deleted_at` ON `music` (`deleted_at`);
+
+-- Record trashing and restoring along with every other change to a piece
+DROP TRIGGER history_music_update;
+CREATE TRIGGER history_music_update AFTER UPDATE ON music BEGIN
+  INSERT INTO history (mid, target, action, field, old_value, new_value)
+    SELECT new.id, 'music', 'update', field, old_value, new_value FROM (
+      SELECT 'title' AS field, old.title AS old_value, new.title AS new_value
+      UNION ALL SELECT 'source', old.source, new.source
+      UNION ALL SELECT 'composer', old.composer, new.composer
+      UNION ALL SELECT 'arranger', old.arranger, new.arranger
+      UNION ALL SELECT 'notes', old.notes, new.notes
+      UNION ALL SELECT 'runtime', old.runtime, new.runtime
+      UNION ALL SELECT 'deleted_at', old.deleted_at, new.deleted_at
+    ) WHERE old_value IS NOT new_value;
+END;
@@ -0,0 +1,45 @@
+-- Composers and arrangers as people, so that one person is one entry however their name gets
+-- spelled. Names and aliases are matched ignoring case. `sort_name` is what lists sort on, like
+-- "Bach, Johann Sebastian"; born and died are years.
+CREATE TABLE `people` (
+  `id` integer PRIMARY KEY,
+  `name` string NOT NULL UNIQUE COLLATE NOCASE,
+  `sort_name` string,
+  `born` integer,
+  `died` integer
+);
+
+-- Other spellings of a person's name, which find the person just like their name does.
+CREATE TABLE `person_aliases` (
+  `pid` integer NOT NULL,
+  `alias` string NOT NULL UNIQUE COLLATE NOCASE,
+  FOREIGN KEY(pid) REFERENCES people(id) ON DELETE CASCADE
+);
+
+CREATE INDEX `person_aliases_pid` ON `person_aliases` (`pid`);
+
+-- `composer` and `arranger` keep the person's name as shown, and are kept in step with these.
+ALTER TABLE `music` ADD COLUMN `composer_id` integer REFERENCES people(id) ON DELETE SET NULL;
+ALTER TABLE `music` ADD COLUMN `arranger_id` integer REFERENCES people(id) ON DELETE SET NULL;
+
+CREATE INDEX `music_composer_id` ON `music` (`composer_id`);
+CREATE INDEX `music_arranger_id` ON `music` (`arranger_id`);
+
+-- One person per distinct name, ignoring case and surrounding space. Spellings that differ by
+-- more than that are left for the people manager to merge.
+INSERT INTO people (name)
+  SELECT min(name) FROM (
+    SELECT trim(composer) AS name FROM music
+    UNION ALL SELECT trim(arranger) FROM music
+  ) WHERE name IS NOT NULL AND name != ''
+  GROUP BY lower(name);
+
+UPDATE music SET
+  composer_id = (SELECT id FROM people WHERE name = trim(music.composer)),
+  composer = (SELECT name FROM people WHERE name = trim(music.composer))
+WHERE composer IS NOT NULL;
+
+UPDATE music SET
+  arranger_id = (SELECT id FROM people WHERE name = trim(music.arranger)),
+  arranger = (SELECT name FROM people WHERE name = trim(music.arranger))
+WHERE arranger IS NOT NULL;
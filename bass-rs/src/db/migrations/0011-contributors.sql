@@ -0,0 +1,15 @@
+-- Credits beyond the composer and arranger, like lyricists, translators, editors and
+-- orchestrators, or a second arranger. `role` is lowercase; `position` orders the credits of a
+-- piece.
+CREATE TABLE `contributors` (
+  `mid` integer NOT NULL,
+  `position` integer NOT NULL,
+  `pid` integer NOT NULL,
+  `role` string NOT NULL,
+  PRIMARY KEY (`mid`, `position`),
+  FOREIGN KEY(mid) REFERENCES music(id) ON DELETE CASCADE,
+  FOREIGN KEY(pid) REFERENCES people(id) ON DELETE CASCADE
+);
+
+CREATE INDEX `contributors_pid` ON `contributors` (`pid`);
+CREATE INDEX `contributors_role` ON `contributors` (`role`);
@@ -0,0 +1,37 @@
+-- The key a piece is written in, like "F♯ minor", and the range of its notes, like "G3–C5".
+-- Both are kept as written so the spelling survives; `key_signature` counts sharps (negative
+-- for flats) and `range_low`/`range_high` count semitones above C0, for searching.
+ALTER TABLE `music` ADD COLUMN `key` string;
+ALTER TABLE `music` ADD COLUMN `key_signature` integer;
+ALTER TABLE `music` ADD COLUMN `range` string;
+ALTER TABLE `music` ADD COLUMN `range_low` integer;
+ALTER TABLE `music` ADD COLUMN `range_high` integer;
+
+CREATE INDEX `music_key` ON `music` (`key`);
+CREATE INDEX `music_range` ON `music` (`range_low`, `range_high`);
+
+-- Record changes to the key and range, and keep them when a piece is deleted for good
+DROP TRIGGER history_music_update;
+CREATE TRIGGER history_music_update AFTER UPDATE ON music BEGIN
+  INSERT INTO history (mid, target, action, field, old_value, new_value)
+    SELECT new.id, 'music', 'update', field, old_value, new_value FROM (
+      SELECT 'title' AS field, old.title AS old_value, new.title AS new_value
+      UNION ALL SELECT 'source', old.source, new.source
+      UNION ALL SELECT 'composer', old.composer, new.composer
+      UNION ALL SELECT 'arranger', old.arranger, new.arranger
+      UNION ALL SELECT 'notes', old.notes, new.notes
+      UNION ALL SELECT 'runtime', old.runtime, new.runtime
+      UNION ALL SELECT 'deleted_at', old.deleted_at, new.deleted_at
+      UNION ALL SELECT 'key', old.key, new.key
+      UNION ALL SELECT 'range', old.range, new.range
+    ) WHERE old_value IS NOT new_value;
+END;
+
+DROP TRIGGER history_music_delete;
+CREATE TRIGGER history_music_delete AFTER DELETE ON music BEGIN
+  INSERT INTO history (mid, target, action, old_value)
+    VALUES (old.id, 'music', 'delete', json_object(
+      'id', old.id, 'title', old.title, 'source', old.source, 'composer', old.composer,
+      'arranger', old.arranger, 'notes', old.notes, 'runtime', old.runtime, 'key', old.key,
+      'range', old.range));
+END;
@@ -0,0 +1,13 @@
+-- The range of each voice or instrument part of a piece, like the soprano line or the tuba
+-- part. Stored like `music.range`: as written, and as semitones above C0 for searching.
+CREATE TABLE `part_ranges` (
+  `mid` integer NOT NULL,
+  `part` string NOT NULL COLLATE NOCASE,
+  `range` string NOT NULL,
+  `range_low` integer NOT NULL,
+  `range_high` integer NOT NULL,
+  PRIMARY KEY (`mid`, `part`),
+  FOREIGN KEY(mid) REFERENCES music(id) ON DELETE CASCADE
+);
+
+CREATE INDEX `part_ranges_part` ON `part_ranges` (`part`, `range_low`, `range_high`);
@@ -0,0 +1,49 @@
+-- Cataloging details that used to end up in keywords or notes. `grade` is a difficulty grade
+-- like 3 or 2.5, and `ismn` is kept as its 13 digits. The rest are `text` rather than `string`,
+-- since SQLite would turn all-digit values like catalog numbers into numbers otherwise.
+ALTER TABLE `music` ADD COLUMN `voicing` text COLLATE NOCASE;
+ALTER TABLE `music` ADD COLUMN `grade` real;
+ALTER TABLE `music` ADD COLUMN `publisher` text COLLATE NOCASE;
+ALTER TABLE `music` ADD COLUMN `catalog_number` text COLLATE NOCASE;
+ALTER TABLE `music` ADD COLUMN `ismn` text;
+ALTER TABLE `music` ADD COLUMN `copyright_year` integer;
+ALTER TABLE `music` ADD COLUMN `language` text COLLATE NOCASE;
+
+CREATE INDEX `music_voicing` ON `music` (`voicing`);
+CREATE INDEX `music_publisher` ON `music` (`publisher`);
+CREATE INDEX `music_ismn` ON `music` (`ismn`);
+
+-- Record changes to the new fields, and keep them when a piece is deleted for good
+DROP TRIGGER history_music_update;
+CREATE TRIGGER history_music_update AFTER UPDATE ON music BEGIN
+  INSERT INTO history (mid, target, action, field, old_value, new_value)
+    SELECT new.id, 'music', 'update', field, old_value, new_value FROM (
+      SELECT 'title' AS field, old.title AS old_value, new.title AS new_value
+      UNION ALL SELECT 'source', old.source, new.source
+      UNION ALL SELECT 'composer', old.composer, new.composer
+      UNION ALL SELECT 'arranger', old.arranger, new.arranger
+      UNION ALL SELECT 'notes', old.notes, new.notes
+      UNION ALL SELECT 'runtime', old.runtime, new.runtime
+      UNION ALL SELECT 'deleted_at', old.deleted_at, new.deleted_at
+      UNION ALL SELECT 'key', old.key, new.key
+      UNION ALL SELECT 'range', old.range, new.range
+      UNION ALL SELECT 'voicing', old.voicing, new.voicing
+      UNION ALL SELECT 'grade', old.grade, new.grade
+      UNION ALL SELECT 'publisher', old.publisher, new.publisher
+      UNION ALL SELECT 'catalog_number', old.catalog_number, new.catalog_number
+      UNION ALL SELECT 'ismn', old.ismn, new.ismn
+      UNION ALL SELECT 'copyright_year', old.copyright_year, new.copyright_year
+      UNION ALL SELECT 'language', old.language, new.language
+    ) WHERE old_value IS NOT new_value;
+END;
+
+DROP TRIGGER history_music_delete;
+CREATE TRIGGER history_music_delete AFTER DELETE ON music BEGIN
+  INSERT INTO history (mid, target, action, old_value)
+    VALUES (old.id, 'music', 'delete', json_object(
+      'id', old.id, 'title', old.title, 'source', old.source, 'composer', old.composer,
+      'arranger', old.arranger, 'notes', old.notes, 'runtime', old.runtime, 'key', old.key,
+      'range', old.range, 'voicing', old.voicing, 'grade', old.grade, 'publisher', old.publisher,
+      'catalog_number', old.catalog_number, 'ismn', old.ismn, 'copyright_year', old.copyright_year,
+      'language', old.language));
+END;
@@ -0,0 +1,20 @@
+-- Fields each library defines for itself. `kind` is one of text, number, date, choice or
+-- yes/no, and `choices` holds the allowed values of a choice field, one per line.
+CREATE TABLE `custom_fields` (
+  `id` integer PRIMARY KEY,
+  `name` text NOT NULL UNIQUE COLLATE NOCASE,
+  `kind` text NOT NULL,
+  `choices` text
+);
+
+-- `value` has no type, so numbers stay numbers and dates and text stay text
+CREATE TABLE `custom_values` (
+  `mid` integer NOT NULL,
+  `fid` integer NOT NULL,
+  `value` NOT NULL,
+  PRIMARY KEY (`mid`, `fid`),
+  FOREIGN KEY(mid) REFERENCES music(id) ON DELETE CASCADE,
+  FOREIGN KEY(fid) REFERENCES custom_fields(id) ON DELETE CASCADE
+);
+
+CREATE INDEX `custom_values_field` ON `custom_values` (`fid`, `value`);
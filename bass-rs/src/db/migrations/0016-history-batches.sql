@@ -0,0 +1,213 @@
+-- Ties whatever a permanent delete takes with it to the delete itself, and starts recording
+-- changes to credits, part ranges and custom values.
+--
+-- `batch` is the id of a piece's 'delete' change, on the rows its cascade removed. The delete is
+-- recorded before the row goes so the cascade can find it, along with `lost`: whether loans,
+-- performances, program slots or attachments went too, which history can't bring back.
+--
+-- New targets, with `ref` as noted:
+--   'contributor'   a credit; `ref` is its position
+--   'part_range'    the range of one part
+--   'custom_value'  a custom field's value; `ref` is the field's id
+--
+-- The table is rebuilt to widen the check on `target`, so every trigger that writes to it is
+-- dropped first and made again below.
+DROP TRIGGER history_music_insert;
+DROP TRIGGER history_music_update;
+DROP TRIGGER history_music_delete;
+DROP TRIGGER history_keyword_link;
+DROP TRIGGER history_keyword_unlink;
+DROP TRIGGER history_copy_insert;
+DROP TRIGGER history_copy_update;
+DROP TRIGGER history_copy_delete;
+
+CREATE TABLE `history_new` (
+  `id` integer PRIMARY KEY,
+  `changed_at` string NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%S', 'now')),
+  `mid` integer NOT NULL,
+  `target` string NOT NULL CHECK (`target` IN ('music', 'keyword', 'copy', 'contributor', 'part_range', 'custom_value')),
+  `action` string NOT NULL CHECK (`action` IN ('insert', 'update', 'delete')),
+  `field` string,
+  `ref` integer,
+  `old_value`,
+  `new_value`,
+  `batch` integer
+);
+
+INSERT INTO history_new (id, changed_at, mid, target, action, field, ref, old_value, new_value)
+  SELECT id, changed_at, mid, target, action, field, ref, old_value, new_value FROM history;
+DROP TABLE history;
+ALTER TABLE history_new RENAME TO history;
+
+CREATE INDEX `history_mid` ON `history` (`mid`, `id`);
+CREATE INDEX `history_batch` ON `history` (`batch`) WHERE `batch` IS NOT NULL;
+
+-- Deletes recorded before now can only be matched up the way undo used to: removed in the same
+-- second as their piece, just before it
+UPDATE history SET batch = (
+  SELECT min(d.id) FROM history d
+  WHERE d.mid = history.mid AND d.target = 'music' AND d.action = 'delete'
+    AND d.changed_at = history.changed_at AND d.id > history.id
+) WHERE action = 'delete' AND target IN ('keyword', 'copy');
+
+CREATE TRIGGER history_music_insert AFTER INSERT ON music BEGIN
+  INSERT INTO history (mid, target, action, new_value)
+    VALUES (new.id, 'music', 'insert', json_object(
+      'id', new.id, 'title', new.title, 'source', new.source, 'composer', new.composer,
+      'arranger', new.arranger, 'notes', new.notes, 'runtime', new.runtime));
+END;
+
+CREATE TRIGGER history_music_update AFTER UPDATE ON music BEGIN
+  INSERT INTO history (mid, target, action, field, old_value, new_value)
+    SELECT new.id, 'music', 'update', field, old_value, new_value FROM (
+      SELECT 'title' AS field, old.title AS old_value, new.title AS new_value
+      UNION ALL SELECT 'source', old.source, new.source
+      UNION ALL SELECT 'composer', old.composer, new.composer
+      UNION ALL SELECT 'arranger', old.arranger, new.arranger
+      UNION ALL SELECT 'notes', old.notes, new.notes
+      UNION ALL SELECT 'runtime', old.runtime, new.runtime
+      UNION ALL SELECT 'deleted_at', old.deleted_at, new.deleted_at
+      UNION ALL SELECT 'key', old.key, new.key
+      UNION ALL SELECT 'range', old.range, new.range
+      UNION ALL SELECT 'voicing', old.voicing, new.voicing
+      UNION ALL SELECT 'grade', old.grade, new.grade
+      UNION ALL SELECT 'publisher', old.publisher, new.publisher
+      UNION ALL SELECT 'catalog_number', old.catalog_number, new.catalog_number
+      UNION ALL SELECT 'ismn', old.ismn, new.ismn
+      UNION ALL SELECT 'copyright_year', old.copyright_year, new.copyright_year
+      UNION ALL SELECT 'language', old.language, new.language
+    ) WHERE old_value IS NOT new_value;
+END;
+
+CREATE TRIGGER history_music_delete BEFORE DELETE ON music BEGIN
+  INSERT INTO history (mid, target, action, old_value)
+    VALUES (old.id, 'music', 'delete', json_object(
+      'id', old.id, 'title', old.title, 'source', old.source, 'composer', old.composer,
+      'arranger', old.arranger, 'notes', old.notes, 'runtime', old.runtime, 'key', old.key,
+      'range', old.range, 'voicing', old.voicing, 'grade', old.grade, 'publisher', old.publisher,
+      'catalog_number', old.catalog_number, 'ismn', old.ismn, 'copyright_year', old.copyright_year,
+      'language', old.language,
+      'lost', EXISTS (SELECT 1 FROM loans JOIN copies ON copies.id = loans.cid WHERE copies.mid = old.id)
+        OR EXISTS (SELECT 1 FROM performance_music WHERE mid = old.id)
+        OR EXISTS (SELECT 1 FROM program_items WHERE mid = old.id)
+        OR EXISTS (SELECT 1 FROM attachments WHERE mid = old.id)));
+END;
+
+-- The cascade runs after the piece's row is gone, so a missing piece means this row went with it
+CREATE TRIGGER history_keyword_link AFTER INSERT ON music_keywords BEGIN
+  INSERT INTO history (mid, target, action, ref, new_value)
+    VALUES (new.mid, 'keyword', 'insert', new.kid,
+      (SELECT coalesce(category || ':', '') || keyword FROM keywords WHERE id = new.kid));
+END;
+
+CREATE TRIGGER history_keyword_unlink AFTER DELETE ON music_keywords BEGIN
+  INSERT INTO history (mid, target, action, ref, old_value, batch)
+    VALUES (old.mid, 'keyword', 'delete', old.kid,
+      (SELECT coalesce(category || ':', '') || keyword FROM keywords WHERE id = old.kid),
+      CASE WHEN NOT EXISTS (SELECT 1 FROM music WHERE id = old.mid) THEN
+        (SELECT max(id) FROM history WHERE mid = old.mid AND target = 'music' AND action = 'delete') END);
+END;
+
+CREATE TRIGGER history_copy_insert AFTER INSERT ON copies BEGIN
+  INSERT INTO history (mid, target, action, ref, new_value)
+    VALUES (new.mid, 'copy', 'insert', new.id, json_object(
+      'id', new.id, 'mid', new.mid, 'part', new.part, 'count', new.count, 'location', new.location,
+      'condition', new.condition, 'accession', new.accession));
+END;
+
+CREATE TRIGGER history_copy_update AFTER UPDATE ON copies
+WHEN old.part IS NOT new.part OR old.count IS NOT new.count OR old.location IS NOT new.location
+  OR old.condition IS NOT new.condition OR old.accession IS NOT new.accession
+BEGIN
+  INSERT INTO history (mid, target, action, ref, old_value, new_value)
+    VALUES (new.mid, 'copy', 'update', new.id, json_object(
+      'id', old.id, 'mid', old.mid, 'part', old.part, 'count', old.count, 'location', old.location,
+      'condition', old.condition, 'accession', old.accession), json_object(
+      'id', new.id, 'mid', new.mid, 'part', new.part, 'count', new.count, 'location', new.location,
+      'condition', new.condition, 'accession', new.accession));
+END;
+
+CREATE TRIGGER history_copy_delete AFTER DELETE ON copies BEGIN
+  INSERT INTO history (mid, target, action, ref, old_value, batch)
+    VALUES (old.mid, 'copy', 'delete', old.id, json_object(
+      'id', old.id, 'mid', old.mid, 'part', old.part, 'count', old.count, 'location', old.location,
+      'condition', old.condition, 'accession', old.accession),
+      CASE WHEN NOT EXISTS (SELECT 1 FROM music WHERE id = old.mid) THEN
+        (SELECT max(id) FROM history WHERE mid = old.mid AND target = 'music' AND action = 'delete') END);
+END;
+
+-- Credits keep the person's name, since people can be merged or deleted later
+CREATE TRIGGER history_contributor_insert AFTER INSERT ON contributors BEGIN
+  INSERT INTO history (mid, target, action, ref, new_value)
+    VALUES (new.mid, 'contributor', 'insert', new.position, json_object(
+      'position', new.position, 'role', new.role, 'name', (SELECT name FROM people WHERE id = new.pid)));
+END;
+
+CREATE TRIGGER history_contributor_update AFTER UPDATE ON contributors
+WHEN old.pid IS NOT new.pid OR old.role IS NOT new.role
+BEGIN
+  INSERT INTO history (mid, target, action, ref, old_value, new_value)
+    VALUES (new.mid, 'contributor', 'update', new.position, json_object(
+      'position', old.position, 'role', old.role, 'name', (SELECT name FROM people WHERE id = old.pid)), json_object(
+      'position', new.position, 'role', new.role, 'name', (SELECT name FROM people WHERE id = new.pid)));
+END;
+
+CREATE TRIGGER history_contributor_delete AFTER DELETE ON contributors BEGIN
+  INSERT INTO history (mid, target, action, ref, old_value, batch)
+    VALUES (old.mid, 'contributor', 'delete', old.position, json_object(
+      'position', old.position, 'role', old.role, 'name', (SELECT name FROM people WHERE id = old.pid)),
+      CASE WHEN NOT EXISTS (SELECT 1 FROM music WHERE id = old.mid) THEN
+        (SELECT max(id) FROM history WHERE mid = old.mid AND target = 'music' AND action = 'delete') END);
+END;
+
+CREATE TRIGGER history_part_range_insert AFTER INSERT ON part_ranges BEGIN
+  INSERT INTO history (mid, target, action, new_value)
+    VALUES (new.mid, 'part_range', 'insert', json_object('part', new.part, 'range', new.range));
+END;
+
+CREATE TRIGGER history_part_range_update AFTER UPDATE ON part_ranges
+WHEN old.part IS NOT new.part OR old.range IS NOT new.range
+BEGIN
+  INSERT INTO history (mid, target, action, old_value, new_value)
+    VALUES (new.mid, 'part_range', 'update', json_object('part', old.part, 'range', old.range),
+      json_object('part', new.part, 'range', new.range));
+END;
+
+CREATE TRIGGER history_part_range_delete AFTER DELETE ON part_ranges BEGIN
+  INSERT INTO history (mid, target, action, old_value, batch)
+    VALUES (old.mid, 'part_range', 'delete', json_object('part', old.part, 'range', old.range),
+      CASE WHEN NOT EXISTS (SELECT 1 FROM music WHERE id = old.mid) THEN
+        (SELECT max(id) FROM history WHERE mid = old.mid AND target = 'music' AND action = 'delete') END);
+END;
+
+-- `shown` is the value as people read it, with yes/no fields spelled out
+CREATE TRIGGER history_custom_value_insert AFTER INSERT ON custom_values BEGIN
+  INSERT INTO history (mid, target, action, ref, new_value)
+    SELECT new.mid, 'custom_value', 'insert', new.fid, json_object('field', f.name, 'value', new.value,
+      'shown', CASE WHEN f.kind = 'yes/no' THEN iif(new.value, 'yes', 'no') ELSE new.value END)
+    FROM custom_fields f WHERE f.id = new.fid;
+END;
+
+CREATE TRIGGER history_custom_value_update AFTER UPDATE ON custom_values
+WHEN old.value IS NOT new.value
+BEGIN
+  INSERT INTO history (mid, target, action, ref, old_value, new_value)
+    SELECT new.mid, 'custom_value', 'update', new.fid,
+      json_object('field', f.name, 'value', old.value,
+        'shown', CASE WHEN f.kind = 'yes/no' THEN iif(old.value, 'yes', 'no') ELSE old.value END),
+      json_object('field', f.name, 'value', new.value,
+        'shown', CASE WHEN f.kind = 'yes/no' THEN iif(new.value, 'yes', 'no') ELSE new.value END)
+    FROM custom_fields f WHERE f.id = new.fid;
+END;
+
+-- Values removed along with their field aren't recorded: there's no field to put them back in
+CREATE TRIGGER history_custom_value_delete AFTER DELETE ON custom_values
+WHEN EXISTS (SELECT 1 FROM custom_fields WHERE id = old.fid)
+BEGIN
+  INSERT INTO history (mid, target, action, ref, old_value, batch)
+    SELECT old.mid, 'custom_value', 'delete', old.fid, json_object('field', f.name, 'value', old.value,
+      'shown', CASE WHEN f.kind = 'yes/no' THEN iif(old.value, 'yes', 'no') ELSE old.value END),
+      CASE WHEN NOT EXISTS (SELECT 1 FROM music WHERE id = old.mid) THEN
+        (SELECT max(id) FROM history WHERE mid = old.mid AND target = 'music' AND action = 'delete') END
+    FROM custom_fields f WHERE f.id = old.fid;
+END;
@@ -0,0 +1,123 @@
+use rusqlite::{named_params, Row};
+
+use crate::theory::Range;
+
+use super::{Database, Music, Result};
+
+/// The range of one voice or instrument part of a piece, like its soprano line or tuba part.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartRange {
+    pub part: String,
+    pub range: Range,
+}
+
+impl PartRange {
+    // Rows whose range no longer parses are left out rather than failing the whole list.
+    fn from_row(row: &Row) -> Option<PartRange> {
+        let range: String = row.get_unwrap(1);
+        Some(PartRange {
+            part: row.get_unwrap(0),
+            range: range.parse().ok()?,
+        })
+    }
+
+    pub fn new(part: &str, range: Range) -> PartRange {
+        PartRange {
+            part: part.trim().into(),
+            range,
+        }
+    }
+}
+
+impl std::fmt::Display for PartRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.part, self.range)
+    }
+}
+
+impl Music {
+    /// The ranges recorded for the piece's parts, by part name.
+    pub fn part_ranges(&self, db: &Database) -> Result<Option<Vec<PartRange>>> {
+        if self.id.is_none() {
+            return Ok(None);
+        }
+        let ranges = db.query("SELECT part, range FROM part_ranges WHERE mid = :id ORDER BY part;",
+            named_params!{":id": self.id}, |row| Ok(PartRange::from_row(row)))?;
+        Ok(Some(ranges.into_iter().flatten().collect()))
+    }
+
+    /// Records the range of `part`, replacing any range it had. Part names are compared
+    /// ignoring case.
+    pub fn set_part_range(&self, db: &Database, part_range: &PartRange) -> Result<()> {
+        db.execute("INSERT INTO part_ranges (mid, part, range, range_low, range_high)
+            VALUES (:mid, :part, :range, :low, :high)
+            ON CONFLICT (mid, part) DO UPDATE SET
+                part = excluded.part,
+                range = excluded.range,
+                range_low = excluded.range_low,
+                range_high = excluded.range_high;", named_params!{
+            ":mid": self.id,
+            ":part": part_range.part,
+            ":range": part_range.range.to_string(),
+            ":low": part_range.range.low().number(),
+            ":high": part_range.range.high().number(),
+        })?;
+        Ok(())
+    }
+
+    pub fn remove_part_range(&self, db: &Database, part: &str) -> Result<()> {
+        db.execute("DELETE FROM part_ranges WHERE mid = :mid AND part = :part;",
+            named_params!{":mid": self.id, ":part": part.trim()})?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn titles(db: &Database, query: &crate::db::MusicQuery) -> Vec<String> {
+        let mut titles: Vec<_> = query.run(db).unwrap().into_iter().map(|m| m.title).collect();
+        titles.sort();
+        titles
+    }
+
+    fn piece(db: &Database, title: &str, ranges: &[(&str, &str)]) -> Music {
+        let mut music = Music::new();
+        music.title = title.into();
+        music.insert(db).unwrap();
+        for (part, range) in ranges {
+            music.set_part_range(db, &PartRange::new(part, range.parse().unwrap())).unwrap();
+        }
+        music
+    }
+
+    #[test]
+    fn each_part_has_one_range() {
+        let db = Database::open_in_memory().unwrap();
+        let music = piece(&db, "Hallelujah Chorus", &[("Soprano", "D4-A5"), ("Tenor", "D3-A4"), ("soprano", "D4-G5")]);
+        let ranges = music.part_ranges(&db).unwrap().unwrap();
+        assert_eq!(ranges.iter().map(|r| r.to_string()).collect::<Vec<_>>(), ["soprano: D4–G5", "Tenor: D3–A4"]);
+
+        music.remove_part_range(&db, " Tenor ").unwrap();
+        assert_eq!(music.part_ranges(&db).unwrap().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn parts_are_searched_by_their_own_range() {
+        let db = Database::open_in_memory().unwrap();
+        piece(&db, "Messiah", &[("Soprano", "D4-A5"), ("Bass", "G2-D4")]);
+        piece(&db, "Lullaby", &[("Soprano", "C4-D5"), ("Tuba", "F1-F3")]);
+        piece(&db, "Fanfare", &[]);
+        let pitch = |s: &str| -> crate::theory::Pitch { s.parse().unwrap() };
+
+        assert_eq!(titles(&db, Music::query().has_part_range("soprano")), ["Lullaby", "Messiah"]);
+        assert_eq!(titles(&db, Music::query().part_high_le("Soprano", &pitch("E5"))), ["Lullaby"]);
+        assert_eq!(titles(&db, Music::query().part_high_gt("Soprano", &pitch("E5"))), ["Messiah"]);
+        assert_eq!(titles(&db, Music::query().part_low_lt("Bass", &pitch("A2"))), ["Messiah"]);
+        assert_eq!(titles(&db, Music::query().part_range_within("Tuba", &"F1-F3".parse().unwrap())), ["Lullaby"]);
+        assert_eq!(titles(&db, Music::query().part_range_includes("Soprano", &pitch("A5"))), ["Messiah"]);
+        // Another part reaching the note doesn't count
+        assert!(titles(&db, Music::query().part_low_ge("Tuba", &pitch("G2"))).is_empty());
+    }
+}
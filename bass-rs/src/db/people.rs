@@ -0,0 +1,372 @@
+use rusqlite::{named_params, Row};
+
+use super::{Database, Music, Result};
+use super::duplicates::{name_similarity, normalize};
+
+/// A composer, arranger or anyone else credited on a piece.
+#[derive(Clone, Debug)]
+pub struct Person {
+    id: Option<i32>,
+    /// How the person is shown, like "Johann Sebastian Bach".
+    pub name: String,
+    /// How the person is sorted, like "Bach, Johann Sebastian". Falls back to `name`.
+    pub sort_name: Option<String>,
+    pub born: Option<i32>,
+    pub died: Option<i32>,
+}
+
+#[allow(dead_code)]
+impl Person {
+    fn from_row(row: &Row) -> Person {
+        Person {
+            id: row.get_unwrap(0),
+            name: row.get_unwrap(1),
+            sort_name: row.get_unwrap(2),
+            born: row.get_unwrap(3),
+            died: row.get_unwrap(4),
+        }
+    }
+
+    pub fn new(name: &str) -> Person {
+        Person {
+            id: None,
+            name: name.trim().into(),
+            sort_name: None,
+            born: None,
+            died: None,
+        }
+    }
+
+    pub fn id(&self) -> Option<i32> {
+        self.id
+    }
+
+    /// Everyone, in sort name order.
+    pub fn list_all(db: &Database) -> Result<Vec<Person>> {
+        db.query("SELECT * FROM people ORDER BY coalesce(sort_name, name) COLLATE NOCASE;", (), |row| {
+            Ok(Person::from_row(row))
+        })
+    }
+
+    /// Everyone, in sort name order, with how many pieces credit them. Pieces in the trash
+    /// don't count.
+    pub fn usage_counts(db: &Database) -> Result<Vec<(Person, usize)>> {
+        db.query("SELECT p.*, (SELECT count(*) FROM music m WHERE m.deleted_at IS NULL
+                AND (m.composer_id = p.id OR m.arranger_id = p.id
+                    OR EXISTS (SELECT 1 FROM contributors c WHERE c.mid = m.id AND c.pid = p.id)))
+            FROM people p
+            ORDER BY coalesce(p.sort_name, p.name) COLLATE NOCASE;", (), |row| {
+                Ok((Person::from_row(row), row.get::<_, i64>(5)? as usize))
+            })
+    }
+
+    pub fn by_id(db: &Database, id: i32) -> Result<Option<Person>> {
+        db.query_row("SELECT * FROM people WHERE id = :id;", named_params!{":id": id}, |row| {
+            Ok(Person::from_row(row))
+        })
+    }
+
+    /// Finds the person called `name`, or with `name` as an alias, ignoring case.
+    pub fn by_name(db: &Database, name: &str) -> Result<Option<Person>> {
+        db.query_row("SELECT * FROM people WHERE name = :name
+            OR id IN (SELECT pid FROM person_aliases WHERE alias = :name)
+            ORDER BY name != :name LIMIT 1;", named_params!{":name": name.trim()}, |row| {
+            Ok(Person::from_row(row))
+        })
+    }
+
+    /// Finds the person called `name`, adding them if they aren't in the database yet.
+    pub fn find_or_insert(db: &Database, name: &str) -> Result<Person> {
+        db.transaction(|db| {
+            if let Some(person) = Person::by_name(db, name)? {
+                return Ok(person);
+            }
+            let mut person = Person::new(name);
+            person.insert(db)?;
+            Ok(person)
+        })
+    }
+
+    pub fn aliases(&self, db: &Database) -> Result<Vec<String>> {
+        db.query("SELECT alias FROM person_aliases WHERE pid = :id ORDER BY alias COLLATE NOCASE;",
+            named_params!{":id": self.id}, |row| row.get(0))
+    }
+
+    /// Replaces the person's aliases with `aliases`. Their own name is never kept as an alias.
+    pub fn set_aliases(&self, db: &Database, aliases: &[String]) -> Result<()> {
+        db.transaction(|db| {
+            db.execute("DELETE FROM person_aliases WHERE pid = :id;", named_params!{":id": self.id})?;
+            for alias in aliases.iter().map(|a| a.trim()).filter(|a| !a.is_empty()) {
+                if alias.eq_ignore_ascii_case(&self.name) {
+                    continue;
+                }
+                // An alias belongs to one person, so it moves here from whoever had it
+                db.execute("INSERT INTO person_aliases (pid, alias) VALUES (:id, :alias)
+                    ON CONFLICT (alias) DO UPDATE SET pid = excluded.pid;", named_params!{
+                        ":id": self.id,
+                        ":alias": alias,
+                    })?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Other people whose names score at least `threshold` against this person's name or
+    /// aliases, most alike first. These are candidates for [`Person::merge_into`].
+    pub fn similar(&self, db: &Database, threshold: f64) -> Result<Vec<(Person, f64)>> {
+        let mut names = self.aliases(db)?;
+        names.push(self.name.clone());
+        let names: Vec<String> = names.iter().map(|n| normalize(n)).collect();
+        let mut found = Vec::new();
+        for person in Person::list_all(db)? {
+            if person.id == self.id {
+                continue;
+            }
+            let mut theirs = person.aliases(db)?;
+            theirs.push(person.name.clone());
+            let score = theirs.iter()
+                .map(|t| normalize(t))
+                .flat_map(|t| names.iter().map(move |n| name_similarity(n, &t)))
+                .fold(0.0, f64::max);
+            if score >= threshold {
+                found.push((person, score));
+            }
+        }
+        found.sort_by(|a, b| b.1.total_cmp(&a.1));
+        Ok(found)
+    }
+
+    /// Saves the person. Renaming someone renames them on every piece that credits them, and
+    /// keeps the old name as an alias.
+    pub fn insert(&mut self, db: &Database) -> Result<()> {
+        self.name = self.name.trim().into();
+        if !self.is_db_entry() {
+            let id = db.insert("INSERT INTO people (name, sort_name, born, died) VALUES (
+                :name,
+                :sort_name,
+                :born,
+                :died
+            );", named_params!{
+                ":name": self.name,
+                ":sort_name": self.sort_name,
+                ":born": self.born,
+                ":died": self.died,
+            })?;
+            self.id = Some(id);
+            Ok(())
+        } else {
+            db.transaction(|db| {
+                let old_name = db.query_row("SELECT name FROM people WHERE id = :id;", named_params!{":id": self.id},
+                    |row| row.get::<_, String>(0))?;
+                db.execute("UPDATE people SET
+                    name = :name,
+                    sort_name = :sort_name,
+                    born = :born,
+                    died = :died
+                WHERE id = :id;", named_params!{
+                    ":id": self.id,
+                    ":name": self.name,
+                    ":sort_name": self.sort_name,
+                    ":born": self.born,
+                    ":died": self.died,
+                })?;
+                // A name is never also an alias, of this person or anyone else
+                db.execute("DELETE FROM person_aliases WHERE alias = :name;", named_params!{":name": self.name})?;
+                if let Some(old_name) = old_name.filter(|n| !n.eq_ignore_ascii_case(&self.name)) {
+                    db.execute("INSERT INTO person_aliases (pid, alias) VALUES (:id, :alias) ON CONFLICT DO NOTHING;",
+                        named_params!{":id": self.id, ":alias": old_name})?;
+                }
+                Person::update_credits(db, self.id, self.id, &self.name)
+            })
+        }
+    }
+
+    // Credits everything credited to `from` to `to`, shown as `name`.
+    fn update_credits(db: &Database, from: Option<i32>, to: Option<i32>, name: &str) -> Result<()> {
+        let params = named_params!{":from": from, ":to": to, ":name": name};
+        db.execute("UPDATE music SET composer_id = :to, composer = :name WHERE composer_id = :from;", params)?;
+        db.execute("UPDATE music SET arranger_id = :to, arranger = :name WHERE arranger_id = :from;", params)?;
+        Ok(())
+    }
+
+    /// Makes this person and `target` one person: everything credited to this person is
+    /// credited to `target`, and this person's name and aliases become aliases of `target`.
+    pub fn merge_into(self, db: &Database, target: &Person) -> Result<()> {
+        if self.id == target.id || !target.is_db_entry() {
+            return Ok(());
+        }
+        db.transaction(|db| {
+            Person::update_credits(db, self.id, target.id, &target.name)?;
+            // Where both were credited in the same role, keep the earlier credit
+            db.execute("DELETE FROM contributors WHERE pid = :from AND EXISTS (SELECT 1 FROM contributors c
+                WHERE c.mid = contributors.mid AND c.role = contributors.role AND c.pid = :to);", named_params!{
+                ":from": self.id,
+                ":to": target.id,
+            })?;
+            db.execute("UPDATE contributors SET pid = :to WHERE pid = :from;", named_params!{
+                ":from": self.id,
+                ":to": target.id,
+            })?;
+            db.execute("UPDATE person_aliases SET pid = :to WHERE pid = :from;", named_params!{
+                ":from": self.id,
+                ":to": target.id,
+            })?;
+            db.execute("DELETE FROM people WHERE id = :id;", named_params!{":id": self.id})?;
+            if !self.name.eq_ignore_ascii_case(&target.name) {
+                db.execute("INSERT INTO person_aliases (pid, alias) VALUES (:id, :alias) ON CONFLICT DO NOTHING;",
+                    named_params!{":id": target.id, ":alias": self.name})?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Merges each pair of people whose names score at least `threshold` against each other and
+    /// against nobody else, keeping whichever is credited on more pieces. Names like several
+    /// people, as "Bach" is like both "J. S. Bach" and "C. P. E. Bach", are left for someone to
+    /// sort out.
+    pub(super) fn merge_unambiguous(db: &Database, threshold: f64) -> Result<()> {
+        db.transaction(|db| {
+            let people = Person::usage_counts(db)?;
+            let mut alike = Vec::with_capacity(people.len());
+            for (person, _) in &people {
+                alike.push(person.similar(db, threshold)?);
+            }
+            let only_match = |i: usize| match &alike[i][..] {
+                [(other, _)] => people.iter().position(|(p, _)| p.id == other.id),
+                _ => None,
+            };
+            let mut merges = Vec::new();
+            for (i, (person, count)) in people.iter().enumerate() {
+                let Some(j) = only_match(i).filter(|j| only_match(*j) == Some(i)) else {
+                    continue;
+                };
+                // When they're credited equally, the one added first stays
+                let (other, other_count) = &people[j];
+                if (other_count, std::cmp::Reverse(other.id)) > (count, std::cmp::Reverse(person.id)) {
+                    merges.push((person.clone(), other.clone()));
+                }
+            }
+            for (person, into) in merges {
+                person.merge_into(db, &into)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Removes the person, and their credit from every piece.
+    pub fn delete(self, db: &Database) -> Result<()> {
+        db.transaction(|db| {
+            db.execute("UPDATE music SET composer = NULL WHERE composer_id = :id;", named_params!{":id": self.id})?;
+            db.execute("UPDATE music SET arranger = NULL WHERE arranger_id = :id;", named_params!{":id": self.id})?;
+            db.execute("DELETE FROM people WHERE id = :id;", named_params!{":id": self.id})?;
+            Ok(())
+        })
+    }
+
+    pub fn is_db_entry(&self) -> bool {
+        self.id.is_some()
+    }
+}
+
+impl std::fmt::Display for Person {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        match (self.born, self.died) {
+            (Some(born), Some(died)) => write!(f, " ({}–{})", born, died),
+            (Some(born), None) => write!(f, " (b. {})", born),
+            (None, Some(died)) => write!(f, " (d. {})", died),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+impl Music {
+    pub fn composer_person(&self, db: &Database) -> Result<Option<Person>> {
+        match self.composer_id {
+            Some(id) => Person::by_id(db, id),
+            None => Ok(None),
+        }
+    }
+
+    pub fn arranger_person(&self, db: &Database) -> Result<Option<Person>> {
+        match self.arranger_id {
+            Some(id) => Person::by_id(db, id),
+            None => Ok(None),
+        }
+    }
+
+    // Points the composer and arranger at the people with those names, adding anyone new, and
+    // shows each under the person's own name rather than whichever alias was typed in.
+    // Expects to run in a transaction.
+    pub(super) fn resolve_people(&mut self, db: &Database) -> Result<()> {
+        let resolve = |name: &mut Option<String>| -> Result<Option<i32>> {
+            match name.as_deref().map(str::trim) {
+                Some(n) if !n.is_empty() => {
+                    let person = Person::find_or_insert(db, n)?;
+                    *name = Some(person.name);
+                    Ok(person.id)
+                }
+                _ => {
+                    *name = None;
+                    Ok(None)
+                }
+            }
+        };
+        self.composer_id = resolve(&mut self.composer)?;
+        self.arranger_id = resolve(&mut self.arranger)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn piece(db: &Database, title: &str, composer: &str) -> Music {
+        let mut music = Music::new();
+        music.title = title.into();
+        music.composer = Some(composer.into());
+        music.insert(db).unwrap();
+        music
+    }
+
+    #[test]
+    fn renamed_people_keep_their_old_name() {
+        let db = Database::open_in_memory().unwrap();
+        let music = piece(&db, "Jesu, Joy of Man's Desiring", "J. S. Bach");
+        let mut bach = music.composer_person(&db).unwrap().unwrap();
+        bach.name = "Johann Sebastian Bach".into();
+        bach.insert(&db).unwrap();
+
+        assert_eq!(bach.aliases(&db).unwrap(), ["J. S. Bach"]);
+        let music = Music::by_id(&db, music.id().unwrap()).unwrap().unwrap();
+        assert_eq!(music.composer.as_deref(), Some("Johann Sebastian Bach"));
+        // Typing the old name finds the same person, and shows their current name
+        let again = piece(&db, "Air on the G String", "j. s. bach");
+        assert_eq!(again.composer_id, bach.id());
+        assert_eq!(again.composer.as_deref(), Some("Johann Sebastian Bach"));
+        assert_eq!(Person::list_all(&db).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn merged_people_take_their_credits_and_aliases_along() {
+        let db = Database::open_in_memory().unwrap();
+        let first = piece(&db, "Sheep May Safely Graze", "Bach, J.S.");
+        let second = piece(&db, "Wachet auf", "Johann Sebastian Bach");
+        let duplicate = first.composer_person(&db).unwrap().unwrap();
+        let bach = second.composer_person(&db).unwrap().unwrap();
+        duplicate.set_aliases(&db, &["JSB".into()]).unwrap();
+
+        let similar = bach.similar(&db, 0.85).unwrap();
+        assert_eq!(similar.iter().map(|(p, _)| p.id()).collect::<Vec<_>>(), [duplicate.id()]);
+
+        duplicate.merge_into(&db, &bach).unwrap();
+        assert_eq!(Person::list_all(&db).unwrap().len(), 1);
+        assert_eq!(bach.aliases(&db).unwrap(), ["Bach, J.S.", "JSB"]);
+        let first = Music::by_id(&db, first.id().unwrap()).unwrap().unwrap();
+        assert_eq!(first.composer_id, bach.id());
+        assert_eq!(first.composer.as_deref(), Some("Johann Sebastian Bach"));
+        assert_eq!(Person::by_name(&db, "JSB").unwrap().unwrap().id(), bach.id());
+        assert_eq!(Person::usage_counts(&db).unwrap()[0].1, 2);
+    }
+}
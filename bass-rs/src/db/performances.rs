@@ -0,0 +1,236 @@
+use rusqlite::{named_params, Row};
+
+use super::{Database, Music, Result};
+
+/// A concert or other event, and the pieces played at it.
+#[derive(Clone, Debug)]
+pub struct Performance {
+    id: Option<i32>,
+    /// ISO 8601 date (`YYYY-MM-DD`).
+    pub date: String,
+    pub venue: Option<String>,
+    pub ensemble: Option<String>,
+    pub notes: Option<String>,
+    /// Ids of the pieces played, in order. Saved along with the rest of the performance by
+    /// [`Performance::insert`].
+    pub music: Vec<i32>,
+}
+
+#[allow(dead_code)]
+impl Performance {
+    fn from_row(row: &Row) -> Performance {
+        Performance {
+            id: row.get_unwrap(0),
+            date: row.get_unwrap(1),
+            venue: row.get_unwrap(2),
+            ensemble: row.get_unwrap(3),
+            notes: row.get_unwrap(4),
+            music: Vec::new(),
+        }
+    }
+
+    // Rows only hold the performance itself, so anything loading one has to fill in its program.
+    fn with_music(mut self, db: &Database) -> Result<Performance> {
+        self.music = db.query("SELECT mid FROM performance_music WHERE pid = :id ORDER BY position;",
+            named_params!{":id": self.id}, |row| row.get(0))?;
+        Ok(self)
+    }
+
+    pub fn new(date: &str) -> Performance {
+        Performance {
+            id: None,
+            date: date.into(),
+            venue: None,
+            ensemble: None,
+            notes: None,
+            music: Vec::new(),
+        }
+    }
+
+    pub fn id(&self) -> Option<i32> {
+        self.id
+    }
+
+    /// Every performance, most recent first.
+    pub fn list_all(db: &Database) -> Result<Vec<Performance>> {
+        db.query("SELECT * FROM performances ORDER BY date DESC, id DESC;", (), |row| {
+            Ok(Performance::from_row(row))
+        })?.into_iter().map(|p| p.with_music(db)).collect()
+    }
+
+    pub fn by_id(db: &Database, id: i32) -> Result<Option<Performance>> {
+        db.query_row("SELECT * FROM performances WHERE id = :id;", named_params!{":id": id}, |row| {
+            Ok(Performance::from_row(row))
+        })?.map(|p| p.with_music(db)).transpose()
+    }
+
+    /// Finds the performance on `date` at `venue` by `ensemble`, or a new unsaved one if there
+    /// isn't one yet.
+    pub fn find_or_new(db: &Database, date: &str, venue: Option<&str>, ensemble: Option<&str>) -> Result<Performance> {
+        let found = db.query_row("SELECT * FROM performances
+            WHERE date = :date AND venue IS :venue AND ensemble IS :ensemble
+            ORDER BY id LIMIT 1;", named_params!{
+                ":date": date,
+                ":venue": venue,
+                ":ensemble": ensemble,
+            }, |row| Ok(Performance::from_row(row)))?;
+        match found {
+            Some(p) => p.with_music(db),
+            None => {
+                let mut p = Performance::new(date);
+                p.venue = venue.map(|v| v.into());
+                p.ensemble = ensemble.map(|e| e.into());
+                Ok(p)
+            }
+        }
+    }
+
+    pub fn list_venues(db: &Database) -> Result<Vec<String>> {
+        db.query("SELECT DISTINCT venue FROM performances WHERE venue IS NOT NULL ORDER BY venue;", (), |row| {
+            row.get(0)
+        })
+    }
+
+    pub fn list_ensembles(db: &Database) -> Result<Vec<String>> {
+        db.query("SELECT DISTINCT ensemble FROM performances WHERE ensemble IS NOT NULL ORDER BY ensemble;", (), |row| {
+            row.get(0)
+        })
+    }
+
+    /// Saves the performance and its program, replacing whatever program was saved before.
+    pub fn insert(&mut self, db: &Database) -> Result<()> {
+        db.transaction_restoring(self, |db, performance| {
+            if !performance.is_db_entry() {
+                let id = db.insert("INSERT INTO performances (date, venue, ensemble, notes) VALUES (
+                    :date,
+                    :venue,
+                    :ensemble,
+                    :notes
+                );", named_params!{
+                    ":date": performance.date,
+                    ":venue": performance.venue,
+                    ":ensemble": performance.ensemble,
+                    ":notes": performance.notes,
+                })?;
+                performance.id = Some(id);
+            } else {
+                db.execute("UPDATE performances SET
+                    date = :date,
+                    venue = :venue,
+                    ensemble = :ensemble,
+                    notes = :notes
+                WHERE id = :id;", named_params!{
+                    ":id": performance.id,
+                    ":date": performance.date,
+                    ":venue": performance.venue,
+                    ":ensemble": performance.ensemble,
+                    ":notes": performance.notes,
+                })?;
+                db.execute("DELETE FROM performance_music WHERE pid = :id;", named_params!{":id": performance.id})?;
+            }
+            for (position, mid) in performance.music.iter().enumerate() {
+                db.execute("INSERT INTO performance_music (pid, position, mid) VALUES (:pid, :position, :mid);", named_params!{
+                    ":pid": performance.id,
+                    ":position": position,
+                    ":mid": mid,
+                })?;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn delete(self, db: &Database) -> Result<()> {
+        db.execute("DELETE FROM performances WHERE id = :id;", named_params!{":id": self.id})?;
+        Ok(())
+    }
+
+    pub fn is_db_entry(&self) -> bool {
+        self.id.is_some()
+    }
+}
+
+impl Music {
+    /// Performances that included this piece, most recent first.
+    pub fn performances(&self, db: &Database) -> Result<Option<Vec<Performance>>> {
+        if self.id.is_none() {
+            return Ok(None);
+        }
+        let performances = db.query("SELECT DISTINCT p.* FROM performances p
+            INNER JOIN performance_music pm ON pm.pid = p.id
+            WHERE pm.mid = :id
+            ORDER BY p.date DESC, p.id DESC;", named_params!{":id": self.id}, |row| {
+            Ok(Performance::from_row(row))
+        })?;
+        Ok(Some(performances.into_iter().map(|p| p.with_music(db)).collect::<Result<_>>()?))
+    }
+
+    /// The date of the most recent performance of this piece, if it has ever been played.
+    pub fn last_performed(&self, db: &Database) -> Result<Option<String>> {
+        if self.id.is_none() {
+            return Ok(None);
+        }
+        let date = db.query_row("SELECT max(p.date) FROM performances p
+            INNER JOIN performance_music pm ON pm.pid = p.id
+            WHERE pm.mid = :id;", named_params!{":id": self.id}, |row| row.get(0))?;
+        Ok(date.flatten())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn piece(db: &Database, title: &str) -> Music {
+        let mut music = Music::new();
+        music.title = title.into();
+        music.insert(db).unwrap();
+        music
+    }
+
+    fn titles(db: &Database, query: &crate::db::MusicQuery) -> Vec<String> {
+        query.run(db).unwrap().into_iter().map(|m| m.title).collect()
+    }
+
+    #[test]
+    fn programs_are_kept_in_order() {
+        let db = Database::open_in_memory().unwrap();
+        let gloria = piece(&db, "Gloria");
+        let magnificat = piece(&db, "Magnificat");
+        let nunc = piece(&db, "Nunc Dimittis");
+
+        let mut evensong = Performance::find_or_new(&db, "2024-12-22", Some("St Mary's"), None).unwrap();
+        evensong.music = vec![magnificat.id().unwrap(), nunc.id().unwrap()];
+        evensong.insert(&db).unwrap();
+        let mut concert = Performance::new("2023-06-01");
+        concert.music = vec![gloria.id().unwrap(), magnificat.id().unwrap()];
+        concert.insert(&db).unwrap();
+
+        let found = Performance::find_or_new(&db, "2024-12-22", Some("St Mary's"), None).unwrap();
+        assert_eq!(found.id(), evensong.id());
+        assert_eq!(found.music, [magnificat.id().unwrap(), nunc.id().unwrap()]);
+        assert!(Performance::find_or_new(&db, "2024-12-22", None, None).unwrap().id().is_none());
+
+        let dates = |m: &Music| m.performances(&db).unwrap().unwrap().into_iter().map(|p| p.date).collect::<Vec<_>>();
+        assert_eq!(dates(&magnificat), ["2024-12-22", "2023-06-01"]);
+        assert_eq!(magnificat.last_performed(&db).unwrap().as_deref(), Some("2024-12-22"));
+        assert_eq!(Performance::list_venues(&db).unwrap(), ["St Mary's"]);
+
+        // Saving again replaces the program rather than adding to it
+        evensong.music = vec![nunc.id().unwrap()];
+        evensong.insert(&db).unwrap();
+        assert_eq!(dates(&magnificat), ["2023-06-01"]);
+        assert_eq!(titles(&db, Music::query().performed_since("2024-01-01")), ["Nunc Dimittis"]);
+        assert_eq!(titles(&db, Music::query().never_performed()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn failed_saves_keep_the_performance_unsaved() {
+        let db = Database::open_in_memory().unwrap();
+        let mut performance = Performance::new("2024-03-01");
+        // No piece has this id, so the program can't be saved
+        performance.music = vec![404];
+        assert!(performance.insert(&db).is_err());
+        assert!(performance.id().is_none());
+        assert!(Performance::list_all(&db).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,290 @@
+use std::fmt::Write;
+
+use rusqlite::{named_params, Row};
+
+use super::{Database, Music, Result};
+
+/// One slot in a [`Program`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProgramItem {
+    /// A piece from the library, by id. Its length is the piece's runtime.
+    Piece(i32),
+    Intermission { label: Option<String>, duration: u16 },
+    /// Announcements, speeches, introductions and the like.
+    Spoken { label: Option<String>, duration: u16 },
+}
+
+impl ProgramItem {
+    fn from_row(row: &Row) -> ProgramItem {
+        let kind: String = row.get_unwrap(0);
+        match kind.as_str() {
+            "piece" => ProgramItem::Piece(row.get_unwrap(1)),
+            "intermission" => ProgramItem::Intermission {
+                label: row.get_unwrap(2),
+                duration: row.get_unwrap::<_, Option<u16>>(3).unwrap_or(0),
+            },
+            _ => ProgramItem::Spoken {
+                label: row.get_unwrap(2),
+                duration: row.get_unwrap::<_, Option<u16>>(3).unwrap_or(0),
+            },
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            ProgramItem::Piece(_) => "piece",
+            ProgramItem::Intermission { .. } => "intermission",
+            ProgramItem::Spoken { .. } => "spoken",
+        }
+    }
+}
+
+/// How long an item of a program runs, and when it ends counting from the start of the program.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timing {
+    /// `None` for pieces without a runtime.
+    pub duration: Option<u16>,
+    /// Seconds from the start of the program, counting unknown durations as zero.
+    pub ends_at: u32,
+}
+
+/// Formats a number of seconds as `m:ss`, or `h:mm:ss` once it reaches an hour.
+pub fn format_runtime(seconds: u32) -> String {
+    let (h, m, s) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if h > 0 {
+        format!("{}:{:02}:{:02}", h, m, s)
+    } else {
+        format!("{}:{:02}", m, s)
+    }
+}
+
+/// An ordered concert program, built from pieces in the library plus intermissions and spoken
+/// segments.
+#[derive(Clone, Debug)]
+pub struct Program {
+    id: Option<i32>,
+    pub name: String,
+    /// ISO 8601 date (`YYYY-MM-DD`) of the concert, if it's been scheduled.
+    pub date: Option<String>,
+    pub notes: Option<String>,
+    /// Saved along with the rest of the program by [`Program::insert`].
+    pub items: Vec<ProgramItem>,
+}
+
+#[allow(dead_code)]
+impl Program {
+    fn from_row(row: &Row) -> Program {
+        Program {
+            id: row.get_unwrap(0),
+            name: row.get_unwrap(1),
+            date: row.get_unwrap(2),
+            notes: row.get_unwrap(3),
+            items: Vec::new(),
+        }
+    }
+
+    fn with_items(mut self, db: &Database) -> Result<Program> {
+        self.items = db.query("SELECT kind, mid, label, duration FROM program_items WHERE pid = :id ORDER BY position;",
+            named_params!{":id": self.id}, |row| Ok(ProgramItem::from_row(row)))?;
+        Ok(self)
+    }
+
+    pub fn new(name: &str) -> Program {
+        Program {
+            id: None,
+            name: name.into(),
+            date: None,
+            notes: None,
+            items: Vec::new(),
+        }
+    }
+
+    pub fn id(&self) -> Option<i32> {
+        self.id
+    }
+
+    /// Every program, the most recently scheduled first and unscheduled ones last.
+    pub fn list_all(db: &Database) -> Result<Vec<Program>> {
+        db.query("SELECT * FROM programs ORDER BY date IS NULL, date DESC, id DESC;", (), |row| {
+            Ok(Program::from_row(row))
+        })?.into_iter().map(|p| p.with_items(db)).collect()
+    }
+
+    pub fn by_id(db: &Database, id: i32) -> Result<Option<Program>> {
+        db.query_row("SELECT * FROM programs WHERE id = :id;", named_params!{":id": id}, |row| {
+            Ok(Program::from_row(row))
+        })?.map(|p| p.with_items(db)).transpose()
+    }
+
+    /// The length and end time of each item, in order.
+    pub fn timings(&self, db: &Database) -> Result<Vec<Timing>> {
+        let mut ends_at = 0;
+        let mut timings = Vec::with_capacity(self.items.len());
+        for item in &self.items {
+            let duration = match item {
+                ProgramItem::Piece(id) => Music::by_id(db, *id)?.and_then(|m| m.runtime),
+                ProgramItem::Intermission { duration, .. } | ProgramItem::Spoken { duration, .. } => Some(*duration),
+            };
+            ends_at += duration.unwrap_or(0) as u32;
+            timings.push(Timing { duration, ends_at });
+        }
+        Ok(timings)
+    }
+
+    /// Total running time in seconds, counting pieces without a runtime as zero.
+    pub fn total_runtime(&self, db: &Database) -> Result<u32> {
+        Ok(self.timings(db)?.last().map_or(0, |t| t.ends_at))
+    }
+
+    /// Writes the program out as plain text, one numbered line per item with its length and the
+    /// running total.
+    pub fn export_text(&self, db: &Database) -> Result<String> {
+        let mut out = String::new();
+        let _ = writeln!(out, "{}", self.name);
+        if let Some(date) = &self.date {
+            let _ = writeln!(out, "{}", date);
+        }
+        out.push('\n');
+
+        let timings = self.timings(db)?;
+        for (i, (item, timing)) in self.items.iter().zip(&timings).enumerate() {
+            let label = match item {
+                ProgramItem::Piece(id) => match Music::by_id(db, *id)? {
+                    Some(m) => {
+                        let mut label = m.title;
+                        if let Some(composer) = m.composer {
+                            let _ = write!(label, " — {}", composer);
+                        }
+                        if let Some(arranger) = m.arranger {
+                            let _ = write!(label, " (arr. {})", arranger);
+                        }
+                        label
+                    }
+                    None => "(missing piece)".into(),
+                },
+                ProgramItem::Intermission { label, .. } => label.clone().unwrap_or("Intermission".into()),
+                ProgramItem::Spoken { label, .. } => label.clone().unwrap_or("Spoken".into()),
+            };
+            let duration = timing.duration.map_or("?".into(), |d| format_runtime(d as u32));
+            let _ = writeln!(out, "{:>3}. {}\t{}\t{}", i + 1, label, duration, format_runtime(timing.ends_at));
+        }
+
+        let _ = writeln!(out, "\nTotal running time: {}", format_runtime(timings.last().map_or(0, |t| t.ends_at)));
+        if let Some(notes) = &self.notes {
+            let _ = writeln!(out, "\n{}", notes);
+        }
+        Ok(out)
+    }
+
+    /// Saves the program and its items, replacing whatever items were saved before.
+    pub fn insert(&mut self, db: &Database) -> Result<()> {
+        db.transaction_restoring(self, |db, program| {
+            if !program.is_db_entry() {
+                let id = db.insert("INSERT INTO programs (name, date, notes) VALUES (
+                    :name,
+                    :date,
+                    :notes
+                );", named_params!{
+                    ":name": program.name,
+                    ":date": program.date,
+                    ":notes": program.notes,
+                })?;
+                program.id = Some(id);
+            } else {
+                db.execute("UPDATE programs SET
+                    name = :name,
+                    date = :date,
+                    notes = :notes
+                WHERE id = :id;", named_params!{
+                    ":id": program.id,
+                    ":name": program.name,
+                    ":date": program.date,
+                    ":notes": program.notes,
+                })?;
+                db.execute("DELETE FROM program_items WHERE pid = :id;", named_params!{":id": program.id})?;
+            }
+            for (position, item) in program.items.iter().enumerate() {
+                let (mid, label, duration) = match item {
+                    ProgramItem::Piece(id) => (Some(*id), None, None),
+                    ProgramItem::Intermission { label, duration } | ProgramItem::Spoken { label, duration } => {
+                        (None, label.clone(), Some(*duration))
+                    }
+                };
+                db.execute("INSERT INTO program_items (pid, position, kind, mid, label, duration)
+                    VALUES (:pid, :position, :kind, :mid, :label, :duration);", named_params!{
+                    ":pid": program.id,
+                    ":position": position,
+                    ":kind": item.kind(),
+                    ":mid": mid,
+                    ":label": label,
+                    ":duration": duration,
+                })?;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn delete(self, db: &Database) -> Result<()> {
+        db.execute("DELETE FROM programs WHERE id = :id;", named_params!{":id": self.id})?;
+        Ok(())
+    }
+
+    pub fn is_db_entry(&self) -> bool {
+        self.id.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn piece(db: &Database, title: &str, runtime: Option<u16>) -> i32 {
+        let mut music = Music::new();
+        music.title = title.into();
+        music.runtime = runtime;
+        music.insert(db).unwrap();
+        music.id().unwrap()
+    }
+
+    #[test]
+    fn runtimes_are_formatted() {
+        assert_eq!(format_runtime(0), "0:00");
+        assert_eq!(format_runtime(754), "12:34");
+        assert_eq!(format_runtime(3600 + 65), "1:01:05");
+    }
+
+    #[test]
+    fn timings_run_on_from_each_item() {
+        let db = Database::open_in_memory().unwrap();
+        let mut program = Program::new("Spring Concert");
+        program.items = vec![
+            ProgramItem::Spoken { label: Some("Welcome".into()), duration: 60 },
+            ProgramItem::Piece(piece(&db, "Zadok the Priest", Some(330))),
+            ProgramItem::Intermission { label: None, duration: 900 },
+            ProgramItem::Piece(piece(&db, "Untimed Encore", None)),
+        ];
+        program.insert(&db).unwrap();
+
+        let program = Program::by_id(&db, program.id().unwrap()).unwrap().unwrap();
+        let timings = program.timings(&db).unwrap();
+        assert_eq!(timings.iter().map(|t| t.duration).collect::<Vec<_>>(), [Some(60), Some(330), Some(900), None]);
+        assert_eq!(timings.iter().map(|t| t.ends_at).collect::<Vec<_>>(), [60, 390, 1290, 1290]);
+        assert_eq!(program.total_runtime(&db).unwrap(), 1290);
+
+        let text = program.export_text(&db).unwrap();
+        assert!(text.contains("  2. Zadok the Priest\t5:30\t6:30"));
+        assert!(text.contains("  4. Untimed Encore\t?\t21:30"));
+        assert!(text.contains("Total running time: 21:30"));
+    }
+
+    #[test]
+    fn failed_saves_keep_the_program_unsaved() {
+        let db = Database::open_in_memory().unwrap();
+        let mut program = Program::new("Carols");
+        // No piece has this id, so the items can't be saved
+        program.items = vec![ProgramItem::Piece(404)];
+        assert!(program.insert(&db).is_err());
+        assert!(program.id().is_none());
+        assert!(Program::list_all(&db).unwrap().is_empty());
+    }
+}
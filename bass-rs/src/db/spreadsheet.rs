@@ -0,0 +1,585 @@
+use std::path::Path;
+
+use super::{format_runtime, CustomField, CustomValue, Database, Keyword, Music, Result};
+
+/// A column of a spreadsheet, and the field of [`Music`] it holds.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SheetColumn {
+    Title,
+    Source,
+    Composer,
+    Arranger,
+    Notes,
+    /// `m:ss`, `h:mm:ss`, or a number of seconds.
+    Runtime,
+    Key,
+    Range,
+    Voicing,
+    Grade,
+    Publisher,
+    CatalogNumber,
+    Ismn,
+    CopyrightYear,
+    Language,
+    /// Several keywords, separated by `keyword_delimiter`. Each can have a `category:` prefix.
+    Keywords,
+    Custom(CustomField),
+}
+
+impl SheetColumn {
+    /// Every column that isn't a custom field.
+    pub const ALL: [SheetColumn; 16] = [
+        SheetColumn::Title,
+        SheetColumn::Source,
+        SheetColumn::Composer,
+        SheetColumn::Arranger,
+        SheetColumn::Notes,
+        SheetColumn::Runtime,
+        SheetColumn::Key,
+        SheetColumn::Range,
+        SheetColumn::Voicing,
+        SheetColumn::Grade,
+        SheetColumn::Publisher,
+        SheetColumn::CatalogNumber,
+        SheetColumn::Ismn,
+        SheetColumn::CopyrightYear,
+        SheetColumn::Language,
+        SheetColumn::Keywords,
+    ];
+
+    /// Picks a column for a spreadsheet header, if it names one. Custom fields are matched by
+    /// name.
+    pub fn guess(db: &Database, header: &str) -> Result<Option<SheetColumn>> {
+        let header = header.trim();
+        if let Ok(column) = header.parse() {
+            return Ok(Some(column));
+        }
+        Ok(CustomField::by_name(db, header)?.map(SheetColumn::Custom))
+    }
+
+    /// The column's name for a header row, like "Catalog Number". [`SheetColumn::guess`] reads it
+    /// back.
+    pub fn heading(&self) -> String {
+        if let SheetColumn::Custom(field) = self {
+            return field.name.clone();
+        }
+        match self {
+            SheetColumn::Ismn => "ISMN".to_owned(),
+            column => column.to_string().split(' ').map(|word| {
+                let mut chars = word.chars();
+                chars.next().map_or(String::new(), |c| c.to_uppercase().chain(chars).collect())
+            }).collect::<Vec<_>>().join(" "),
+        }
+    }
+
+    // What a piece has in this column, as it's written to a spreadsheet
+    fn value(&self, music: &Music, keywords: &[Keyword], custom: &[(CustomField, CustomValue)], keyword_delimiter: char) -> String {
+        let text = |s: &Option<String>| s.clone().unwrap_or_default();
+        fn shown<T: std::fmt::Display>(v: Option<&T>) -> String {
+            v.map(T::to_string).unwrap_or_default()
+        }
+        match self {
+            SheetColumn::Title => music.title.clone(),
+            SheetColumn::Source => music.source.clone(),
+            SheetColumn::Composer => text(&music.composer),
+            SheetColumn::Arranger => text(&music.arranger),
+            SheetColumn::Notes => text(&music.notes),
+            SheetColumn::Runtime => music.runtime.map(|r| format_runtime(r.into())).unwrap_or_default(),
+            SheetColumn::Key => shown(music.key.as_ref()),
+            SheetColumn::Range => shown(music.range.as_ref()),
+            SheetColumn::Voicing => text(&music.voicing),
+            SheetColumn::Grade => shown(music.grade.as_ref()),
+            SheetColumn::Publisher => text(&music.publisher),
+            SheetColumn::CatalogNumber => text(&music.catalog_number),
+            SheetColumn::Ismn => shown(music.ismn.as_ref()),
+            SheetColumn::CopyrightYear => shown(music.copyright_year.as_ref()),
+            SheetColumn::Language => text(&music.language),
+            SheetColumn::Keywords => keywords.iter()
+                .map(|k| k.to_string())
+                .collect::<Vec<_>>()
+                .join(&format!("{} ", keyword_delimiter)),
+            SheetColumn::Custom(field) => shown(custom.iter().find(|(f, _)| f.id() == field.id()).map(|(_, v)| v)),
+        }
+    }
+}
+
+impl std::str::FromStr for SheetColumn {
+    type Err = &'static str;
+    fn from_str(s: &str) -> std::result::Result<SheetColumn, Self::Err> {
+        match s.to_ascii_lowercase().replace('_', " ").as_str() {
+            "title" | "name" => Ok(SheetColumn::Title),
+            "source" => Ok(SheetColumn::Source),
+            "composer" => Ok(SheetColumn::Composer),
+            "arranger" => Ok(SheetColumn::Arranger),
+            "notes" => Ok(SheetColumn::Notes),
+            "runtime" | "duration" => Ok(SheetColumn::Runtime),
+            "key" => Ok(SheetColumn::Key),
+            "range" => Ok(SheetColumn::Range),
+            "voicing" => Ok(SheetColumn::Voicing),
+            "grade" => Ok(SheetColumn::Grade),
+            "publisher" => Ok(SheetColumn::Publisher),
+            "catalog number" | "catalog" => Ok(SheetColumn::CatalogNumber),
+            "ismn" => Ok(SheetColumn::Ismn),
+            "copyright year" | "copyright" => Ok(SheetColumn::CopyrightYear),
+            "language" => Ok(SheetColumn::Language),
+            "keywords" | "tags" => Ok(SheetColumn::Keywords),
+            _ => Err("Not a valid import column"),
+        }
+    }
+}
+
+impl std::fmt::Display for SheetColumn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            SheetColumn::Title => "title",
+            SheetColumn::Source => "source",
+            SheetColumn::Composer => "composer",
+            SheetColumn::Arranger => "arranger",
+            SheetColumn::Notes => "notes",
+            SheetColumn::Runtime => "runtime",
+            SheetColumn::Key => "key",
+            SheetColumn::Range => "range",
+            SheetColumn::Voicing => "voicing",
+            SheetColumn::Grade => "grade",
+            SheetColumn::Publisher => "publisher",
+            SheetColumn::CatalogNumber => "catalog number",
+            SheetColumn::Ismn => "ismn",
+            SheetColumn::CopyrightYear => "copyright year",
+            SheetColumn::Language => "language",
+            SheetColumn::Keywords => "keywords",
+            SheetColumn::Custom(field) => field.name.as_str(),
+        })
+    }
+}
+
+/// A piece read from one row of the file.
+#[derive(Clone, Debug)]
+pub struct ImportedRow {
+    /// Line in the file the row starts on, counting from 1.
+    pub line: u64,
+    pub music: Music,
+    pub keywords: Vec<Keyword>,
+    pub custom: Vec<(CustomField, CustomValue)>,
+}
+
+/// Why a row couldn't be imported.
+#[derive(Clone, Debug)]
+pub struct RowError {
+    pub line: u64,
+    pub message: String,
+}
+
+impl std::fmt::Display for RowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// What an import did, or would do for a dry run.
+#[derive(Clone, Debug, Default)]
+pub struct ImportReport {
+    pub rows: Vec<ImportedRow>,
+    pub errors: Vec<RowError>,
+}
+
+/// Reads pieces out of a CSV or TSV spreadsheet.
+#[derive(Clone, Debug)]
+pub struct CsvImport {
+    /// Field separator, `b','` for CSV or `b'\t'` for TSV.
+    pub delimiter: u8,
+    /// Whether the first row names the columns rather than holding a piece.
+    pub has_headers: bool,
+    /// Where each column goes, by position. Columns past the end or mapped to `None` are skipped.
+    pub columns: Vec<Option<SheetColumn>>,
+    /// What separates keywords within a [`SheetColumn::Keywords`] column.
+    pub keyword_delimiter: char,
+}
+
+impl Default for CsvImport {
+    fn default() -> CsvImport {
+        CsvImport {
+            delimiter: b',',
+            has_headers: true,
+            columns: Vec::new(),
+            keyword_delimiter: ';',
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl CsvImport {
+    /// Sets up an import for `path`, using its extension to pick the delimiter.
+    pub fn for_file<P: AsRef<Path>>(path: P) -> CsvImport {
+        let tsv = path.as_ref().extension().is_some_and(|e| e.eq_ignore_ascii_case("tsv") || e.eq_ignore_ascii_case("tab"));
+        CsvImport {
+            delimiter: if tsv { b'\t' } else { b',' },
+            ..Default::default()
+        }
+    }
+
+    fn reader<P: AsRef<Path>>(&self, path: P) -> Result<csv::Reader<std::fs::File>> {
+        Ok(csv::ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .has_headers(self.has_headers)
+            .flexible(true)
+            .from_path(path)?)
+    }
+
+    /// The first row of the file, to show what each column holds when choosing where it goes.
+    pub fn headers<P: AsRef<Path>>(&self, path: P) -> Result<Vec<String>> {
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .has_headers(false)
+            .flexible(true)
+            .from_path(path)?;
+        let mut record = csv::StringRecord::new();
+        reader.read_record(&mut record)?;
+        Ok(record.iter().map(String::from).collect())
+    }
+
+    /// Sets [`CsvImport::columns`] from the headers, for the ones [`SheetColumn::guess`] recognizes.
+    pub fn guess_columns(&mut self, db: &Database, headers: &[String]) -> Result<()> {
+        self.columns = headers.iter().map(|h| SheetColumn::guess(db, h)).collect::<Result<_>>()?;
+        Ok(())
+    }
+
+    /// Reads every row without touching the database, so the result can be checked first.
+    pub fn dry_run<P: AsRef<Path>>(&self, path: P) -> Result<ImportReport> {
+        let mut report = ImportReport::default();
+        for record in self.reader(path)?.records() {
+            let (line, row) = match record {
+                Ok(record) => (record.position().map_or(0, |p| p.line()), self.parse_record(&record)),
+                Err(e) => (e.position().map_or(0, |p| p.line()), Err(e.to_string())),
+            };
+            match row {
+                Ok(row) => report.rows.push(ImportedRow { line, ..row }),
+                Err(message) => report.errors.push(RowError { line, message }),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Adds every row that reads cleanly to the library, all at once. Rows with errors, whether
+    /// reading or saving them, are skipped and listed in the report.
+    pub fn run<P: AsRef<Path>>(&self, db: &Database, path: P) -> Result<ImportReport> {
+        let mut report = self.dry_run(path)?;
+        let rows = std::mem::take(&mut report.rows);
+        db.transaction(|db| {
+            for mut row in rows {
+                // A row that fails to save leaves nothing of itself behind
+                let saved = db.transaction(|db| {
+                    row.music.insert_with_keywords(db, &mut row.keywords)?;
+                    for (field, value) in &row.custom {
+                        row.music.set_custom_value(db, field, Some(value))?;
+                    }
+                    Ok(())
+                });
+                match saved {
+                    Ok(()) => report.rows.push(row),
+                    Err(e) => report.errors.push(RowError { line: row.line, message: e.to_string() }),
+                }
+            }
+            Ok(())
+        })?;
+        report.errors.sort_by_key(|e| e.line);
+        Ok(report)
+    }
+
+    fn parse_record(&self, record: &csv::StringRecord) -> std::result::Result<ImportedRow, String> {
+        let mut row = ImportedRow {
+            line: 0,
+            music: Music::new(),
+            keywords: Vec::new(),
+            custom: Vec::new(),
+        };
+        for (column, value) in self.columns.iter().zip(record.iter()) {
+            let (Some(column), value) = (column, value.trim()) else {
+                continue;
+            };
+            if value.is_empty() {
+                continue;
+            }
+            let invalid = |e: &dyn std::fmt::Display| format!("\"{}\" is not a valid {}: {}", value, column, e);
+            let text = Some(value.to_owned());
+            let music = &mut row.music;
+            match column {
+                SheetColumn::Title => music.title = value.to_owned(),
+                SheetColumn::Source => music.source = value.to_owned(),
+                SheetColumn::Composer => music.composer = text,
+                SheetColumn::Arranger => music.arranger = text,
+                SheetColumn::Notes => music.notes = text,
+                SheetColumn::Runtime => music.runtime = Some(parse_runtime(value).ok_or_else(|| invalid(&"expected m:ss"))?),
+                SheetColumn::Key => music.key = Some(value.parse().map_err(|e| invalid(&e))?),
+                SheetColumn::Range => music.range = Some(value.parse().map_err(|e| invalid(&e))?),
+                SheetColumn::Voicing => music.voicing = text,
+                SheetColumn::Grade => {
+                    let grade = value.parse().ok().filter(|g: &f64| g.is_finite() && *g >= 0.0);
+                    music.grade = Some(grade.ok_or_else(|| invalid(&"expected a number"))?);
+                }
+                SheetColumn::Publisher => music.publisher = text,
+                SheetColumn::CatalogNumber => music.catalog_number = text,
+                SheetColumn::Ismn => music.ismn = Some(value.parse().map_err(|e| invalid(&e))?),
+                SheetColumn::CopyrightYear => {
+                    let year = value.parse().ok().filter(|y| (1..=9999).contains(y));
+                    music.copyright_year = Some(year.ok_or_else(|| invalid(&"expected a year"))?);
+                }
+                SheetColumn::Language => music.language = text,
+                SheetColumn::Keywords => {
+                    // Blank entries are just stray delimiters, but a category needs a keyword after it
+                    for k in value.split(self.keyword_delimiter).map(str::trim).filter(|k| !k.is_empty()) {
+                        let Ok(keyword) = k.parse::<Keyword>();
+                        if keyword.keyword.is_empty() || keyword.category.as_ref().is_some_and(|c| c.is_empty()) {
+                            return Err(format!("\"{}\" is not a valid keyword", k));
+                        }
+                        row.keywords.push(keyword);
+                    }
+                }
+                SheetColumn::Custom(field) => {
+                    let value = field.parse_value(value).map_err(|e| invalid(&e))?;
+                    row.custom.push((field.clone(), value));
+                }
+            }
+        }
+        if row.music.title.is_empty() {
+            return Err("no title".to_owned());
+        }
+        Ok(row)
+    }
+}
+
+/// Writes pieces out to a CSV or TSV spreadsheet, one row each after a header row.
+#[derive(Clone, Debug)]
+pub struct CsvExport {
+    /// Field separator, `b','` for CSV or `b'\t'` for TSV.
+    pub delimiter: u8,
+    /// Which columns to write, in order.
+    pub columns: Vec<SheetColumn>,
+    /// What goes between keywords in a [`SheetColumn::Keywords`] column, followed by a space.
+    pub keyword_delimiter: char,
+}
+
+impl Default for CsvExport {
+    fn default() -> CsvExport {
+        CsvExport {
+            delimiter: b',',
+            columns: SheetColumn::ALL.to_vec(),
+            keyword_delimiter: ';',
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl CsvExport {
+    /// Sets up an export of every column to `path`, using its extension to pick the delimiter.
+    pub fn for_file<P: AsRef<Path>>(path: P) -> CsvExport {
+        CsvExport {
+            delimiter: CsvImport::for_file(path).delimiter,
+            ..Default::default()
+        }
+    }
+
+    pub fn write<W: std::io::Write>(&self, db: &Database, music: &[Music], writer: W) -> Result<()> {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(self.delimiter)
+            .from_writer(writer);
+        writer.write_record(self.columns.iter().map(SheetColumn::heading))?;
+        let needs_keywords = self.columns.contains(&SheetColumn::Keywords);
+        let needs_custom = self.columns.iter().any(|c| matches!(c, SheetColumn::Custom(_)));
+        for m in music {
+            let keywords = if needs_keywords { m.keywords(db)?.unwrap_or_default() } else { Vec::new() };
+            let custom = if needs_custom { m.custom_values(db)?.unwrap_or_default() } else { Vec::new() };
+            writer.write_record(self.columns.iter().map(|c| c.value(m, &keywords, &custom, self.keyword_delimiter)))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn write_to_path<P: AsRef<Path>>(&self, db: &Database, music: &[Music], path: P) -> Result<()> {
+        self.write(db, music, std::fs::File::create(path)?)
+    }
+}
+
+// Accepts the same forms as the add dialog, `m:ss` or `h:mm:ss`, plus a bare number of seconds
+fn parse_runtime(s: &str) -> Option<u16> {
+    let parts: Vec<_> = s.split(':').collect();
+    if parts.len() > 3 {
+        return None;
+    }
+    let mut seconds: u16 = 0;
+    for (i, part) in parts.into_iter().enumerate() {
+        let part: u16 = part.trim().parse().ok()?;
+        if i > 0 && part >= 60 {
+            return None;
+        }
+        seconds = seconds.checked_mul(60)?.checked_add(part)?;
+    }
+    Some(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::CustomFieldKind;
+
+    fn import(columns: Vec<SheetColumn>) -> CsvImport {
+        CsvImport {
+            columns: columns.into_iter().map(Some).collect(),
+            ..Default::default()
+        }
+    }
+
+    // Writes `contents` to a file of its own, named after the test using it
+    fn sheet(name: &str, contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("bass-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn runtime_forms() {
+        assert_eq!(parse_runtime("4:30"), Some(270));
+        assert_eq!(parse_runtime("1:02:03"), Some(3723));
+        assert_eq!(parse_runtime("90"), Some(90));
+        assert_eq!(parse_runtime("1:75"), None);
+        assert_eq!(parse_runtime("1:00:00:00"), None);
+    }
+
+    #[test]
+    fn huge_runtime_is_a_row_error() {
+        assert_eq!(parse_runtime("100000000:00"), None);
+        assert_eq!(parse_runtime("2000:00"), None);
+        let import = import(vec![SheetColumn::Title, SheetColumn::Runtime]);
+        let record = csv::StringRecord::from(vec!["Long", "100000000:00"]);
+        assert!(import.parse_record(&record).is_err());
+    }
+
+    #[test]
+    fn keywords_are_trimmed_around_the_category() {
+        let import = import(vec![SheetColumn::Title, SheetColumn::Keywords]);
+        let record = csv::StringRecord::from(vec!["Carol", " season: Christmas ; a cappella;"]);
+        let row = import.parse_record(&record).unwrap();
+        assert_eq!(row.keywords, vec!["season:Christmas".parse().unwrap(), "a cappella".parse::<Keyword>().unwrap()]);
+        assert_eq!(row.keywords[0].category.as_deref(), Some("season"));
+        assert_eq!(row.keywords[0].keyword, "Christmas");
+    }
+
+    #[test]
+    fn empty_keywords_are_row_errors() {
+        let import = import(vec![SheetColumn::Title, SheetColumn::Keywords]);
+        for keywords in ["season:", ": Christmas"] {
+            let record = csv::StringRecord::from(vec!["Carol", keywords]);
+            assert!(import.parse_record(&record).is_err(), "{}", keywords);
+        }
+    }
+
+    #[test]
+    fn columns_are_guessed_from_headers() {
+        let db = Database::open_in_memory().unwrap();
+        let mut folder = CustomField::new("Folder", CustomFieldKind::Number);
+        folder.insert(&db).unwrap();
+        let path = sheet("headers.tsv", "Name\tDuration\tfolder\tShelf\nGloria\t3:00\t4\tTop\n");
+        let mut import = CsvImport::for_file(&path);
+        assert_eq!(import.delimiter, b'\t');
+        let headers = import.headers(&path).unwrap();
+        import.guess_columns(&db, &headers).unwrap();
+        assert_eq!(import.columns, [Some(SheetColumn::Title), Some(SheetColumn::Runtime), Some(SheetColumn::Custom(folder)), None]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn dry_runs_change_nothing() {
+        let db = Database::open_in_memory().unwrap();
+        let path = sheet("dry-run.csv", "title,key,keywords\nGloria,D major,latin;season:Christmas\n,C,\nMagnificat,H minor,\n");
+        let import = import(vec![SheetColumn::Title, SheetColumn::Key, SheetColumn::Keywords]);
+
+        let report = import.dry_run(&path).unwrap();
+        assert_eq!(report.rows.len(), 1);
+        assert_eq!(report.rows[0].line, 2);
+        assert_eq!(report.rows[0].keywords.len(), 2);
+        assert_eq!(report.errors.iter().map(|e| e.line).collect::<Vec<_>>(), [3, 4]);
+        assert!(report.errors[1].to_string().starts_with("line 4: \"H minor\" is not a valid key"));
+        assert!(Music::list_all(&db).unwrap().is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn runs_add_the_rows_that_read() {
+        let db = Database::open_in_memory().unwrap();
+        let mut folder = CustomField::new("Folder", CustomFieldKind::Number);
+        folder.insert(&db).unwrap();
+        let path = sheet("run.csv", "title,composer,runtime,folder,keywords\n\
+            Gloria,Vivaldi,4:30,12,latin\n\
+            Broken,,soon,,\n");
+        let mut import = import(vec![SheetColumn::Title, SheetColumn::Composer, SheetColumn::Runtime,
+            SheetColumn::Custom(folder.clone()), SheetColumn::Keywords]);
+        import.keyword_delimiter = '|';
+
+        let report = import.run(&db, &path).unwrap();
+        assert_eq!(report.errors.len(), 1);
+        let music = Music::list_all(&db).unwrap();
+        assert_eq!(music.len(), 1);
+        assert_eq!(music[0].composer.as_deref(), Some("Vivaldi"));
+        assert_eq!(music[0].runtime, Some(270));
+        assert_eq!(music[0].keywords(&db).unwrap().unwrap(), ["latin".parse().unwrap()]);
+        assert_eq!(music[0].custom_values(&db).unwrap().unwrap(), [(folder, CustomValue::Number(12.0))]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn exports_read_back_in() {
+        let voicing = |db: &Database| {
+            let mut field = CustomField::new("Voicing notes", CustomFieldKind::Text);
+            field.insert(db).unwrap();
+            field
+        };
+        let from = Database::open_in_memory().unwrap();
+        let field = voicing(&from);
+        let mut music = Music::new();
+        music.title = "Tomorrow Shall Be My Dancing Day".into();
+        music.source = "Folk carol, with a \"refrain\"".into();
+        music.composer = Some("Traditional".into());
+        music.runtime = Some(150);
+        music.insert_with_keywords(&from, &mut ["season:Christmas".parse().unwrap(), "a cappella".parse().unwrap()]).unwrap();
+        music.set_custom_value(&from, &field, Some(&CustomValue::Text("SATB, divisi".into()))).unwrap();
+
+        let path = std::env::temp_dir().join(format!("bass-round-trip-{}.csv", std::process::id()));
+        let mut export = CsvExport::for_file(&path);
+        export.columns.push(SheetColumn::Custom(field));
+        export.write_to_path(&from, &[music.clone()], &path).unwrap();
+
+        let to = Database::open_in_memory().unwrap();
+        let field = voicing(&to);
+        let mut import = CsvImport::for_file(&path);
+        let headers = import.headers(&path).unwrap();
+        import.guess_columns(&to, &headers).unwrap();
+        let report = import.run(&to, &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        let copy = Music::by_id(&to, report.rows[0].music.id().unwrap()).unwrap().unwrap();
+        assert_eq!(copy.title, music.title);
+        assert_eq!(copy.source, music.source);
+        assert_eq!(copy.composer, music.composer);
+        assert_eq!(copy.runtime, music.runtime);
+        assert_eq!(copy.keywords(&to).unwrap().unwrap(), music.keywords(&from).unwrap().unwrap());
+        assert_eq!(copy.custom_values(&to).unwrap().unwrap(), vec![(field, CustomValue::Text("SATB, divisi".into()))]);
+    }
+
+    #[test]
+    fn rows_that_fail_to_save_are_skipped() {
+        let db = Database::open_in_memory().unwrap();
+        let mut folder = CustomField::new("Folder", CustomFieldKind::Number);
+        folder.insert(&db).unwrap();
+        let path = sheet("failed-save.csv", "title,folder\nGloria,4\nKyrie,\nSanctus,x\n");
+        let import = import(vec![SheetColumn::Title, SheetColumn::Custom(folder.clone())]);
+        // Deleted after the import was set up, so it can't be saved to
+        folder.clone().delete(&db).unwrap();
+
+        let report = import.run(&db, &path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(report.errors.iter().map(|e| e.line).collect::<Vec<_>>(), [2, 4]);
+        assert_eq!(report.rows.iter().map(|r| r.line).collect::<Vec<_>>(), [3]);
+        let titles: Vec<String> = Music::list_all(&db).unwrap().into_iter().map(|m| m.title).collect();
+        assert_eq!(titles, ["Kyrie"]);
+    }
+}
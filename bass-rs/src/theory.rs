@@ -0,0 +1,646 @@
+//! Pitches, intervals, keys and ranges, as written on a score.
+//!
+//! Spelling is kept throughout: B♭ and A♯ sound the same but are different notes here, and
+//! compare equal only by [`Pitch::number`].
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+pub type TheoryError = &'static str;
+
+/// The letter name of a note.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Letter {
+    C,
+    D,
+    E,
+    F,
+    G,
+    A,
+    B,
+}
+
+impl Letter {
+    const ALL: [Letter; 7] = [Letter::C, Letter::D, Letter::E, Letter::F, Letter::G, Letter::A, Letter::B];
+
+    // Steps above C on the staff
+    fn index(&self) -> i32 {
+        Letter::ALL.iter().position(|l| l == self).unwrap() as i32
+    }
+
+    // Semitones above C
+    fn semitones(&self) -> i32 {
+        [0, 2, 4, 5, 7, 9, 11][self.index() as usize]
+    }
+
+    // Place on the circle of fifths, counting from C
+    fn fifths(&self) -> i32 {
+        [0, 2, 4, -1, 1, 3, 5][self.index() as usize]
+    }
+
+    fn from_char(c: char) -> Option<Letter> {
+        match c.to_ascii_uppercase() {
+            'C' => Some(Letter::C),
+            'D' => Some(Letter::D),
+            'E' => Some(Letter::E),
+            'F' => Some(Letter::F),
+            'G' => Some(Letter::G),
+            'A' => Some(Letter::A),
+            'B' => Some(Letter::B),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Letter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// A note name without an octave, like B♭ or F♯.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PitchClass {
+    pub letter: Letter,
+    /// Sharps when positive, flats when negative. Double sharps and flats at most.
+    pub accidental: i8,
+}
+
+impl PitchClass {
+    pub fn new(letter: Letter, accidental: i8) -> PitchClass {
+        PitchClass { letter, accidental }
+    }
+
+    /// Semitones above C, from 0 to 11.
+    pub fn semitones(&self) -> u8 {
+        (self.letter.semitones() + self.accidental as i32).rem_euclid(12) as u8
+    }
+
+    // Reads a pitch class off the front of `s`, returning it and whatever follows. Accepts
+    // `#`, `♯`, `b`, `♭`, `x` and `𝄪`, `𝄫`, or the words sharp and flat, as in "B flat" or
+    // "B-flat".
+    fn parse_prefix(s: &str) -> Result<(PitchClass, &str), TheoryError> {
+        let mut chars = s.chars();
+        let letter = chars.next().and_then(Letter::from_char).ok_or("Not a note name")?;
+        let mut rest = chars.as_str();
+        let mut accidental: i8 = 0;
+        loop {
+            let word = rest.trim_start_matches([' ', '-']);
+            if let Some(r) = word.strip_prefix("sharp") {
+                accidental += 1;
+                rest = r;
+            } else if let Some(r) = word.strip_prefix("flat") {
+                accidental -= 1;
+                rest = r;
+            } else if let Some(r) = rest.strip_prefix(['#', '♯']) {
+                accidental += 1;
+                rest = r;
+            } else if let Some(r) = rest.strip_prefix(['b', '♭']) {
+                accidental -= 1;
+                rest = r;
+            } else if let Some(r) = rest.strip_prefix(['x', '𝄪']) {
+                accidental += 2;
+                rest = r;
+            } else if let Some(r) = rest.strip_prefix('𝄫') {
+                accidental -= 2;
+                rest = r;
+            } else {
+                break;
+            }
+        }
+        if accidental.abs() > 2 {
+            return Err("Too many sharps or flats");
+        }
+        Ok((PitchClass { letter, accidental }, rest))
+    }
+}
+
+impl FromStr for PitchClass {
+    type Err = TheoryError;
+    fn from_str(s: &str) -> Result<PitchClass, Self::Err> {
+        match PitchClass::parse_prefix(s.trim())? {
+            (class, "") => Ok(class),
+            _ => Err("Not a note name"),
+        }
+    }
+}
+
+impl Display for PitchClass {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let accidental = match self.accidental {
+            a if a > 0 => "♯".repeat(a as usize),
+            a => "♭".repeat(a.unsigned_abs() as usize),
+        };
+        write!(f, "{}{}", self.letter, accidental)
+    }
+}
+
+/// A note in a particular octave, in scientific pitch notation: C4 is middle C.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Pitch {
+    pub class: PitchClass,
+    pub octave: i8,
+}
+
+impl Pitch {
+    pub fn new(class: PitchClass, octave: i8) -> Pitch {
+        Pitch { class, octave }
+    }
+
+    /// Semitones above C0, so that higher pitches have higher numbers. C♭4 and B3 share a
+    /// number.
+    pub fn number(&self) -> i32 {
+        self.octave as i32 * 12 + self.class.letter.semitones() + self.class.accidental as i32
+    }
+
+    // Steps above C0 on the staff, ignoring accidentals
+    fn staff_position(&self) -> i32 {
+        self.octave as i32 * 7 + self.class.letter.index()
+    }
+
+    fn from_staff_position(position: i32, number: i32) -> Result<Pitch, TheoryError> {
+        let letter = Letter::ALL[position.rem_euclid(7) as usize];
+        let octave = position.div_euclid(7);
+        let accidental = number - (octave * 12 + letter.semitones());
+        if accidental.abs() > 2 {
+            return Err("Too many sharps or flats");
+        }
+        Ok(Pitch {
+            class: PitchClass::new(letter, accidental as i8),
+            octave: i8::try_from(octave).map_err(|_| "Octave out of range")?,
+        })
+    }
+
+    /// This pitch moved up by `interval`, spelled to match: E4 up a minor third is G4, and
+    /// E4 up an augmented second is F♯♯4.
+    pub fn up(&self, interval: Interval) -> Result<Pitch, TheoryError> {
+        Pitch::from_staff_position(self.staff_position() + interval.number as i32 - 1,
+            self.number() + interval.semitones as i32)
+    }
+
+    /// This pitch moved down by `interval`.
+    pub fn down(&self, interval: Interval) -> Result<Pitch, TheoryError> {
+        Pitch::from_staff_position(self.staff_position() - (interval.number as i32 - 1),
+            self.number() - interval.semitones as i32)
+    }
+}
+
+impl FromStr for Pitch {
+    type Err = TheoryError;
+    fn from_str(s: &str) -> Result<Pitch, Self::Err> {
+        let (class, rest) = PitchClass::parse_prefix(s.trim())?;
+        let octave = rest.trim().parse().map_err(|_| "Not a valid octave")?;
+        Ok(Pitch { class, octave })
+    }
+}
+
+impl Display for Pitch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.class, self.octave)
+    }
+}
+
+/// The distance between two notes, counted both in steps of the staff (a third, a fifth) and in
+/// semitones, which together give its quality.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Interval {
+    /// 1 for a unison, 8 for an octave, 10 for a tenth.
+    number: u8,
+    semitones: u8,
+}
+
+impl Interval {
+    pub const UNISON: Interval = Interval { number: 1, semitones: 0 };
+    pub const OCTAVE: Interval = Interval { number: 8, semitones: 12 };
+
+    /// The interval from the lower of `a` and `b` to the higher.
+    pub fn between(a: &Pitch, b: &Pitch) -> Interval {
+        let (low, high) = if (b.staff_position(), b.number()) >= (a.staff_position(), a.number()) {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        Interval {
+            number: (high.staff_position() - low.staff_position() + 1).clamp(1, u8::MAX as i32) as u8,
+            semitones: (high.number() - low.number()).clamp(0, u8::MAX as i32) as u8,
+        }
+    }
+
+    pub fn number(&self) -> u8 {
+        self.number
+    }
+
+    pub fn semitones(&self) -> u8 {
+        self.semitones
+    }
+
+    // Whether the interval is perfect rather than major or minor at its plainest
+    fn is_perfect_kind(number: u8) -> bool {
+        matches!((number - 1) % 7, 0 | 3 | 4)
+    }
+
+    // Semitones in the major or perfect interval with this number
+    fn natural_semitones(number: u8) -> i32 {
+        let simple = ((number - 1) % 7) as usize;
+        [0, 2, 4, 5, 7, 9, 11][simple] + 12 * ((number - 1) / 7) as i32
+    }
+}
+
+impl FromStr for Interval {
+    type Err = TheoryError;
+    /// Reads the usual shorthand: P5, M3, m7, A4, d5, and AA or dd for doubly augmented or
+    /// diminished.
+    fn from_str(s: &str) -> Result<Interval, Self::Err> {
+        let s = s.trim();
+        let split = s.find(|c: char| c.is_ascii_digit()).ok_or("Not an interval")?;
+        let (quality, number) = s.split_at(split);
+        let number: u8 = number.parse().map_err(|_| "Not an interval")?;
+        if number == 0 {
+            return Err("Not an interval");
+        }
+        let perfect = Interval::is_perfect_kind(number);
+        let offset = match (quality, perfect) {
+            ("P", true) | ("M", false) => 0,
+            ("m", false) => -1,
+            ("A", _) => 1,
+            ("AA", _) => 2,
+            ("d", true) => -1,
+            ("d", false) => -2,
+            ("dd", true) => -2,
+            ("dd", false) => -3,
+            _ => return Err("Not a valid interval quality"),
+        };
+        let semitones = Interval::natural_semitones(number) + offset;
+        Ok(Interval {
+            number,
+            semitones: u8::try_from(semitones).map_err(|_| "Not an interval")?,
+        })
+    }
+}
+
+impl Display for Interval {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let offset = self.semitones as i32 - Interval::natural_semitones(self.number);
+        let quality = match (offset, Interval::is_perfect_kind(self.number)) {
+            (0, true) => "P".to_string(),
+            (0, false) => "M".to_string(),
+            (-1, false) => "m".to_string(),
+            (o, _) if o > 0 => "A".repeat(o as usize),
+            (o, true) => "d".repeat(o.unsigned_abs() as usize),
+            (o, false) => "d".repeat(o.unsigned_abs() as usize - 1),
+        };
+        write!(f, "{}{}", quality, self.number)
+    }
+}
+
+/// The scale a key is built on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Mode {
+    Major,
+    Minor,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 7] = [
+        Mode::Major,
+        Mode::Minor,
+        Mode::Dorian,
+        Mode::Phrygian,
+        Mode::Lydian,
+        Mode::Mixolydian,
+        Mode::Locrian,
+    ];
+
+    // How many fifths flatter than the major key on the same tonic the signature is
+    fn fifths(&self) -> i32 {
+        match self {
+            Mode::Lydian => 1,
+            Mode::Major => 0,
+            Mode::Mixolydian => -1,
+            Mode::Dorian => -2,
+            Mode::Minor => -3,
+            Mode::Phrygian => -4,
+            Mode::Locrian => -5,
+        }
+    }
+}
+
+impl FromStr for Mode {
+    type Err = TheoryError;
+    fn from_str(s: &str) -> Result<Mode, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "major" | "ionian" => Ok(Mode::Major),
+            "minor" | "aeolian" => Ok(Mode::Minor),
+            "dorian" => Ok(Mode::Dorian),
+            "phrygian" => Ok(Mode::Phrygian),
+            "lydian" => Ok(Mode::Lydian),
+            "mixolydian" => Ok(Mode::Mixolydian),
+            "locrian" => Ok(Mode::Locrian),
+            _ => Err("Not a valid mode"),
+        }
+    }
+}
+
+impl Display for Mode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            Mode::Major => "major",
+            Mode::Minor => "minor",
+            Mode::Dorian => "dorian",
+            Mode::Phrygian => "phrygian",
+            Mode::Lydian => "lydian",
+            Mode::Mixolydian => "mixolydian",
+            Mode::Locrian => "locrian",
+        })
+    }
+}
+
+/// The sharps or flats at the start of each staff.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct KeySignature(i8);
+
+impl KeySignature {
+    /// `sharps` sharps when positive, or that many flats when negative.
+    pub fn new(sharps: i8) -> KeySignature {
+        KeySignature(sharps)
+    }
+
+    /// Sharps when positive, flats when negative.
+    pub fn sharps(&self) -> i8 {
+        self.0
+    }
+
+    /// The major key with this signature.
+    pub fn major_key(&self) -> Key {
+        self.key(Mode::Major)
+    }
+
+    /// The minor key with this signature.
+    pub fn minor_key(&self) -> Key {
+        self.key(Mode::Minor)
+    }
+
+    /// The key in `mode` with this signature, spelled with the accidentals of the signature.
+    pub fn key(&self, mode: Mode) -> Key {
+        let fifths = self.0 as i32 - mode.fifths();
+        // Each seven fifths round the circle adds a sharp to the same letter
+        let letter = Letter::ALL.into_iter().find(|l| (fifths - l.fifths()).rem_euclid(7) == 0).unwrap();
+        let accidental = (fifths - letter.fifths()).div_euclid(7);
+        Key {
+            tonic: PitchClass::new(letter, accidental as i8),
+            mode,
+        }
+    }
+}
+
+impl FromStr for KeySignature {
+    type Err = TheoryError;
+    /// Reads "3♯", "2b", "1 flat" and so on, or "0" for none.
+    fn from_str(s: &str) -> Result<KeySignature, Self::Err> {
+        let s = s.trim();
+        if s == "0" || s.eq_ignore_ascii_case("none") || s == KeySignature(0).to_string() {
+            return Ok(KeySignature(0));
+        }
+        let split = s.find(|c: char| !c.is_ascii_digit()).ok_or("Missing sharps or flats")?;
+        let (count, kind) = s.split_at(split);
+        let count: i8 = count.parse().map_err(|_| "Not a valid key signature")?;
+        if count > 7 {
+            return Err("Not a valid key signature");
+        }
+        match kind.trim().trim_end_matches('s') {
+            "#" | "♯" | "sharp" => Ok(KeySignature(count)),
+            "b" | "♭" | "flat" => Ok(KeySignature(-count)),
+            _ => Err("Not a valid key signature"),
+        }
+    }
+}
+
+impl Display for KeySignature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            0 => write!(f, "no sharps or flats"),
+            n if n > 0 => write!(f, "{}♯", n),
+            n => write!(f, "{}♭", n.unsigned_abs()),
+        }
+    }
+}
+
+/// A key, like F♯ minor or D dorian.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub tonic: PitchClass,
+    pub mode: Mode,
+}
+
+impl Key {
+    pub fn new(tonic: PitchClass, mode: Mode) -> Key {
+        Key { tonic, mode }
+    }
+
+    /// The signature the key is written with. Keys like G♯ major that would need more than
+    /// seven sharps or flats give signatures past 7.
+    pub fn signature(&self) -> KeySignature {
+        let fifths = self.tonic.letter.fifths() + 7 * self.tonic.accidental as i32 + self.mode.fifths();
+        KeySignature(fifths as i8)
+    }
+}
+
+impl FromStr for Key {
+    type Err = TheoryError;
+    /// Reads a tonic followed by a mode, like "F♯ minor", "Bb major" or "d dorian". A tonic
+    /// alone is major, and a tonic followed by "m" is minor, as in "Am".
+    fn from_str(s: &str) -> Result<Key, Self::Err> {
+        let (tonic, rest) = PitchClass::parse_prefix(s.trim())?;
+        let mode = match rest.trim() {
+            "" => Mode::Major,
+            "m" => Mode::Minor,
+            mode => mode.parse()?,
+        };
+        Ok(Key { tonic, mode })
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.tonic, self.mode)
+    }
+}
+
+/// The lowest and highest notes of a part, voice or instrument.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Range {
+    low: Pitch,
+    high: Pitch,
+}
+
+impl Range {
+    /// The range between `a` and `b`, whichever order they come in.
+    pub fn new(a: Pitch, b: Pitch) -> Range {
+        if b.number() < a.number() {
+            Range { low: b, high: a }
+        } else {
+            Range { low: a, high: b }
+        }
+    }
+
+    pub fn low(&self) -> Pitch {
+        self.low
+    }
+
+    pub fn high(&self) -> Pitch {
+        self.high
+    }
+
+    /// How far apart the lowest and highest notes are.
+    pub fn span(&self) -> Interval {
+        Interval::between(&self.low, &self.high)
+    }
+
+    pub fn contains(&self, pitch: &Pitch) -> bool {
+        (self.low.number()..=self.high.number()).contains(&pitch.number())
+    }
+
+    /// Whether every note of `other` is within this range, as when a singer with this range can
+    /// sing a part with range `other`.
+    pub fn covers(&self, other: &Range) -> bool {
+        self.contains(&other.low) && self.contains(&other.high)
+    }
+}
+
+impl FromStr for Range {
+    type Err = TheoryError;
+    /// Reads two pitches separated by a dash or "to", like "G3–C5", "Bb2-F4" or "C4 to A5".
+    fn from_str(s: &str) -> Result<Range, Self::Err> {
+        let s = s.trim();
+        let (low, high) = s.split_once(['–', '—'])
+            .or_else(|| s.split_once(" to "))
+            .or_else(|| {
+                // A dash after an octave number, so that octave -1 still reads as a number
+                let dash = s.char_indices()
+                    .find(|&(i, c)| c == '-' && s[..i].ends_with(|p: char| p.is_ascii_digit()))?.0;
+                Some((&s[..dash], &s[dash + 1..]))
+            })
+            .ok_or("Not a range")?;
+        Ok(Range::new(low.parse()?, high.parse()?))
+    }
+}
+
+impl Display for Range {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}–{}", self.low, self.high)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pitch(s: &str) -> Pitch {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn accidentals_are_read_every_way_they_are_written() {
+        assert_eq!(PitchClass::parse_prefix("B flat"), Ok((PitchClass::new(Letter::B, -1), "")));
+        assert_eq!(PitchClass::parse_prefix("Bb"), Ok((PitchClass::new(Letter::B, -1), "")));
+        assert_eq!(PitchClass::parse_prefix("B-flat4"), Ok((PitchClass::new(Letter::B, -1), "4")));
+        assert_eq!(PitchClass::parse_prefix("F𝄪3"), Ok((PitchClass::new(Letter::F, 2), "3")));
+        assert_eq!(PitchClass::parse_prefix("c sharp minor"), Ok((PitchClass::new(Letter::C, 1), " minor")));
+        assert!(PitchClass::parse_prefix("Bbbb").is_err());
+        assert!(PitchClass::parse_prefix("H").is_err());
+    }
+
+    #[test]
+    fn pitches() {
+        let c_sharp = pitch("C#4");
+        assert_eq!(c_sharp, Pitch::new(PitchClass::new(Letter::C, 1), 4));
+        assert_eq!(c_sharp.to_string(), "C♯4");
+        assert_eq!(c_sharp.number(), pitch("D♭4").number());
+        assert_ne!(c_sharp, pitch("D♭4"));
+        assert_eq!("Bb".parse::<PitchClass>().unwrap().to_string(), "B♭");
+        assert_eq!("Bb".parse::<PitchClass>().unwrap().semitones(), 10);
+        assert!("Bb".parse::<Pitch>().is_err());
+        for s in ["C♯4", "B♭3", "F♯♯5", "C♭-1", "G𝄫2"] {
+            assert_eq!(pitch(s).to_string().parse::<Pitch>(), Ok(pitch(s)));
+        }
+    }
+
+    #[test]
+    fn intervals() {
+        for s in ["dd5", "m7", "P1", "A4", "d7", "M10", "P8", "AA6"] {
+            assert_eq!(s.parse::<Interval>().unwrap().to_string(), s);
+        }
+        assert_eq!("dd5".parse::<Interval>().unwrap().semitones(), 5);
+        assert_eq!("m7".parse::<Interval>().unwrap().semitones(), 10);
+        assert!("P3".parse::<Interval>().is_err());
+        assert!("M0".parse::<Interval>().is_err());
+
+        assert_eq!(Interval::between(&pitch("E4"), &pitch("G4")).to_string(), "m3");
+        assert_eq!(Interval::between(&pitch("G4"), &pitch("E4")).to_string(), "m3");
+        assert_eq!(pitch("E4").up("A2".parse().unwrap()).unwrap().to_string(), "F♯♯4");
+        assert_eq!(pitch("C4").down("M3".parse().unwrap()).unwrap().to_string(), "A♭3");
+    }
+
+    #[test]
+    fn signatures_give_the_key_in_each_mode() {
+        let key = |sharps: i8, mode: Mode| KeySignature::new(sharps).key(mode).to_string();
+        assert_eq!(key(3, Mode::Major), "A major");
+        assert_eq!(key(3, Mode::Minor), "F♯ minor");
+        assert_eq!(key(0, Mode::Dorian), "D dorian");
+        assert_eq!(key(1, Mode::Phrygian), "B phrygian");
+        assert_eq!(key(-1, Mode::Lydian), "B♭ lydian");
+        assert_eq!(key(-2, Mode::Mixolydian), "F mixolydian");
+        assert_eq!(key(0, Mode::Locrian), "B locrian");
+        assert_eq!(key(-6, Mode::Minor), "E♭ minor");
+        for sharps in -7..=7 {
+            for mode in Mode::ALL {
+                assert_eq!(KeySignature::new(sharps).key(mode).signature(), KeySignature::new(sharps));
+            }
+        }
+    }
+
+    #[test]
+    fn keys() {
+        let f_sharp_minor: Key = "F♯ minor".parse().unwrap();
+        assert_eq!(f_sharp_minor, Key::new(PitchClass::new(Letter::F, 1), Mode::Minor));
+        assert_eq!(f_sharp_minor.signature().to_string(), "3♯");
+        assert_eq!("Bb".parse::<Key>().unwrap().to_string(), "B♭ major");
+        assert_eq!("Am".parse::<Key>().unwrap().to_string(), "A minor");
+        assert_eq!("d Dorian".parse::<Key>().unwrap().to_string(), "D dorian");
+        assert!("F♯ happy".parse::<Key>().is_err());
+        for s in ["F♯ minor", "B♭ major", "C♯ locrian"] {
+            assert_eq!(s.parse::<Key>().unwrap().to_string(), s);
+        }
+
+        assert_eq!("2 flats".parse(), Ok(KeySignature::new(-2)));
+        assert_eq!("none".parse(), Ok(KeySignature::new(0)));
+        assert!("8#".parse::<KeySignature>().is_err());
+        for sharps in -7..=7 {
+            let signature = KeySignature::new(sharps);
+            assert_eq!(signature.to_string().parse(), Ok(signature));
+        }
+    }
+
+    #[test]
+    fn ranges() {
+        let lowest: Range = "C-1-G0".parse().unwrap();
+        assert_eq!((lowest.low(), lowest.high()), (pitch("C-1"), pitch("G0")));
+        assert_eq!(lowest.to_string(), "C-1–G0");
+        assert_eq!(lowest.to_string().parse(), Ok(lowest));
+
+        let alto: Range = "G3–C5".parse().unwrap();
+        assert_eq!(alto.span().to_string(), "P11");
+        assert_eq!("C5 to G3".parse(), Ok(alto));
+        assert_eq!("Bb2-F4".parse::<Range>().unwrap().to_string(), "B♭2–F4");
+        assert!(alto.contains(&pitch("B♯4")));
+        assert!(!alto.contains(&pitch("C♯5")));
+        assert!(alto.covers(&"A3-A4".parse().unwrap()));
+        assert!(!alto.covers(&"A3-D5".parse().unwrap()));
+        assert!("G3".parse::<Range>().is_err());
+    }
+}
@@ -6,6 +6,17 @@ use std::io::{Read, Write};
 pub struct Config {
     pub last_db: Option<String>,
     pub ui: UIConfig,
+    #[serde(default)]
+    pub library: LibraryConfig,
+}
+
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct LibraryConfig {
+    /// Delete keywords as soon as the last piece using them lets go of them.
+    pub prune_unused_keywords: bool,
+    /// Copy attached files into the collection's attachment folder instead of linking to them.
+    #[serde(default)]
+    pub copy_attachments: bool,
 }
 
 
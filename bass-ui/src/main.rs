@@ -7,12 +7,15 @@ use std::io::Write;
 use std::path::{Path, PathBuf};
 
 use std::rc::Rc;
-use std::sync::{LazyLock, RwLock, Arc};
+use std::sync::{OnceLock, RwLock, Arc};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread::{JoinHandle, self};
 use std::time::{Instant, Duration};
 
-use libbass::db::{self, Music as DBMusic, Keyword};
+use libbass::db::{Database, Music as DBMusic, MusicCopy as DBCopy, Keyword, Loan as DBLoan, Borrower, Performance as DBPerformance, Program as DBProgram, ProgramItem, format_runtime,
+    Attachment as DBAttachment, AttachmentKind, Change, Person, Contributor as DBContributor, ContributorRole,
+    PartRange as DBPartRange, Ismn, CustomField, CustomFieldKind, CsvImport, CsvExport, SheetColumn, ImportReport, is_date, DUPLICATE_THRESHOLD};
+use libbass::theory::{Key, KeySignature, Pitch, Range};
 mod search;
 mod config;
 use config::Config;
@@ -44,16 +47,16 @@ use config::Config;
 struct WordProvider {
     thread: JoinHandle<()>,
     kill: Arc<AtomicBool>,
-    cache: Arc<RwLock<Vec<Keyword>>>,
+    cache: Arc<RwLock<Vec<(Keyword, usize)>>>,
 }
 
 impl WordProvider {
-    fn new(update_interval: Duration) -> WordProvider {
+    fn new(db: Arc<Database>, update_interval: Duration) -> WordProvider {
         let cache = Arc::new(RwLock::new(Vec::new()));
         let kill = Arc::new(AtomicBool::new(false));
         let thread_cache = cache.clone();
         let thread_kill = kill.clone();
-        *cache.write().unwrap() = Keyword::list_all().unwrap();
+        *cache.write().unwrap() = Keyword::usage_counts(&db).unwrap();
         WordProvider {
             thread: thread::spawn(move || {
                 let mut next_time = Instant::now();
@@ -63,7 +66,7 @@ impl WordProvider {
                         continue;
                     }
 
-                    let mut keys = Keyword::list_all().unwrap();
+                    let mut keys = Keyword::usage_counts(&db).unwrap();
                     {
                         let mut writer = thread_cache.write().unwrap();
                         writer.clear();
@@ -77,7 +80,7 @@ impl WordProvider {
         }
     }
 
-    fn words(&self) -> Vec<Keyword> {
+    fn words(&self) -> Vec<(Keyword, usize)> {
         self.cache.read().unwrap().clone()
     }
 
@@ -90,33 +93,99 @@ impl WordProvider {
     }
 }
 
-static WORD_PROVIDER: LazyLock<WordProvider> = LazyLock::new(|| {
-    WordProvider::new(Duration::from_secs(5))
-});
+static WORD_PROVIDER: OnceLock<WordProvider> = OnceLock::new();
 
 static CURRENT_SEARCH: RwLock<Option<UISearch>> = RwLock::new(None);
 
 fn music_from_ui(m: &Music) -> DBMusic {
-    let mut dbm = DBMusic::new_with_id(m.id.into());
+    let mut dbm = DBMusic::new_with_id(m.id);
     dbm.title = m.title.clone().into();
     dbm.source = m.source.clone().into();
     dbm.composer = if m.composer.is_empty() {None} else {Some(m.composer.clone().into())};
     dbm.arranger = if m.arranger.is_empty() {None} else {Some(m.arranger.clone().into())};
+    dbm.key = m.key.parse().ok();
+    dbm.range = m.range.parse().ok();
+    dbm.voicing = if m.voicing.is_empty() {None} else {Some(m.voicing.clone().into())};
+    dbm.grade = parse_grade(&m.grade);
+    dbm.publisher = if m.publisher.is_empty() {None} else {Some(m.publisher.clone().into())};
+    dbm.catalog_number = if m.catalog_number.is_empty() {None} else {Some(m.catalog_number.clone().into())};
+    dbm.ismn = m.ismn.parse().ok();
+    dbm.copyright_year = parse_year(&m.copyright_year);
+    dbm.language = if m.language.is_empty() {None} else {Some(m.language.clone().into())};
     dbm.notes = if m.notes.is_empty() {None} else {Some(m.notes.clone().into())};
     dbm.runtime = if m.runtime == 0 {None} else {
-        let time: i32 = m.runtime.into();
+        let time: i32 = m.runtime;
         Some(time as u16)
     };
     dbm
 }
 
+fn copy_to_ui(c: DBCopy) -> MusicCopy {
+    MusicCopy {
+        id: c.id().unwrap_or(-1),
+        part: c.part.unwrap_or_default().into(),
+        count: c.count as i32,
+        location: c.location.unwrap_or_default().into(),
+        condition: c.condition.unwrap_or_default().into(),
+        accession: c.accession.unwrap_or_default().into(),
+    }
+}
+
+fn performance_to_ui(p: DBPerformance) -> Performance {
+    Performance {
+        id: p.id().unwrap_or(-1),
+        date: p.date.into(),
+        venue: p.venue.unwrap_or_default().into(),
+        ensemble: p.ensemble.unwrap_or_default().into(),
+    }
+}
+
+fn attachment_to_ui(a: DBAttachment) -> Attachment {
+    let location = match a.path.as_deref() {
+        _ if a.is_embedded() => "stored in the database".into(),
+        Some(p) if Path::new(p).is_absolute() => p.to_string(),
+        Some(p) => format!("attachments/{}", p),
+        None => String::new(),
+    };
+    Attachment {
+        id: a.id().unwrap_or(-1),
+        kind: a.kind.to_string().into(),
+        name: a.name().into(),
+        location: location.into(),
+    }
+}
+
+fn history_to_ui(c: Change) -> HistoryEntry {
+    HistoryEntry {
+        id: c.id(),
+        changed_at: format!("{} UTC", c.changed_at).into(),
+        description: c.to_string().into(),
+    }
+}
+
+fn open_with_system(path: &Path) -> std::io::Result<()> {
+    #[cfg(target_os = "macos")]
+    let mut command = std::process::Command::new("open");
+    #[cfg(target_os = "windows")]
+    let mut command = {
+        let mut c = std::process::Command::new("cmd");
+        c.args(["/C", "start", ""]);
+        c
+    };
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let mut command = std::process::Command::new("xdg-open");
+    command.arg(path).spawn().map(|_| ())
+}
+
 fn words_by_hint(hint: slint::SharedString) -> slint::ModelRc<slint::SharedString> {
     let hint = hint.to_string();
-    let words = WORD_PROVIDER.words();
-    let keys: Vec<slint::SharedString> = words.into_iter().filter_map(|k| {
-        (k.category.as_ref().map_or(false, |c| c.starts_with(&hint)) || k.keyword.starts_with(&hint))
-            .then(|| k.to_string().into())
+    let words = WORD_PROVIDER.get().map(|p| p.words()).unwrap_or_default();
+    let mut matches: Vec<(Keyword, usize)> = words.into_iter().filter(|(k, _)| {
+        k.category.as_ref().is_some_and(|c| c.starts_with(&hint)) || k.keyword.starts_with(&hint)
     }).collect();
+    // Most used first
+    matches.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    let keys: Vec<slint::SharedString> = matches.into_iter().map(|(k, _)| k.to_string().into()).collect();
     Rc::new(slint::VecModel::from(keys)).into()
 }
 
@@ -174,6 +243,358 @@ fn validate_time(t: slint::SharedString) -> bool {
     }))
 }
 
+// ISO 8601 calendar dates only, like 2024-09-30
+fn validate_date(d: slint::SharedString) -> bool {
+    is_date(&d)
+}
+
+fn validate_key(k: slint::SharedString) -> bool {
+    k.parse::<Key>().is_ok()
+}
+
+fn validate_key_signature(k: slint::SharedString) -> bool {
+    k.parse::<KeySignature>().is_ok()
+}
+
+fn validate_pitch(p: slint::SharedString) -> bool {
+    p.parse::<Pitch>().is_ok()
+}
+
+fn validate_range(r: slint::SharedString) -> bool {
+    r.parse::<Range>().is_ok()
+}
+
+fn parse_grade(g: &str) -> Option<f64> {
+    g.trim().parse().ok().filter(|g: &f64| g.is_finite() && *g >= 0.0)
+}
+
+fn validate_grade(g: slint::SharedString) -> bool {
+    parse_grade(&g).is_some()
+}
+
+fn validate_ismn(i: slint::SharedString) -> bool {
+    i.parse::<Ismn>().is_ok()
+}
+
+fn parse_year(y: &str) -> Option<i32> {
+    y.trim().parse().ok().filter(|y| (1..=9999).contains(y))
+}
+
+fn validate_year(y: slint::SharedString) -> bool {
+    parse_year(&y).is_some()
+}
+
+fn keyword_usage(db: &Database) -> slint::ModelRc<KeywordUsage> {
+    let usage = attempt!(Keyword::usage_counts(db)).into_iter().map(|(k, count)| {
+        KeywordUsage {
+            id: assume!(k.id()),
+            category: k.category.unwrap_or_default().into(),
+            keyword: k.keyword.into(),
+            count: count as i32,
+        }
+    });
+    Rc::new(slint::VecModel::from_iter(usage)).into()
+}
+
+fn people_usage(db: &Database) -> slint::ModelRc<PersonUsage> {
+    let year = |y: Option<i32>| y.map(|y| y.to_string()).unwrap_or_default().into();
+    let usage = attempt!(Person::usage_counts(db)).into_iter().map(|(p, count)| {
+        PersonUsage {
+            id: assume!(p.id()),
+            aliases: attempt!(p.aliases(db)).join("; ").into(),
+            name: p.name.into(),
+            sort_name: p.sort_name.unwrap_or_default().into(),
+            born: year(p.born),
+            died: year(p.died),
+            count: count as i32,
+        }
+    });
+    Rc::new(slint::VecModel::from_iter(usage)).into()
+}
+
+fn custom_field_usage(db: &Database) -> slint::ModelRc<CustomFieldUsage> {
+    let usage = attempt!(CustomField::usage_counts(db)).into_iter().map(|(f, count)| {
+        CustomFieldUsage {
+            id: assume!(f.id()),
+            name: f.name.into(),
+            kind: f.kind.to_string().into(),
+            choices: f.choices.join("; ").into(),
+            count: count as i32,
+        }
+    });
+    Rc::new(slint::VecModel::from_iter(usage)).into()
+}
+
+// Every custom field, with the piece's value for it if it has one
+fn custom_values_to_ui(db: &Database, m: Option<&DBMusic>) -> slint::ModelRc<CustomFieldValue> {
+    let values = match m {
+        Some(m) => assume!(attempt!(m.custom_values(db))),
+        None => Vec::new(),
+    };
+    let fields = attempt!(CustomField::list_all(db)).into_iter().map(|f| {
+        let value = values.iter().find(|(v, _)| v.id() == f.id()).map(|(_, v)| v.to_string()).unwrap_or_default();
+        let choices = f.choices.iter().map(|c| slint::SharedString::from(c.as_str()));
+        CustomFieldValue {
+            field_id: assume!(f.id()),
+            kind: f.kind.to_string().into(),
+            choices: Rc::new(slint::VecModel::from_iter(choices)).into(),
+            name: f.name.into(),
+            value: value.into(),
+        }
+    });
+    Rc::new(slint::VecModel::from_iter(fields)).into()
+}
+
+// What the main list is showing: the results of the current search, or the whole library
+fn current_music(db: &Database) -> Vec<DBMusic> {
+    current_results(db).into_iter().map(|(m, _)| m).collect()
+}
+
+// `current_music`, with the snippet of each piece the search matched in
+fn current_results(db: &Database) -> Vec<(DBMusic, String)> {
+    match *attempt!(CURRENT_SEARCH.read()) {
+        Some(ref s) => {
+            let text = s.search_text.to_string();
+            let search: search::Search = attempt!(text.parse());
+            attempt!(search.execute_with_snippets(db))
+        }
+        None => {
+            attempt!(DBMusic::list_all(db)).into_iter().map(|m| (m, String::new())).collect()
+        }
+    }
+}
+
+// A column by the name it's shown with, which is a custom field's name if it isn't a built-in one
+fn sheet_column(db: &Database, name: &str) -> Option<SheetColumn> {
+    match name.parse() {
+        Ok(column) => Some(column),
+        Err(_) => attempt!(CustomField::by_name(db, name)).map(SheetColumn::Custom),
+    }
+}
+
+// Builds the import the dialog describes, with the columns mapped the way they're shown
+fn import_from_ui(db: &Database, dialog: &ImportDialog) -> CsvImport {
+    let mut import = CsvImport::for_file(dialog.get_file().as_str());
+    import.has_headers = dialog.get_has_headers();
+    import.keyword_delimiter = dialog.get_keyword_delimiter().trim().chars().next().unwrap_or(';');
+    import.columns = dialog.get_columns().iter().map(|c| sheet_column(db, &c.target)).collect();
+    import
+}
+
+fn import_report_to_ui(report: &ImportReport) -> slint::ModelRc<ImportReportLine> {
+    let mut lines: Vec<_> = report.rows.iter().map(|row| (row.line, ImportReportLine {
+        text: format!("line {}: {} ({})", row.line, row.music.title, music_detail(&row.music)).into(),
+        error: false,
+    })).chain(report.errors.iter().map(|e| (e.line, ImportReportLine {
+        text: e.to_string().into(),
+        error: true,
+    }))).collect();
+    lines.sort_by_key(|(line, _)| *line);
+    Rc::new(slint::VecModel::from_iter(lines.into_iter().map(|(_, l)| l))).into()
+}
+
+fn copy_label(c: &DBCopy, count: u32) -> String {
+    format!("{} × {}", count, c.part.as_deref().unwrap_or("complete set"))
+}
+
+fn loan_to_ui(db: &Database, l: DBLoan) -> Loan {
+    let copy = attempt!(DBCopy::by_id(db, l.copy_id()));
+    let title = match copy.as_ref() {
+        Some(c) => attempt!(DBMusic::by_id(db, c.music_id())).map(|m| m.title).unwrap_or_default(),
+        None => String::new(),
+    };
+    let borrower = attempt!(Borrower::by_id(db, l.borrower_id())).map(|b| b.name).unwrap_or_default();
+    Loan {
+        id: l.id().unwrap_or(-1),
+        title: title.into(),
+        copy: copy.map(|c| copy_label(&c, l.count)).unwrap_or_default().into(),
+        borrower: borrower.into(),
+        checked_out: l.checked_out.clone().into(),
+        due: l.due.clone().unwrap_or_default().into(),
+        overdue: l.is_overdue(),
+    }
+}
+
+fn loan_report(db: &Database, all: bool) -> slint::ModelRc<Loan> {
+    let loans = if all {
+        attempt!(DBLoan::outstanding(db))
+    } else {
+        attempt!(DBLoan::overdue(db))
+    };
+    Rc::new(slint::VecModel::from_iter(loans.into_iter().map(|l| loan_to_ui(db, l)))).into()
+}
+
+fn trash_list(db: &Database) -> slint::ModelRc<TrashedMusic> {
+    let music = attempt!(DBMusic::list_trash(db)).into_iter().map(|m| {
+        TrashedMusic {
+            id: assume!(m.id()),
+            deleted_at: m.deleted_at().unwrap_or_default().into(),
+            title: m.title.into(),
+            composer: m.composer.unwrap_or_default().into(),
+        }
+    });
+    Rc::new(slint::VecModel::from_iter(music)).into()
+}
+
+fn music_detail(m: &DBMusic) -> String {
+    match &m.composer {
+        Some(composer) => format!("{} · {}", composer, m.source),
+        None => m.source.clone(),
+    }
+}
+
+fn duplicate_list(db: &Database) -> slint::ModelRc<DuplicatePair> {
+    let pairs = attempt!(DBMusic::find_duplicates(db, DUPLICATE_THRESHOLD)).into_iter().map(|d| {
+        DuplicatePair {
+            first_id: assume!(d.first.id()),
+            first_detail: music_detail(&d.first).into(),
+            first_title: d.first.title.into(),
+            second_id: assume!(d.second.id()),
+            second_detail: music_detail(&d.second).into(),
+            second_title: d.second.title.into(),
+            score: format!("{:.0}%", d.score * 100.0).into(),
+        }
+    });
+    Rc::new(slint::VecModel::from_iter(pairs)).into()
+}
+
+// Files copied into the library for a piece, which have to go when the piece does.
+fn managed_files(db: &Database, folder: &Path, m: &DBMusic) -> Vec<PathBuf> {
+    assume!(attempt!(m.attachments(db))).into_iter()
+        .filter(|a| a.path.as_ref().is_some_and(|p| Path::new(p).is_relative()))
+        .filter_map(|a| a.resolve(folder))
+        .collect()
+}
+
+fn remove_files(paths: &[PathBuf]) {
+    for path in paths {
+        if let Err(e) = std::fs::remove_file(path) {
+            warn!("Could not remove {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn program_details(db: &Database, p: &DBProgram) -> ProgramDetails {
+    let timings = attempt!(p.timings(db));
+    let entries = p.items.iter().zip(&timings).map(|(item, timing)| {
+        let (kind, label, detail) = match item {
+            ProgramItem::Piece(id) => match attempt!(DBMusic::by_id(db, *id)) {
+                Some(m) => {
+                    let detail = match (m.composer, m.arranger) {
+                        (Some(c), Some(a)) => format!("{} / arr. {}", c, a),
+                        (Some(c), None) => c,
+                        (None, Some(a)) => format!("arr. {}", a),
+                        (None, None) => String::new(),
+                    };
+                    ("piece", m.title, detail)
+                }
+                None => ("piece", String::new(), String::new()),
+            },
+            ProgramItem::Intermission { label, .. } => ("intermission", label.clone().unwrap_or("Intermission".into()), String::new()),
+            ProgramItem::Spoken { label, .. } => ("spoken", label.clone().unwrap_or("Spoken".into()), String::new()),
+        };
+        ProgramEntry {
+            kind: kind.into(),
+            label: label.into(),
+            detail: detail.into(),
+            duration: timing.duration.map_or("?".into(), |d| format_runtime(d as u32)).into(),
+            ends_at: format_runtime(timing.ends_at).into(),
+        }
+    });
+    ProgramDetails {
+        id: p.id().unwrap_or(-1),
+        name: p.name.clone().into(),
+        date: p.date.clone().unwrap_or_default().into(),
+        total: format_runtime(timings.last().map_or(0, |t| t.ends_at)).into(),
+        entries: Rc::new(slint::VecModel::from_iter(entries)).into(),
+    }
+}
+
+// Reloads the program list, showing `select` if given, or else whichever program was showing.
+fn refresh_programs(main_window: &Bass, db: &Database, select: Option<i32>) {
+    let programs = attempt!(DBProgram::list_all(db));
+    let select = select.unwrap_or(main_window.get_program().id);
+    let index = programs.iter().position(|p| p.id() == Some(select))
+        .unwrap_or(0);
+    let names: Vec<slint::SharedString> = programs.iter().map(|p| p.name.clone().into()).collect();
+    let ids: Vec<i32> = programs.iter().map(|p| assume!(p.id())).collect();
+    main_window.set_program_names(Rc::new(slint::VecModel::from(names)).into());
+    main_window.set_program_ids(Rc::new(slint::VecModel::from(ids)).into());
+    match programs.get(index) {
+        Some(p) => {
+            main_window.set_program_index(index as i32);
+            main_window.set_program(program_details(db, p));
+        }
+        None => {
+            main_window.set_program_index(-1);
+            main_window.set_program(ProgramDetails {id: -1, ..Default::default()});
+        }
+    }
+}
+
+fn edit_program<F: FnOnce(&mut DBProgram)>(db: &Database, id: i32, f: F) {
+    let Some(mut program) = attempt!(DBProgram::by_id(db, id)) else {
+        return;
+    };
+    f(&mut program);
+    if let Err(e) = program.insert(db) {
+        warn!("Could not save program {}: {}", id, e);
+    }
+}
+
+// A piece as the list shows it. The rest of what the detailed view needs comes from
+// `load_details`, once the piece is selected.
+fn music_to_ui(db: &Database, m: DBMusic) -> Music {
+    let keywords = assume!(attempt!(m.keywords(db)));
+    let keywords: Vec<slint::SharedString> = keywords.into_iter().map(|k| k.to_string().into()).collect();
+    Music {
+        id: assume!(m.id()),
+        title: m.title.into(),
+        source: m.source.into(),
+        composer: m.composer.unwrap_or("".into()).into(),
+        arranger: m.arranger.unwrap_or("".into()).into(),
+        key: m.key.map(|k| k.to_string()).unwrap_or_default().into(),
+        range: m.range.map(|r| r.to_string()).unwrap_or_default().into(),
+        voicing: m.voicing.unwrap_or_default().into(),
+        grade: m.grade.map(|g| g.to_string()).unwrap_or_default().into(),
+        publisher: m.publisher.unwrap_or_default().into(),
+        catalog_number: m.catalog_number.unwrap_or_default().into(),
+        ismn: m.ismn.map(|i| i.to_string()).unwrap_or_default().into(),
+        copyright_year: m.copyright_year.map(|y| y.to_string()).unwrap_or_default().into(),
+        language: m.language.unwrap_or_default().into(),
+        notes: m.notes.unwrap_or("".into()).into(),
+        runtime: m.runtime.unwrap_or(0).into(),
+        keywords: Rc::new(slint::VecModel::from(keywords)).into(),
+        ..Default::default()
+    }
+}
+
+fn load_details(db: &Database, mut m: Music) -> Music {
+    let music = DBMusic::new_with_id(m.id);
+    let copies = assume!(attempt!(music.copies(db)));
+    m.copies = Rc::new(slint::VecModel::from_iter(copies.into_iter().map(copy_to_ui))).into();
+    let loans = assume!(attempt!(music.outstanding_loans(db)));
+    m.loans = Rc::new(slint::VecModel::from_iter(loans.into_iter().map(|l| loan_to_ui(db, l)))).into();
+    let performances = assume!(attempt!(music.performances(db)));
+    m.performances = Rc::new(slint::VecModel::from_iter(performances.into_iter().map(performance_to_ui))).into();
+    let attachments = assume!(attempt!(music.attachments(db)));
+    m.attachments = Rc::new(slint::VecModel::from_iter(attachments.into_iter().map(attachment_to_ui))).into();
+    m.custom = custom_values_to_ui(db, Some(&music));
+    let contributors = assume!(attempt!(music.contributors(db)));
+    m.contributors = Rc::new(slint::VecModel::from_iter(contributors.into_iter().map(|c| Contributor {
+        role: c.role.to_string().into(),
+        name: c.name.into(),
+    }))).into();
+    let part_ranges = assume!(attempt!(music.part_ranges(db)));
+    m.part_ranges = Rc::new(slint::VecModel::from_iter(part_ranges.into_iter().map(|p| PartRange {
+        part: p.part.into(),
+        range: p.range.to_string().into(),
+    }))).into();
+    let history = assume!(attempt!(music.history(db, 15)));
+    m.history = Rc::new(slint::VecModel::from_iter(history.into_iter().map(history_to_ui))).into();
+    m
+}
+
 #[allow(dead_code)]
 fn find_dbs<P: AsRef<Path>>(file: P) -> std::io::Result<Vec<PathBuf>> {
     Ok(std::fs::read_dir(file)?.filter_map(|entry| {
@@ -203,6 +624,7 @@ fn main() -> Result<(), slint::PlatformError> {
         std::fs::File::create(file_root.join("log.txt")).unwrap()).unwrap();
 
     let database_files = file_root.join("dbs");
+    let attachment_files = file_root.join("attachments");
     if !database_files.is_dir() {
         attempt!(std::fs::create_dir(&database_files));
     }
@@ -210,17 +632,47 @@ fn main() -> Result<(), slint::PlatformError> {
     let last_db = attempt!(config.read()).last_db.clone().unwrap_or("collection.sqlite3".into());
     // TODO maybe someday. Allow for multiple different dbs.
 
-    attempt!(db::init(Some(database_files.join(&last_db))));
+    let database = Arc::new(attempt!(Database::open(database_files.join(&last_db))));
+    database.set_prune_unused_keywords(attempt!(config.read()).library.prune_unused_keywords);
     attempt!(config.write()).last_db = Some("collection.sqlite3".into());
+    let _ = WORD_PROVIDER.set(WordProvider::new(database.clone(), Duration::from_secs(5)));
     
     let main_window = Bass::new()?;
+    main_window.set_copy_attachments(attempt!(config.read()).library.copy_attachments);
     let add_dialog = AddDialog::new()?;
     let search_dialog = SearchDialog::new()?;
+    let keyword_manager = KeywordManager::new()?;
+    let people_manager = PeopleManager::new()?;
+    let custom_field_manager = CustomFieldManager::new()?;
+    let import_dialog = ImportDialog::new()?;
+    let export_dialog = ExportDialog::new()?;
+    let loan_report_window = LoanReport::new()?;
+    let trash_window = TrashView::new()?;
+    let duplicates_window = DuplicatesView::new()?;
 
     main_window.global::<KeywordInputLogic>().on_words(words_by_hint);
     add_dialog.global::<KeywordInputLogic>().on_words(words_by_hint);
     search_dialog.global::<KeywordInputLogic>().on_words(words_by_hint);
+    keyword_manager.global::<KeywordInputLogic>().on_words(words_by_hint);
     search_dialog.global::<BusinessLogic>().on_validate_time(validate_time);
+    main_window.global::<BusinessLogic>().on_validate_date(validate_date);
+    main_window.global::<BusinessLogic>().on_validate_time(validate_time);
+    for logic in [main_window.global::<BusinessLogic>(), add_dialog.global::<BusinessLogic>(), search_dialog.global::<BusinessLogic>()] {
+        logic.on_validate_key(validate_key);
+        logic.on_validate_key_signature(validate_key_signature);
+        logic.on_validate_pitch(validate_pitch);
+        logic.on_validate_range(validate_range);
+        logic.on_validate_grade(validate_grade);
+        logic.on_validate_ismn(validate_ismn);
+        logic.on_validate_year(validate_year);
+        let db = database.clone();
+        logic.on_validate_custom(move |id, value| {
+            match attempt!(CustomField::by_id(&db, id)) {
+                Some(field) => field.parse_value(&value).is_ok(),
+                None => false,
+            }
+        });
+    }
 
     add_dialog.on_validate_time(validate_time);
     let weak_add = add_dialog.as_weak();
@@ -257,30 +709,77 @@ fn main() -> Result<(), slint::PlatformError> {
 
     let weak_add = add_dialog.as_weak();
     let weak_main = main_window.as_weak();
+    let db = database.clone();
     add_dialog.on_submit(move |out, runtime| {
         let dialog = weak_add.unwrap();
         let main_window = weak_main.unwrap();
         let mut music = DBMusic::new();
         music.title = out.title.into();
         music.source = out.source.into();
-        if out.composer.len() > 0 {
+        if !out.composer.is_empty() {
             music.composer = Some(out.composer.into());
         }
-        if out.arranger.len() > 0 {
+        if !out.arranger.is_empty() {
             music.arranger = Some(out.arranger.into());
         }
-        if out.notes.len() > 0 {
+        music.key = out.key.parse().ok();
+        music.range = out.range.parse().ok();
+        if !out.voicing.is_empty() {
+            music.voicing = Some(out.voicing.into());
+        }
+        music.grade = parse_grade(&out.grade);
+        if !out.publisher.is_empty() {
+            music.publisher = Some(out.publisher.into());
+        }
+        if !out.catalog_number.is_empty() {
+            music.catalog_number = Some(out.catalog_number.into());
+        }
+        music.ismn = out.ismn.parse().ok();
+        music.copyright_year = parse_year(&out.copyright_year);
+        if !out.language.is_empty() {
+            music.language = Some(out.language.into());
+        }
+        if !out.notes.is_empty() {
             music.notes = Some(out.notes.into());
         }
-        if runtime.len() > 0 {
+        if !runtime.is_empty() {
             let runtime = runtime.rsplit(":").enumerate().fold(0, |acc, (i, r)| {
                 let r: u16 = r.parse().unwrap();
                 acc + r * 60u16.pow(i as u32)
             });
             music.runtime = Some(runtime);
         }
+        let mut custom = Vec::new();
+        for c in out.custom.iter().filter(|c| !c.value.is_empty()) {
+            let field = assume!(attempt!(CustomField::by_id(&db, c.field_id)));
+            match field.parse_value(&c.value) {
+                Ok(value) => custom.push((field, value)),
+                Err(e) => {
+                    dialog.invoke_error(format!("{}: {}", c.name, e).into());
+                    return;
+                }
+            }
+        }
+        if let Some((existing, _)) = attempt!(music.likely_duplicates(&db, DUPLICATE_THRESHOLD)).first() {
+            let add_anyway = rfd::MessageDialog::new()
+                .set_title("Possible Duplicate")
+                .set_description(format!("\"{}\" ({}) is already in the library and looks like the same piece. Add this one anyway?",
+                    existing.title, music_detail(existing)))
+                .set_buttons(rfd::MessageButtons::YesNo)
+                .set_parent(&dialog.window().window_handle())
+                .show();
+            if add_anyway != rfd::MessageDialogResult::Yes {
+                return;
+            }
+        }
         let mut keywords: Vec<Keyword> = out.keywords.iter().map(|k| k.parse().unwrap()).collect();
-        attempt!(music.insert_with_keywords(&mut keywords));
+        attempt!(db.transaction(|db| {
+            music.insert_with_keywords(db, &mut keywords)?;
+            for (field, value) in &custom {
+                music.set_custom_value(db, field, Some(value))?;
+            }
+            Ok(())
+        }));
         main_window.invoke_trigger_refresh();
         let _ = dialog.hide();
         dialog.invoke_clear_form();
@@ -302,45 +801,723 @@ fn main() -> Result<(), slint::PlatformError> {
     });
     
 
+    let weak_manager = keyword_manager.as_weak();
+    keyword_manager.on_close_clicked(move || {
+        let manager = weak_manager.unwrap();
+        attempt!(manager.hide());
+    });
+
+    let weak_manager = keyword_manager.as_weak();
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    keyword_manager.on_relabel(move |id, category, keyword| {
+        let manager = weak_manager.unwrap();
+        let main_window = weak_main.unwrap();
+        let mut key = assume!(attempt!(Keyword::by_id(&db, id)));
+        let category = if category.is_empty() {None} else {Some(category.as_str())};
+        let result = db.transaction(|db| {
+            key.recategorize(db, category)?;
+            key.rename(db, &keyword)
+        });
+        if let Err(e) = result {
+            warn!("Could not update keyword {}: {}", id, e);
+        }
+        manager.invoke_clear_selection();
+        manager.set_keywords(keyword_usage(&db));
+        main_window.invoke_trigger_refresh();
+    });
+
+    let weak_manager = keyword_manager.as_weak();
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    keyword_manager.on_merge(move |id, target| {
+        let manager = weak_manager.unwrap();
+        let main_window = weak_main.unwrap();
+        let key = assume!(attempt!(Keyword::by_id(&db, id)));
+        let mut target: Keyword = target.parse().unwrap();
+        if let Err(e) = key.merge_into(&db, &mut target) {
+            warn!("Could not merge keyword {} into {}: {}", id, target, e);
+        }
+        manager.set_keywords(keyword_usage(&db));
+        main_window.invoke_trigger_refresh();
+    });
+
+    let weak_manager = keyword_manager.as_weak();
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    keyword_manager.on_delete(move |id| {
+        let manager = weak_manager.unwrap();
+        let main_window = weak_main.unwrap();
+        let key = assume!(attempt!(Keyword::by_id(&db, id)));
+        attempt!(key.delete(&db));
+        manager.set_keywords(keyword_usage(&db));
+        main_window.invoke_trigger_refresh();
+    });
+
+    let weak_manager = keyword_manager.as_weak();
+    let db = database.clone();
+    keyword_manager.on_prune_unused(move || {
+        let manager = weak_manager.unwrap();
+        let removed = attempt!(Keyword::prune_unused(&db));
+        info!("Removed {} unused keywords", removed);
+        manager.set_keywords(keyword_usage(&db));
+    });
+
+    let weak_manager = keyword_manager.as_weak();
+    let db = database.clone();
+    main_window.on_show_keyword_manager(move || {
+        let manager = weak_manager.unwrap();
+        manager.invoke_clear_selection();
+        manager.set_keywords(keyword_usage(&db));
+        attempt!(manager.show());
+    });
+
+    let weak_people = people_manager.as_weak();
+    people_manager.on_close_clicked(move || {
+        let manager = weak_people.unwrap();
+        attempt!(manager.hide());
+    });
+
+    let weak_people = people_manager.as_weak();
+    let db = database.clone();
+    people_manager.on_select(move |id| {
+        let manager = weak_people.unwrap();
+        let person = assume!(attempt!(Person::by_id(&db, id)));
+        let similar = attempt!(person.similar(&db, 0.8)).into_iter().map(|(p, _)| slint::SharedString::from(p.name));
+        manager.set_similar(Rc::new(slint::VecModel::from_iter(similar)).into());
+    });
+
+    let weak_people = people_manager.as_weak();
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    people_manager.on_save(move |id, name, sort_name, born, died, aliases| {
+        let manager = weak_people.unwrap();
+        let main_window = weak_main.unwrap();
+        let mut person = assume!(attempt!(Person::by_id(&db, id)));
+        person.name = name.into();
+        person.sort_name = Some(sort_name.trim().to_string()).filter(|s| !s.is_empty());
+        person.born = born.trim().parse().ok();
+        person.died = died.trim().parse().ok();
+        let aliases: Vec<String> = aliases.split(';').map(String::from).collect();
+        let result = db.transaction(|db| {
+            person.insert(db)?;
+            person.set_aliases(db, &aliases)
+        });
+        if let Err(e) = result {
+            warn!("Could not update person {}: {}", id, e);
+        }
+        manager.invoke_clear_selection();
+        manager.set_people(people_usage(&db));
+        main_window.invoke_trigger_refresh();
+    });
+
+    let weak_people = people_manager.as_weak();
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    people_manager.on_merge(move |id, target| {
+        let manager = weak_people.unwrap();
+        let main_window = weak_main.unwrap();
+        let person = assume!(attempt!(Person::by_id(&db, id)));
+        let Some(target) = attempt!(Person::by_name(&db, &target)) else {
+            warn!("No one called {} to merge {} into", target, person);
+            return;
+        };
+        if let Err(e) = person.merge_into(&db, &target) {
+            warn!("Could not merge person {} into {}: {}", id, target, e);
+        }
+        manager.set_people(people_usage(&db));
+        main_window.invoke_trigger_refresh();
+    });
+
+    let weak_people = people_manager.as_weak();
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    people_manager.on_delete(move |id| {
+        let manager = weak_people.unwrap();
+        let main_window = weak_main.unwrap();
+        let person = assume!(attempt!(Person::by_id(&db, id)));
+        attempt!(person.delete(&db));
+        manager.set_people(people_usage(&db));
+        main_window.invoke_trigger_refresh();
+    });
+
+    let weak_people = people_manager.as_weak();
+    let db = database.clone();
+    main_window.on_show_people_manager(move || {
+        let manager = weak_people.unwrap();
+        manager.invoke_clear_selection();
+        manager.set_people(people_usage(&db));
+        attempt!(manager.show());
+    });
+
+    let weak_fields = custom_field_manager.as_weak();
+    custom_field_manager.on_close_clicked(move || {
+        let manager = weak_fields.unwrap();
+        attempt!(manager.hide());
+    });
+
+    let weak_fields = custom_field_manager.as_weak();
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    custom_field_manager.on_save(move |id, name, kind, choices| {
+        let manager = weak_fields.unwrap();
+        let main_window = weak_main.unwrap();
+        let kind = kind.parse().unwrap_or(CustomFieldKind::Text);
+        let mut field = match id {
+            -1 => CustomField::new(&name, kind),
+            id => assume!(attempt!(CustomField::by_id(&db, id))),
+        };
+        field.name = name.into();
+        field.kind = kind;
+        field.choices = choices.split(';').map(String::from).collect();
+        if let Err(e) = field.insert(&db) {
+            warn!("Could not save custom field {}: {}", field.name, e);
+        }
+        manager.set_fields(custom_field_usage(&db));
+        main_window.invoke_trigger_refresh();
+    });
+
+    let weak_fields = custom_field_manager.as_weak();
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    custom_field_manager.on_delete(move |id| {
+        let manager = weak_fields.unwrap();
+        let main_window = weak_main.unwrap();
+        let field = assume!(attempt!(CustomField::by_id(&db, id)));
+        attempt!(field.delete(&db));
+        manager.set_fields(custom_field_usage(&db));
+        main_window.invoke_trigger_refresh();
+    });
+
+    let weak_fields = custom_field_manager.as_weak();
+    let db = database.clone();
+    main_window.on_show_custom_field_manager(move || {
+        let manager = weak_fields.unwrap();
+        manager.invoke_clear_selection();
+        manager.set_fields(custom_field_usage(&db));
+        attempt!(manager.show());
+    });
+
     let weak_add = add_dialog.as_weak();
+    let db = database.clone();
     main_window.on_show_add_dialog(move || {
         let dialog = weak_add.unwrap();
+        dialog.set_custom(custom_values_to_ui(&db, None));
         attempt!(dialog.show());
     });
 
+    let db = database.clone();
     main_window.on_update_entry(move |m| {
         let mut music = music_from_ui(&m);
         let mut keywords: Vec<Keyword> = m.keywords.iter().map(|k| k.parse().unwrap()).collect();
 
-        let _ = music.update_keywords(&mut keywords);
-        let _ = music.insert();
+        let _ = music.update_keywords(&db, &mut keywords);
+        let _ = music.insert(&db);
     });
 
+    let db = database.clone();
     main_window.on_remove_keyword(move |m, idx, key| {
         let mut music = music_from_ui(&m);
         let mut key = key.parse().unwrap();
-        let _ = attempt!(music.remove_keyword(&mut key));
+        attempt!(music.remove_keyword(&db, &mut key));
 
         let keys = m.keywords.as_any().downcast_ref::<slint::VecModel<slint::SharedString>>().unwrap();
         keys.remove(idx as usize);
     });
 
+    let db = database.clone();
     main_window.on_remove_entry(move |m| {
         let music = music_from_ui(&m);
-        let _ = attempt!(music.delete());
+        attempt!(music.delete(&db));
     });
 
+    let db = database.clone();
     main_window.on_add_keyword(move |m, k| {
         let mut music = music_from_ui(&m);
         let mut key = k.parse().unwrap();
-        if !assume!(attempt!(music.keywords())).contains(&key) {
-            let _ = attempt!(music.add_keyword(&mut key));
+        if !assume!(attempt!(music.keywords(&db))).contains(&key) {
+            attempt!(music.add_keyword(&db, &mut key));
 
             let keys = m.keywords.as_any().downcast_ref::<slint::VecModel<slint::SharedString>>().unwrap();
             keys.push(k);
         }
     });
 
+    let db = database.clone();
+    main_window.on_add_copy(move |m, c| {
+        let non_empty = |s: slint::SharedString| if s.is_empty() {None} else {Some(s.to_string())};
+        let mut copy = DBCopy::new(m.id);
+        copy.part = non_empty(c.part);
+        copy.count = c.count.max(1) as u32;
+        copy.location = non_empty(c.location);
+        copy.condition = non_empty(c.condition);
+        copy.accession = non_empty(c.accession);
+        if let Err(e) = copy.insert(&db) {
+            warn!("Could not add copy to {}: {}", m.id, e);
+            return;
+        }
+
+        let copies = m.copies.as_any().downcast_ref::<slint::VecModel<MusicCopy>>().unwrap();
+        copies.push(copy_to_ui(copy));
+    });
+
+    let db = database.clone();
+    main_window.on_remove_copy(move |m, idx, c| {
+        if let Some(copy) = attempt!(DBCopy::by_id(&db, c.id)) {
+            attempt!(copy.delete(&db));
+        }
+
+        let copies = m.copies.as_any().downcast_ref::<slint::VecModel<MusicCopy>>().unwrap();
+        copies.remove(idx as usize);
+    });
+
+    let db = database.clone();
+    main_window.on_lend_copy(move |m, c, borrower, count, due| {
+        let copy = assume!(attempt!(DBCopy::by_id(&db, c.id)));
+        let due = if due.is_empty() {None} else {Some(due.as_str())};
+        let result = db.transaction(|db| {
+            let borrower = Borrower::find_or_insert(db, borrower.trim())?;
+            DBLoan::check_out(db, &copy, &borrower, count.max(1) as u32, due)
+        });
+        match result {
+            Ok(loan) => {
+                let loans = m.loans.as_any().downcast_ref::<slint::VecModel<Loan>>().unwrap();
+                loans.push(loan_to_ui(&db, loan));
+            }
+            Err(e) => warn!("Could not lend copy {} of {}: {}", c.id, m.id, e),
+        }
+    });
+
+    let db = database.clone();
+    main_window.on_return_loan(move |m, idx, l| {
+        if let Some(mut loan) = attempt!(DBLoan::by_id(&db, l.id)) {
+            attempt!(loan.check_in(&db));
+        }
+
+        let loans = m.loans.as_any().downcast_ref::<slint::VecModel<Loan>>().unwrap();
+        loans.remove(idx as usize);
+    });
+
+    let db = database.clone();
+    main_window.on_add_contributor(move |m, role, name| {
+        let music = DBMusic::new_with_id(m.id);
+        let mut contributors = assume!(attempt!(music.contributors(&db)));
+        contributors.push(DBContributor::new(&name, role.parse().unwrap_or(ContributorRole::Other)));
+        if let Err(e) = music.set_contributors(&db, &mut contributors) {
+            warn!("Could not credit {} on {}: {}", name, m.id, e);
+        }
+    });
+
+    let db = database.clone();
+    main_window.on_move_contributor(move |m, from, to| {
+        let music = DBMusic::new_with_id(m.id);
+        let mut contributors = assume!(attempt!(music.contributors(&db)));
+        if (0..contributors.len() as i32).contains(&to) {
+            contributors.swap(from as usize, to as usize);
+            attempt!(music.set_contributors(&db, &mut contributors));
+        }
+    });
+
+    let db = database.clone();
+    main_window.on_remove_contributor(move |m, idx| {
+        let music = DBMusic::new_with_id(m.id);
+        let mut contributors = assume!(attempt!(music.contributors(&db)));
+        contributors.remove(idx as usize);
+        attempt!(music.set_contributors(&db, &mut contributors));
+    });
+
+    let db = database.clone();
+    main_window.on_set_part_range(move |m, part, range| {
+        let Ok(range) = range.parse::<Range>() else {
+            warn!("Not a valid range: {}", range);
+            return;
+        };
+        let music = DBMusic::new_with_id(m.id);
+        if let Err(e) = music.set_part_range(&db, &DBPartRange::new(&part, range)) {
+            warn!("Could not set the {} range on {}: {}", part, m.id, e);
+        }
+    });
+
+    let db = database.clone();
+    main_window.on_remove_part_range(move |m, idx| {
+        let music = DBMusic::new_with_id(m.id);
+        let part_ranges = assume!(attempt!(music.part_ranges(&db)));
+        let part_range = assume!(part_ranges.get(idx as usize));
+        attempt!(music.remove_part_range(&db, &part_range.part));
+    });
+
+    let db = database.clone();
+    main_window.on_set_custom(move |m, id, value| {
+        let field = assume!(attempt!(CustomField::by_id(&db, id)));
+        let music = DBMusic::new_with_id(m.id);
+        let value = match value.trim() {
+            "" => None,
+            v => match field.parse_value(v) {
+                Ok(v) => Some(v),
+                Err(e) => {
+                    warn!("Not a valid value for {}: {}", field.name, e);
+                    return;
+                }
+            },
+        };
+        attempt!(music.set_custom_value(&db, &field, value.as_ref()));
+    });
+
+    let db = database.clone();
+    main_window.on_add_performance(move |m, p| {
+        let venue = if p.venue.is_empty() {None} else {Some(p.venue.as_str())};
+        let ensemble = if p.ensemble.is_empty() {None} else {Some(p.ensemble.as_str())};
+        let mut performance = attempt!(DBPerformance::find_or_new(&db, &p.date, venue, ensemble));
+        if performance.music.contains(&m.id) {
+            return;
+        }
+        performance.music.push(m.id);
+        if let Err(e) = performance.insert(&db) {
+            warn!("Could not add {} to performance on {}: {}", m.id, p.date, e);
+            return;
+        }
+
+        // Keep the list most recent first
+        let performances = m.performances.as_any().downcast_ref::<slint::VecModel<Performance>>().unwrap();
+        let idx = performances.iter().position(|other| other.date < p.date).unwrap_or(performances.row_count());
+        performances.insert(idx, performance_to_ui(performance));
+    });
+
+    let db = database.clone();
+    main_window.on_remove_performance(move |m, idx, p| {
+        if let Some(mut performance) = attempt!(DBPerformance::by_id(&db, p.id)) {
+            performance.music.retain(|&id| id != m.id);
+            attempt!(performance.insert(&db));
+        }
+
+        let performances = m.performances.as_any().downcast_ref::<slint::VecModel<Performance>>().unwrap();
+        performances.remove(idx as usize);
+    });
+
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    let folder = attachment_files.clone();
+    main_window.on_add_attachment(move |m, kind, label, copy| {
+        let main_window = weak_main.unwrap();
+        let file_name = rfd::FileDialog::new()
+            .set_directory("~")
+            .set_parent(&main_window.window().window_handle())
+            .pick_file();
+        let Some(file_name) = file_name else {
+            return;
+        };
+        let mut attachment = DBAttachment::new(m.id, &file_name);
+        if let Ok(kind) = kind.parse::<AttachmentKind>() {
+            attachment.kind = kind;
+        }
+        attachment.label = if label.is_empty() {None} else {Some(label.into())};
+        if copy {
+            if let Err(e) = attachment.copy_into(&folder) {
+                warn!("Could not copy {} into the library: {}", file_name.display(), e);
+                return;
+            }
+        }
+        attempt!(attachment.insert(&db));
+
+        let attachments = m.attachments.as_any().downcast_ref::<slint::VecModel<Attachment>>().unwrap();
+        attachments.push(attachment_to_ui(attachment));
+    });
+
+    let db = database.clone();
+    let folder = attachment_files.clone();
+    main_window.on_open_attachment(move |a| {
+        let Some(attachment) = attempt!(DBAttachment::by_id(&db, a.id)) else {
+            return;
+        };
+        let path = if attachment.is_embedded() {
+            // The system handler needs a real file, so write out a temporary one
+            let Some(data) = attempt!(attachment.data(&db)) else {
+                return;
+            };
+            let dir = std::env::temp_dir().join("bass-attachments");
+            attempt!(std::fs::create_dir_all(&dir));
+            let path = dir.join(format!("{}-{}", a.id, attachment.name()));
+            attempt!(std::fs::write(&path, data));
+            path
+        } else {
+            assume!(attachment.resolve(&folder))
+        };
+        if let Err(e) = open_with_system(&path) {
+            warn!("Could not open {}: {}", path.display(), e);
+        }
+    });
+
+    let db = database.clone();
+    let folder = attachment_files.clone();
+    main_window.on_remove_attachment(move |m, idx, a| {
+        if let Some(attachment) = attempt!(DBAttachment::by_id(&db, a.id)) {
+            // Copies in the library folder belong to the library, so they go too
+            let managed = attachment.path.as_ref().is_some_and(|p| Path::new(p).is_relative());
+            let path = attachment.resolve(&folder);
+            attempt!(attachment.delete(&db));
+            if let (true, Some(path)) = (managed, path) {
+                if let Err(e) = std::fs::remove_file(&path) {
+                    warn!("Could not remove {}: {}", path.display(), e);
+                }
+            }
+        }
+
+        let attachments = m.attachments.as_any().downcast_ref::<slint::VecModel<Attachment>>().unwrap();
+        attachments.remove(idx as usize);
+    });
+
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    main_window.on_undo_change(move |m, entry| {
+        let main_window = weak_main.unwrap();
+        let Some(change) = attempt!(Change::by_id(&db, entry.id)) else {
+            return;
+        };
+        if let Err(e) = change.undo(&db) {
+            warn!("Could not undo change {}: {}", entry.id, e);
+            return;
+        }
+        // Undoing the piece's creation takes the piece with it
+        if attempt!(DBMusic::by_id(&db, m.id)).is_none() {
+            main_window.invoke_clear_selection();
+        }
+    });
+
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    main_window.on_refresh_programs(move || {
+        let main_window = weak_main.unwrap();
+        refresh_programs(&main_window, &db, None);
+    });
+
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    main_window.on_select_program(move |id| {
+        let main_window = weak_main.unwrap();
+        refresh_programs(&main_window, &db, Some(id));
+    });
+
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    main_window.on_new_program(move || {
+        let main_window = weak_main.unwrap();
+        let mut program = DBProgram::new("Untitled program");
+        attempt!(program.insert(&db));
+        refresh_programs(&main_window, &db, program.id());
+    });
+
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    main_window.on_save_program(move |id, name, date| {
+        let main_window = weak_main.unwrap();
+        edit_program(&db, id, |p| {
+            p.name = name.into();
+            p.date = if date.is_empty() {None} else {Some(date.into())};
+        });
+        refresh_programs(&main_window, &db, Some(id));
+    });
+
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    main_window.on_delete_program(move |id| {
+        let main_window = weak_main.unwrap();
+        if let Some(program) = attempt!(DBProgram::by_id(&db, id)) {
+            attempt!(program.delete(&db));
+        }
+        refresh_programs(&main_window, &db, None);
+    });
+
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    main_window.on_add_to_program(move |id, music_id| {
+        let main_window = weak_main.unwrap();
+        edit_program(&db, id, |p| p.items.push(ProgramItem::Piece(music_id)));
+        refresh_programs(&main_window, &db, Some(id));
+    });
+
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    main_window.on_add_program_segment(move |id, kind, label, duration| {
+        let main_window = weak_main.unwrap();
+        let label = if label.is_empty() {None} else {Some(label.to_string())};
+        let duration = attempt!(search::SearchType::from_time(&duration)).as_num();
+        let item = if kind == "Intermission" {
+            ProgramItem::Intermission { label, duration }
+        } else {
+            ProgramItem::Spoken { label, duration }
+        };
+        edit_program(&db, id, |p| p.items.push(item));
+        refresh_programs(&main_window, &db, Some(id));
+    });
+
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    main_window.on_move_program_entry(move |id, from, to| {
+        let main_window = weak_main.unwrap();
+        edit_program(&db, id, |p| {
+            if (0..p.items.len() as i32).contains(&to) {
+                p.items.swap(from as usize, to as usize);
+            }
+        });
+        refresh_programs(&main_window, &db, Some(id));
+    });
+
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    main_window.on_remove_program_entry(move |id, idx| {
+        let main_window = weak_main.unwrap();
+        edit_program(&db, id, |p| {
+            p.items.remove(idx as usize);
+        });
+        refresh_programs(&main_window, &db, Some(id));
+    });
+
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    main_window.on_export_program(move |id| {
+        let main_window = weak_main.unwrap();
+        let Some(program) = attempt!(DBProgram::by_id(&db, id)) else {
+            return;
+        };
+        let file_name = rfd::FileDialog::new()
+            .set_directory("~")
+            .set_file_name(format!("{}.txt", program.name))
+            .set_parent(&main_window.window().window_handle())
+            .set_can_create_directories(true)
+            .save_file();
+        if let Some(file_name) = file_name {
+            attempt!(std::fs::write(file_name, attempt!(program.export_text(&db))));
+        }
+    });
+
+    let weak_report = loan_report_window.as_weak();
+    loan_report_window.on_close_clicked(move || {
+        let report = weak_report.unwrap();
+        attempt!(report.hide());
+    });
+
+    let weak_report = loan_report_window.as_weak();
+    let db = database.clone();
+    loan_report_window.on_refresh(move || {
+        let report = weak_report.unwrap();
+        report.set_loans(loan_report(&db, report.get_show_all()));
+    });
+
+    let weak_report = loan_report_window.as_weak();
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    loan_report_window.on_check_in(move |id| {
+        let report = weak_report.unwrap();
+        let main_window = weak_main.unwrap();
+        if let Some(mut loan) = attempt!(DBLoan::by_id(&db, id)) {
+            attempt!(loan.check_in(&db));
+        }
+        report.set_loans(loan_report(&db, report.get_show_all()));
+        main_window.invoke_trigger_refresh();
+    });
+
+    let weak_report = loan_report_window.as_weak();
+    let db = database.clone();
+    main_window.on_show_loan_report(move || {
+        let report = weak_report.unwrap();
+        report.set_loans(loan_report(&db, report.get_show_all()));
+        attempt!(report.show());
+    });
+
+    let weak_trash = trash_window.as_weak();
+    trash_window.on_close_clicked(move || {
+        let trash = weak_trash.unwrap();
+        attempt!(trash.hide());
+    });
+
+    let weak_trash = trash_window.as_weak();
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    trash_window.on_restore(move |id| {
+        let trash = weak_trash.unwrap();
+        let main_window = weak_main.unwrap();
+        if let Some(mut music) = attempt!(DBMusic::by_id(&db, id)) {
+            attempt!(music.restore(&db));
+        }
+        trash.set_music(trash_list(&db));
+        main_window.invoke_trigger_refresh();
+    });
+
+    let weak_trash = trash_window.as_weak();
+    let db = database.clone();
+    let folder = attachment_files.clone();
+    trash_window.on_delete_permanently(move |id| {
+        let trash = weak_trash.unwrap();
+        if let Some(music) = attempt!(DBMusic::by_id(&db, id)) {
+            let files = managed_files(&db, &folder, &music);
+            attempt!(music.delete_permanently(&db));
+            remove_files(&files);
+        }
+        trash.set_music(trash_list(&db));
+    });
+
+    let weak_trash = trash_window.as_weak();
+    let db = database.clone();
+    let folder = attachment_files.clone();
+    trash_window.on_empty_trash(move || {
+        let trash = weak_trash.unwrap();
+        let confirmed = rfd::MessageDialog::new()
+            .set_title("Empty Trash")
+            .set_description("Permanently delete every piece in the trash?")
+            .set_buttons(rfd::MessageButtons::OkCancel)
+            .set_parent(&trash.window().window_handle())
+            .show();
+        if confirmed != rfd::MessageDialogResult::Ok {
+            return;
+        }
+        let files: Vec<PathBuf> = attempt!(DBMusic::list_trash(&db)).iter()
+            .flat_map(|m| managed_files(&db, &folder, m))
+            .collect();
+        let removed = attempt!(DBMusic::empty_trash(&db));
+        remove_files(&files);
+        info!("Permanently deleted {} pieces", removed);
+        trash.set_music(trash_list(&db));
+    });
+
+    let weak_duplicates = duplicates_window.as_weak();
+    duplicates_window.on_close_clicked(move || {
+        let duplicates = weak_duplicates.unwrap();
+        attempt!(duplicates.hide());
+    });
+
+    let weak_duplicates = duplicates_window.as_weak();
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    duplicates_window.on_merge(move |keep, other| {
+        let duplicates = weak_duplicates.unwrap();
+        let main_window = weak_main.unwrap();
+        let (Some(mut keep), Some(other)) = (attempt!(DBMusic::by_id(&db, keep)), attempt!(DBMusic::by_id(&db, other))) else {
+            return;
+        };
+        if let Err(e) = keep.merge(&db, other) {
+            warn!("Could not merge duplicates: {}", e);
+        }
+        duplicates.set_pairs(duplicate_list(&db));
+        main_window.invoke_clear_selection();
+        main_window.invoke_trigger_refresh();
+    });
+
+    let weak_duplicates = duplicates_window.as_weak();
+    let db = database.clone();
+    main_window.on_show_duplicates(move || {
+        let duplicates = weak_duplicates.unwrap();
+        duplicates.set_pairs(duplicate_list(&db));
+        attempt!(duplicates.show());
+    });
+
+    let weak_trash = trash_window.as_weak();
+    let db = database.clone();
+    main_window.on_show_trash(move || {
+        let trash = weak_trash.unwrap();
+        trash.set_music(trash_list(&db));
+        attempt!(trash.show());
+    });
+
     let weak_search = search_dialog.as_weak();
     main_window.on_show_search_dialog(move || {
         let dialog = weak_search.unwrap();
@@ -364,38 +1541,27 @@ fn main() -> Result<(), slint::PlatformError> {
         main_window.invoke_trigger_refresh();
     });
 
+    let db = database.clone();
+    main_window.on_load_details(move |m| load_details(&db, m));
+
     let weak_main = main_window.as_weak();
+    let db = database.clone();
     main_window.on_trigger_refresh(move || {
         let main_window = weak_main.unwrap();
 
-        let musics = match *attempt!(CURRENT_SEARCH.read()) {
-            Some(ref s) => {
-                let text = s.search_text.to_string();
-                let search: search::Search = attempt!(text.parse());
-                attempt!(search.execute())
-            }
-            None => {
-                attempt!(DBMusic::list_all())
-            }
-        };
+        let results = current_results(&db);
         
-        let music_list = musics.into_iter().map(|m| {
-            let keywords = assume!(attempt!(m.keywords()));
-            let keywords: Vec<slint::SharedString> = keywords.into_iter().map(|k| k.to_string().into()).collect();
-            let keymodel = Rc::new(slint::VecModel::from(keywords));
-            Music {
-                id: assume!(m.id()).into(),
-                title: m.title.into(),
-                source: m.source.into(),
-                composer: m.composer.unwrap_or("".into()).into(),
-                arranger: m.arranger.unwrap_or("".into()).into(),
-                notes: m.notes.unwrap_or("".into()).into(),
-                runtime: m.runtime.unwrap_or(0).into(),
-                keywords: keymodel.into(),
-            }
+        let music_list = results.into_iter().map(|(m, snippet)| Music {
+            snippet: snippet.into(),
+            ..music_to_ui(&db, m)
         });
         let model = Rc::new(slint::VecModel::from_iter(music_list));
         main_window.set_music_list(model.into());
+
+        // Titles and runtimes in the program come from the library
+        if main_window.get_show_program() {
+            refresh_programs(&main_window, &db, None);
+        }
     });
 
     let weak_main = main_window.as_weak();
@@ -462,28 +1628,68 @@ fn main() -> Result<(), slint::PlatformError> {
     let weak_main = main_window.as_weak();
     let weak_add = add_dialog.as_weak();
     let weak_search = search_dialog.as_weak();
+    let weak_manager = keyword_manager.as_weak();
+    let weak_people = people_manager.as_weak();
+    let weak_fields = custom_field_manager.as_weak();
+    let weak_import = import_dialog.as_weak();
+    let weak_export = export_dialog.as_weak();
+    let weak_report = loan_report_window.as_weak();
+    let weak_trash = trash_window.as_weak();
+    let weak_duplicates = duplicates_window.as_weak();
     let dup_config = config.clone();
     main_window.on_update_default_font_size(move |action| {
         let main_window = weak_main.unwrap();
         let add_dialog = weak_add.unwrap();
         let search_dialog = weak_search.unwrap();
+        let keyword_manager = weak_manager.unwrap();
+        let people_manager = weak_people.unwrap();
+        let custom_field_manager = weak_fields.unwrap();
+        let import_dialog = weak_import.unwrap();
+        let export_dialog = weak_export.unwrap();
+        let loan_report_window = weak_report.unwrap();
+        let trash_window = weak_trash.unwrap();
+        let duplicates_window = weak_duplicates.unwrap();
         match action {
             FontSizeAction::Default => {
                 let default_size = attempt!(dup_config.read()).ui.default_font_size;
                 main_window.set__default_font_size(default_size);
                 add_dialog.set__default_font_size(default_size + 2.0);
                 search_dialog.set__default_font_size(default_size + 2.0);
+                keyword_manager.set__default_font_size(default_size + 2.0);
+                people_manager.set__default_font_size(default_size + 2.0);
+                custom_field_manager.set__default_font_size(default_size + 2.0);
+                import_dialog.set__default_font_size(default_size + 2.0);
+                export_dialog.set__default_font_size(default_size + 2.0);
+                loan_report_window.set__default_font_size(default_size + 2.0);
+                trash_window.set__default_font_size(default_size + 2.0);
+                duplicates_window.set__default_font_size(default_size + 2.0);
             }
             FontSizeAction::Increase => {
                 main_window.set__default_font_size(main_window.get__default_font_size() + 2.0);
                 add_dialog.set__default_font_size(add_dialog.get__default_font_size() + 2.0);
                 search_dialog.set__default_font_size(search_dialog.get__default_font_size() + 2.0);
+                keyword_manager.set__default_font_size(keyword_manager.get__default_font_size() + 2.0);
+                people_manager.set__default_font_size(people_manager.get__default_font_size() + 2.0);
+                custom_field_manager.set__default_font_size(custom_field_manager.get__default_font_size() + 2.0);
+                import_dialog.set__default_font_size(import_dialog.get__default_font_size() + 2.0);
+                export_dialog.set__default_font_size(export_dialog.get__default_font_size() + 2.0);
+                loan_report_window.set__default_font_size(loan_report_window.get__default_font_size() + 2.0);
+                trash_window.set__default_font_size(trash_window.get__default_font_size() + 2.0);
+                duplicates_window.set__default_font_size(duplicates_window.get__default_font_size() + 2.0);
                 attempt!(dup_config.write()).ui.default_font_size = main_window.get__default_font_size();
             }
             FontSizeAction::Decrease => {
                 main_window.set__default_font_size(main_window.get__default_font_size() - 2.0);
                 add_dialog.set__default_font_size(add_dialog.get__default_font_size() - 2.0);
                 search_dialog.set__default_font_size(search_dialog.get__default_font_size() - 2.0);
+                keyword_manager.set__default_font_size(keyword_manager.get__default_font_size() - 2.0);
+                people_manager.set__default_font_size(people_manager.get__default_font_size() - 2.0);
+                custom_field_manager.set__default_font_size(custom_field_manager.get__default_font_size() - 2.0);
+                import_dialog.set__default_font_size(import_dialog.get__default_font_size() - 2.0);
+                export_dialog.set__default_font_size(export_dialog.get__default_font_size() - 2.0);
+                loan_report_window.set__default_font_size(loan_report_window.get__default_font_size() - 2.0);
+                trash_window.set__default_font_size(trash_window.get__default_font_size() - 2.0);
+                duplicates_window.set__default_font_size(duplicates_window.get__default_font_size() - 2.0);
                 attempt!(dup_config.write()).ui.default_font_size = main_window.get__default_font_size();
             }
         }
@@ -504,6 +1710,135 @@ fn main() -> Result<(), slint::PlatformError> {
         }
     });
 
+    let weak_import = import_dialog.as_weak();
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    main_window.on_import_csv(move || {
+        let main_window = weak_main.unwrap();
+        let dialog = weak_import.unwrap();
+        let file_name = rfd::FileDialog::new()
+            .set_directory("~")
+            .add_filter("Spreadsheet", &["csv", "tsv", "txt"])
+            .set_parent(&main_window.window().window_handle())
+            .pick_file();
+        let Some(file_name) = file_name else {
+            return;
+        };
+        let mut import = CsvImport::for_file(&file_name);
+        let headers = match import.headers(&file_name) {
+            Ok(headers) => headers,
+            Err(e) => {
+                warn!("Could not read {}: {}", file_name.display(), e);
+                return;
+            }
+        };
+        attempt!(import.guess_columns(&db, &headers));
+        let columns = headers.into_iter().zip(&import.columns).map(|(header, column)| ImportMapping {
+            header: header.into(),
+            target: column.as_ref().map_or("(skip)".to_owned(), |c| c.to_string()).into(),
+        });
+        let targets = std::iter::once("(skip)".to_owned())
+            .chain(SheetColumn::ALL.iter().map(|c| c.to_string()))
+            .chain(attempt!(CustomField::list_all(&db)).into_iter().map(|f| f.name))
+            .map(slint::SharedString::from);
+        dialog.set_file(file_name.to_string_lossy().as_ref().into());
+        dialog.set_columns(Rc::new(slint::VecModel::from_iter(columns)).into());
+        dialog.set_targets(Rc::new(slint::VecModel::from_iter(targets)).into());
+        dialog.set_has_headers(true);
+        dialog.set_keyword_delimiter(";".into());
+        dialog.set_summary("".into());
+        dialog.set_report(Rc::new(slint::VecModel::default()).into());
+        dialog.set_imported(false);
+        attempt!(dialog.show());
+    });
+
+    let weak_import = import_dialog.as_weak();
+    let db = database.clone();
+    import_dialog.on_preview(move || {
+        let dialog = weak_import.unwrap();
+        let import = import_from_ui(&db, &dialog);
+        match import.dry_run(dialog.get_file().as_str()) {
+            Ok(report) => {
+                dialog.set_summary(format!("{} rows would be imported, {} have errors", report.rows.len(), report.errors.len()).into());
+                dialog.set_report(import_report_to_ui(&report));
+            }
+            Err(e) => dialog.set_summary(format!("Could not read the file: {}", e).into()),
+        }
+    });
+
+    let weak_import = import_dialog.as_weak();
+    let weak_main = main_window.as_weak();
+    let db = database.clone();
+    import_dialog.on_import(move || {
+        let dialog = weak_import.unwrap();
+        let main_window = weak_main.unwrap();
+        let import = import_from_ui(&db, &dialog);
+        match import.run(&db, dialog.get_file().as_str()) {
+            Ok(report) => {
+                dialog.set_summary(format!("Imported {} rows, skipped {} with errors", report.rows.len(), report.errors.len()).into());
+                dialog.set_report(import_report_to_ui(&report));
+                dialog.set_imported(true);
+                main_window.invoke_trigger_refresh();
+            }
+            Err(e) => dialog.set_summary(format!("Nothing was imported: {}", e).into()),
+        }
+    });
+
+    let weak_import = import_dialog.as_weak();
+    import_dialog.on_close_clicked(move || {
+        let dialog = weak_import.unwrap();
+        attempt!(dialog.hide());
+    });
+
+    let weak_export = export_dialog.as_weak();
+    let db = database.clone();
+    main_window.on_show_export_dialog(move || {
+        let dialog = weak_export.unwrap();
+        let columns = SheetColumn::ALL.iter().map(SheetColumn::heading)
+            .chain(attempt!(CustomField::list_all(&db)).into_iter().map(|f| f.name))
+            .map(|name| ExportColumn { name: name.into(), checked: true });
+        dialog.set_columns(Rc::new(slint::VecModel::from_iter(columns)).into());
+        dialog.set_search_active(attempt!(CURRENT_SEARCH.read()).is_some());
+        dialog.invoke_reset();
+        attempt!(dialog.show());
+    });
+
+    let weak_export = export_dialog.as_weak();
+    export_dialog.on_cancel_clicked(move || {
+        let dialog = weak_export.unwrap();
+        attempt!(dialog.hide());
+    });
+
+    let weak_export = export_dialog.as_weak();
+    let db = database.clone();
+    export_dialog.on_export(move |tsv, only_results| {
+        let dialog = weak_export.unwrap();
+        let file_name = rfd::FileDialog::new()
+            .set_directory("~")
+            .set_file_name(if tsv { "bass.tsv" } else { "bass.csv" })
+            .set_parent(&dialog.window().window_handle())
+            .set_can_create_directories(true)
+            .save_file();
+        let Some(file_name) = file_name else {
+            return;
+        };
+        let export = CsvExport {
+            delimiter: if tsv { b'\t' } else { b',' },
+            columns: dialog.get_columns().iter().filter(|c| c.checked).filter_map(|c| sheet_column(&db, &c.name)).collect(),
+            ..Default::default()
+        };
+        let music = if only_results {
+            current_music(&db)
+        } else {
+            attempt!(DBMusic::list_all(&db))
+        };
+        if let Err(e) = export.write_to_path(&db, &music, &file_name) {
+            warn!("Could not export to {}: {}", file_name.display(), e);
+            return;
+        }
+        attempt!(dialog.hide());
+    });
+
     main_window.invoke_trigger_refresh();
     main_window.invoke_refresh_searches();
     main_window.invoke_update_default_font_size(FontSizeAction::Default);
@@ -1,5 +1,6 @@
 
-use libbass::db::{Music, Keyword, self};
+use libbass::db::{Database, Music, Keyword, Ismn, CustomField, self};
+use libbass::theory::{Key, KeySignature, Mode, Pitch, Range};
 
 use std::fmt::{Display, self, Formatter};
 
@@ -55,7 +56,22 @@ pub enum Field {
     Arranger,
     Notes,
     Runtime,
+    Key,
+    Range,
+    Part,
+    Voicing,
+    Grade,
+    Publisher,
+    Catalog,
+    Ismn,
+    Copyright,
+    Language,
+    Custom,
     Keyword,
+    Location,
+    Loans,
+    Performed,
+    Everything,
 }
 
 impl Display for Field {
@@ -67,7 +83,22 @@ impl Display for Field {
             Field::Arranger => "arranger",
             Field::Notes => "notes",
             Field::Runtime => "runtime",
+            Field::Key => "key",
+            Field::Range => "range",
+            Field::Part => "part",
+            Field::Voicing => "voicing",
+            Field::Grade => "grade",
+            Field::Publisher => "publisher",
+            Field::Catalog => "catalog",
+            Field::Ismn => "ismn",
+            Field::Copyright => "copyright",
+            Field::Language => "language",
+            Field::Custom => "field",
             Field::Keyword => "keywords",
+            Field::Location => "location",
+            Field::Loans => "loans",
+            Field::Performed => "performed",
+            Field::Everything => "everything",
         })
     }
 }
@@ -83,7 +114,22 @@ impl std::str::FromStr for Field {
             "arranger" => Ok(Field::Arranger),
             "notes" => Ok(Field::Notes),
             "runtime" => Ok(Field::Runtime),
+            "key" => Ok(Field::Key),
+            "range" => Ok(Field::Range),
+            "part" => Ok(Field::Part),
+            "voicing" => Ok(Field::Voicing),
+            "grade" => Ok(Field::Grade),
+            "publisher" => Ok(Field::Publisher),
+            "catalog" => Ok(Field::Catalog),
+            "ismn" => Ok(Field::Ismn),
+            "copyright" => Ok(Field::Copyright),
+            "language" => Ok(Field::Language),
+            "field" => Ok(Field::Custom),
             "keywords" => Ok(Field::Keyword),
+            "location" => Ok(Field::Location),
+            "loans" => Ok(Field::Loans),
+            "performed" => Ok(Field::Performed),
+            "everything" => Ok(Field::Everything),
             _ => Err("Not a valid field"),
         }
     }
@@ -97,7 +143,7 @@ pub enum SearchType {
 impl SearchType {
     pub fn from_time(s: &str) -> Result<SearchType, &'static str> {
         let mut time: u16 = 0;
-        if s.len() == 0 {
+        if s.is_empty() {
             return Ok(SearchType::Num(0));
         }
         for (i, t) in s.rsplitn(2, ":").enumerate() {
@@ -146,15 +192,109 @@ impl Search {
         }
     }
 
-    #[allow(dead_code)]
-    pub fn execute(&self) -> db::Result<Vec<Music>> {
-        if self.field == Field::Keyword {
-            let keywords: Vec<Keyword> = self.right.to_string().split(" ").map(|s| s.parse().unwrap()).collect();
-            return Music::by_keywords(&keywords);
+    fn years(&self) -> u32 {
+        self.right.to_string().trim().parse().unwrap_or(0)
+    }
+
+    fn keywords(&self) -> Vec<Keyword> {
+        self.right.to_string().split_whitespace().map(|s| s.parse().unwrap()).collect()
+    }
+
+    // Adds the condition `f` builds from the parsed right hand side, or one that matches nothing
+    // if it doesn't parse.
+    fn parsed<'q, T: std::str::FromStr, F>(&self, query: &'q mut db::MusicQuery, f: F) -> &'q mut db::MusicQuery
+    where F: FnOnce(&mut db::MusicQuery, T) {
+        match self.right.to_string().trim().parse() {
+            Ok(value) => query.all_of(|q| f(q, value)),
+            Err(_) => query.any_of(|_| {}),
+        }
+    }
+
+    // Like `parsed`, for a right hand side of a part name followed by what to parse, as in
+    // "bass trombone F1–F3". Whatever follows the last space is parsed.
+    fn part_parsed<'q, T: std::str::FromStr, F>(&self, query: &'q mut db::MusicQuery, f: F) -> &'q mut db::MusicQuery
+    where F: FnOnce(&mut db::MusicQuery, &str, T) {
+        let right = self.right.to_string();
+        match right.trim().rsplit_once(' ').map(|(part, value)| (part, value.parse())) {
+            Some((part, Ok(value))) => query.all_of(|q| f(q, part, value)),
+            _ => query.any_of(|_| {}),
+        }
+    }
+
+    // Custom fields are named on the right hand side, before a colon and the value: "field <
+    // Acquired: 2010-01-01". "has" takes just the name. Fields that don't exist and values that
+    // don't fit the field match nothing.
+    fn execute_custom(&self, db: &Database) -> db::Result<Vec<Music>> {
+        let right = self.right.to_string();
+        let (name, text) = right.split_once(':').unwrap_or((&right, ""));
+        let Some(field) = CustomField::by_name(db, name.trim())? else {
+            return Ok(Vec::new());
+        };
+        let mut query = Music::query();
+        match (self.op, self.invert) {
+            (SearchOp::Has, false) => query.has_custom(&field),
+            (SearchOp::Has, true) => query.null_custom(&field),
+            (SearchOp::Contains, false) => query.custom_contains(&field, text.trim()),
+            (SearchOp::Contains, true) => query.not(|q| {
+                q.custom_contains(&field, text.trim());
+            }),
+            (op, invert) => {
+                let Ok(value) = field.parse_value(text) else {
+                    return Ok(Vec::new());
+                };
+                match (op, invert) {
+                    (SearchOp::Eq | SearchOp::StrEq, false) => query.custom_eq(&field, &value),
+                    (SearchOp::Eq | SearchOp::StrEq, true) => query.custom_ne(&field, &value),
+                    (SearchOp::Lt, false) | (SearchOp::Ge, true) => query.custom_lt(&field, &value),
+                    (SearchOp::Le, false) | (SearchOp::Gt, true) => query.custom_le(&field, &value),
+                    (SearchOp::Gt, false) | (SearchOp::Le, true) => query.custom_gt(&field, &value),
+                    (SearchOp::Ge, false) | (SearchOp::Lt, true) => query.custom_ge(&field, &value),
+                    (o, i) => panic!("Not a valid query combination (field: {}, op: {}, inverted: {})", self.field, o, i),
+                }
+            }
+        };
+        query.run(db)
+    }
+
+    // Like `execute`, along with where in each piece the search matched. Only "everything"
+    // searches have snippets; the others leave them empty.
+    pub fn execute_with_snippets(&self, db: &Database) -> db::Result<Vec<(Music, String)>> {
+        if self.field == Field::Everything {
+            let hits = Music::search_text(db, &self.right.to_string())?;
+            return Ok(hits.into_iter().map(|h| (h.music, h.snippet)).collect());
+        }
+        Ok(self.execute(db)?.into_iter().map(|m| (m, String::new())).collect())
+    }
+
+    pub fn execute(&self, db: &Database) -> db::Result<Vec<Music>> {
+        if self.field == Field::Everything {
+            let hits = Music::search_text(db, &self.right.to_string())?;
+            return Ok(hits.into_iter().map(|h| h.music).collect());
+        }
+        if self.field == Field::Custom {
+            return self.execute_custom(db);
         }
 
         let mut query = Music::query();
         match (self.field, self.op, self.invert) {
+            (Field::Keyword, _, false) => query.has_all_keywords(&self.keywords()),
+            (Field::Keyword, _, true) => query.not(|q| {
+                q.has_all_keywords(&self.keywords());
+            }),
+
+            // "loans has out" or "loans has overdue"
+            (Field::Loans, _, invert) => {
+                let overdue = self.right.to_string().trim() == "overdue";
+                let cond = |q: &mut db::MusicQuery| {
+                    if overdue { q.overdue(); } else { q.on_loan(); }
+                };
+                if invert { query.not(cond) } else { query.all_of(cond) }
+            }
+
+            // "performed < 5" is within the last five years
+            (Field::Performed, SearchOp::Lt, false) => query.performed_within_years(self.years()),
+            (Field::Performed, SearchOp::Lt, true) => query.not_performed_in_years(self.years()),
+
             (Field::Title, SearchOp::StrEq, false)  => query.title_eq(&self.right.to_string()),
             (Field::Title, SearchOp::StrEq, true)  => query.title_ne(&self.right.to_string()),
             (Field::Title, SearchOp::Contains, false)  => query.title_contains(&self.right.to_string()),
@@ -175,6 +315,90 @@ impl Search {
             (Field::Arranger, SearchOp::Contains, false)  => query.arranger_contains(&self.right.to_string()),
             (Field::Arranger, SearchOp::Contains, true)  => query.arranger_not_contains(&self.right.to_string()),
             
+            (Field::Location, SearchOp::StrEq, false)  => query.location_eq(&self.right.to_string()),
+            (Field::Location, SearchOp::StrEq, true)  => query.not(|q| {
+                q.location_eq(&self.right.to_string());
+            }),
+            (Field::Location, SearchOp::Contains, false)  => query.location_contains(&self.right.to_string()),
+            (Field::Location, SearchOp::Contains, true)  => query.location_not_contains(&self.right.to_string()),
+
+            // "key '= F♯ minor", "key in minor" for the mode, "key == 3♭" for the signature
+            (Field::Key, SearchOp::StrEq, false) => self.parsed(&mut query, |q, key: Key| { q.key_eq(&key); }),
+            (Field::Key, SearchOp::StrEq, true) => self.parsed(&mut query, |q, key: Key| { q.key_ne(&key); }),
+            (Field::Key, SearchOp::Contains, false) => self.parsed(&mut query, |q, mode: Mode| { q.key_in_mode(mode); }),
+            (Field::Key, SearchOp::Contains, true) => self.parsed(&mut query, |q, mode: Mode| {
+                q.not(|q| { q.key_in_mode(mode); });
+            }),
+            (Field::Key, SearchOp::Eq, invert) => self.parsed(&mut query, |q, signature: KeySignature| {
+                if invert { q.not(|q| { q.key_signature_eq(signature); }); } else { q.key_signature_eq(signature); }
+            }),
+
+            // "range in G3–C5" fits within it, "range has C5" reaches that note
+            (Field::Range, SearchOp::Contains, invert) => self.parsed(&mut query, |q, range: Range| {
+                if invert { q.not(|q| { q.range_within(&range); }); } else { q.range_within(&range); }
+            }),
+            (Field::Range, SearchOp::Has, invert) => self.parsed(&mut query, |q, pitch: Pitch| {
+                if invert { q.not(|q| { q.range_includes(&pitch); }); } else { q.range_includes(&pitch); }
+            }),
+            // "range <= G5" never goes above it, "range >= F1" never below
+            (Field::Range, SearchOp::Le, false) => self.parsed(&mut query, |q, pitch: Pitch| { q.range_high_le(&pitch); }),
+            (Field::Range, SearchOp::Le, true) => self.parsed(&mut query, |q, pitch: Pitch| { q.range_high_gt(&pitch); }),
+            (Field::Range, SearchOp::Ge, false) => self.parsed(&mut query, |q, pitch: Pitch| { q.range_low_ge(&pitch); }),
+            (Field::Range, SearchOp::Ge, true) => self.parsed(&mut query, |q, pitch: Pitch| { q.range_low_lt(&pitch); }),
+
+            // The same for one part, named before the range or note: "part <= soprano G5"
+            (Field::Part, SearchOp::Contains, invert) => self.part_parsed(&mut query, |q, part, range: Range| {
+                if invert { q.not(|q| { q.part_range_within(part, &range); }); } else { q.part_range_within(part, &range); }
+            }),
+            (Field::Part, SearchOp::Has, invert) => self.part_parsed(&mut query, |q, part, pitch: Pitch| {
+                if invert { q.not(|q| { q.part_range_includes(part, &pitch); }); } else { q.part_range_includes(part, &pitch); }
+            }),
+            (Field::Part, SearchOp::Le, false) => self.part_parsed(&mut query, |q, part, pitch: Pitch| { q.part_high_le(part, &pitch); }),
+            (Field::Part, SearchOp::Le, true) => self.part_parsed(&mut query, |q, part, pitch: Pitch| { q.part_high_gt(part, &pitch); }),
+            (Field::Part, SearchOp::Ge, false) => self.part_parsed(&mut query, |q, part, pitch: Pitch| { q.part_low_ge(part, &pitch); }),
+            (Field::Part, SearchOp::Ge, true) => self.part_parsed(&mut query, |q, part, pitch: Pitch| { q.part_low_lt(part, &pitch); }),
+
+            (Field::Voicing, SearchOp::StrEq, false) => query.voicing_eq(&self.right.to_string()),
+            (Field::Voicing, SearchOp::StrEq, true) => query.voicing_ne(&self.right.to_string()),
+            (Field::Voicing, SearchOp::Contains, false) => query.voicing_contains(&self.right.to_string()),
+            (Field::Voicing, SearchOp::Contains, true) => query.voicing_not_contains(&self.right.to_string()),
+
+            (Field::Publisher, SearchOp::StrEq, false) => query.publisher_eq(&self.right.to_string()),
+            (Field::Publisher, SearchOp::StrEq, true) => query.publisher_ne(&self.right.to_string()),
+            (Field::Publisher, SearchOp::Contains, false) => query.publisher_contains(&self.right.to_string()),
+            (Field::Publisher, SearchOp::Contains, true) => query.publisher_not_contains(&self.right.to_string()),
+
+            (Field::Catalog, SearchOp::StrEq, false) => query.catalog_number_eq(&self.right.to_string()),
+            (Field::Catalog, SearchOp::StrEq, true) => query.catalog_number_ne(&self.right.to_string()),
+
+            (Field::Ismn, SearchOp::StrEq, false) => self.parsed(&mut query, |q, ismn: Ismn| { q.ismn_eq(&ismn); }),
+            (Field::Ismn, SearchOp::StrEq, true) => self.parsed(&mut query, |q, ismn: Ismn| { q.ismn_ne(&ismn); }),
+
+            (Field::Language, SearchOp::StrEq, false) => query.language_eq(&self.right.to_string()),
+            (Field::Language, SearchOp::StrEq, true) => query.language_ne(&self.right.to_string()),
+
+            (Field::Grade, SearchOp::Eq, false) => self.parsed(&mut query, |q, grade: f64| { q.grade_eq(grade); }),
+            (Field::Grade, SearchOp::Eq, true) => self.parsed(&mut query, |q, grade: f64| { q.grade_ne(grade); }),
+            (Field::Grade, SearchOp::Lt, false) => self.parsed(&mut query, |q, grade: f64| { q.grade_lt(grade); }),
+            (Field::Grade, SearchOp::Lt, true) => self.parsed(&mut query, |q, grade: f64| { q.grade_ge(grade); }),
+            (Field::Grade, SearchOp::Le, false) => self.parsed(&mut query, |q, grade: f64| { q.grade_le(grade); }),
+            (Field::Grade, SearchOp::Le, true) => self.parsed(&mut query, |q, grade: f64| { q.grade_gt(grade); }),
+            (Field::Grade, SearchOp::Gt, false) => self.parsed(&mut query, |q, grade: f64| { q.grade_gt(grade); }),
+            (Field::Grade, SearchOp::Gt, true) => self.parsed(&mut query, |q, grade: f64| { q.grade_le(grade); }),
+            (Field::Grade, SearchOp::Ge, false) => self.parsed(&mut query, |q, grade: f64| { q.grade_ge(grade); }),
+            (Field::Grade, SearchOp::Ge, true) => self.parsed(&mut query, |q, grade: f64| { q.grade_lt(grade); }),
+
+            (Field::Copyright, SearchOp::Eq, false) => self.parsed(&mut query, |q, year: i32| { q.copyright_year_eq(year); }),
+            (Field::Copyright, SearchOp::Eq, true) => self.parsed(&mut query, |q, year: i32| { q.copyright_year_ne(year); }),
+            (Field::Copyright, SearchOp::Lt, false) => self.parsed(&mut query, |q, year: i32| { q.copyright_year_lt(year); }),
+            (Field::Copyright, SearchOp::Lt, true) => self.parsed(&mut query, |q, year: i32| { q.copyright_year_ge(year); }),
+            (Field::Copyright, SearchOp::Le, false) => self.parsed(&mut query, |q, year: i32| { q.copyright_year_le(year); }),
+            (Field::Copyright, SearchOp::Le, true) => self.parsed(&mut query, |q, year: i32| { q.copyright_year_gt(year); }),
+            (Field::Copyright, SearchOp::Gt, false) => self.parsed(&mut query, |q, year: i32| { q.copyright_year_gt(year); }),
+            (Field::Copyright, SearchOp::Gt, true) => self.parsed(&mut query, |q, year: i32| { q.copyright_year_le(year); }),
+            (Field::Copyright, SearchOp::Ge, false) => self.parsed(&mut query, |q, year: i32| { q.copyright_year_ge(year); }),
+            (Field::Copyright, SearchOp::Ge, true) => self.parsed(&mut query, |q, year: i32| { q.copyright_year_lt(year); }),
+
             (Field::Notes, SearchOp::Contains, false)  => query.notes_contains(&self.right.to_string()),
             (Field::Notes, SearchOp::Contains, true)  => query.notes_not_contains(&self.right.to_string()),
         
@@ -190,7 +414,7 @@ impl Search {
             (Field::Runtime, SearchOp::Ge, true) => query.runtime_lt(self.right.as_num()),
             (f, o, i) => panic!("Not a valid query combination (field: {}, op: {}, inverted: {})", f, o, i),
         };
-        query.run()
+        query.run(db)
     }
 }
 